@@ -0,0 +1,49 @@
+use std::{fs, path::Path, process::Command};
+
+/// EtherCrab source this crate is built against. Must match the path dependency in `Cargo.toml`.
+const ETHERCRAB_PATH: &str = "../ethercrab";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Don't require `protoc` to be installed on the test machine
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+
+    tonic_build::compile_protos("proto/live.proto")?;
+
+    println!(
+        "cargo:rustc-env=ETHERCRAB_GIT_REV={}",
+        ethercrab_rev().unwrap_or_default()
+    );
+
+    for watch in [".git/HEAD", ".git/refs", ".cargo_vcs_info.json"] {
+        let path = Path::new(ETHERCRAB_PATH).join(watch);
+
+        // Watching a path that doesn't exist reruns this script on every build
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+
+    println!("cargo:rerun-if-changed=build.rs");
+
+    Ok(())
+}
+
+/// Full commit hash of the EtherCrab checkout, or of the commit a crates.io release was published
+/// from.
+fn ethercrab_rev() -> Option<String> {
+    let git = Command::new("git")
+        .args(["-C", ETHERCRAB_PATH, "rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    git.or_else(|| {
+        let vcs_info =
+            fs::read_to_string(Path::new(ETHERCRAB_PATH).join(".cargo_vcs_info.json")).ok()?;
+
+        let (_, rest) = vcs_info.split_once("\"sha1\"")?;
+
+        rest.split('"').nth(1).map(String::from)
+    })
+}
@@ -0,0 +1,80 @@
+//! Write a copy of a run's dump with Wireshark packet comments on interesting frames.
+//!
+//! Frames are found from the ingested `frames` rows, so comments reflect the same pairing as the
+//! database. Comments are added with `editcap -a`, and show up in Wireshark's packet list and with
+//! the `frame.comment` display filter.
+
+use sqlx::{query_as, PgPool};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Most comments to add to one dump, to keep `editcap`'s command line reasonable.
+const MAX_ANNOTATIONS: i64 = 10_000;
+
+/// Write `<dump>.annotated.pcapng` next to `dump`, marking lost PDUs, likely retries and round
+/// trips longer than `spike_ns`. Returns `None` if nothing needed annotating.
+pub async fn annotate(
+    db: &PgPool,
+    run: &str,
+    dump: &Path,
+    spike_ns: u32,
+) -> anyhow::Result<Option<PathBuf>> {
+    // A PDU index reused straight after a lost PDU with the same index is most likely a retry
+    let annotations: Vec<(i32, String)> = query_as(
+        r#"select packet_number, case
+            when rx_time_ns = 0 then 'latency-data: no response'
+            when prev_lost then 'latency-data: retry after lost packet ' || prev_packet_number
+            else 'latency-data: round trip ' || delta_time_ns || ' ns'
+        end
+        from (
+            select packet_number, rx_time_ns, delta_time_ns,
+                lag(rx_time_ns = 0) over w as prev_lost,
+                lag(packet_number) over w as prev_packet_number
+            from frames
+            where run = $1
+            window w as (partition by index order by packet_number)
+        ) f
+        where rx_time_ns = 0 or prev_lost or delta_time_ns > $2
+        order by packet_number
+        limit $3"#,
+    )
+    .bind(run)
+    .bind(spike_ns as i32)
+    .bind(MAX_ANNOTATIONS)
+    .fetch_all(db)
+    .await?;
+
+    if annotations.is_empty() {
+        return Ok(None);
+    }
+
+    if annotations.len() as i64 == MAX_ANNOTATIONS {
+        log::warn!(
+            "Only annotating the first {} frames of {}",
+            MAX_ANNOTATIONS,
+            run
+        );
+    }
+
+    let out = dump.with_extension("annotated.pcapng");
+
+    let mut cmd = Command::new("editcap");
+
+    for (packet_number, comment) in annotations.iter() {
+        cmd.arg("-a").arg(format!("{}:{}", packet_number, comment));
+    }
+
+    let status = cmd.arg(dump).arg(&out).status()?;
+
+    anyhow::ensure!(status.success(), "editcap exited with {}", status);
+
+    log::info!(
+        "--> Annotated {} frames in {}",
+        annotations.len(),
+        out.display()
+    );
+
+    Ok(Some(out))
+}
@@ -0,0 +1,150 @@
+//! Performance baselines stored as TOML, so they can be kept under version control and updated
+//! through code review.
+//!
+//! ```toml
+//! # Allowed increase over every expected value, as a fraction
+//! tolerance = 0.1
+//!
+//! [scenario."1thr-1task-<host>-<settings>-1000us".cycle_time_delta]
+//! p50_ns = 1000000
+//! p99_ns = 1012000
+//! max_ns = 1100000
+//! # Optional per-metric override
+//! tolerance = 0.5
+//! ```
+
+use crate::{scenarios::summaries_by_slug, scenarios::RunMetadata};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Tolerance used when a file doesn't give one.
+const DEFAULT_TOLERANCE: f64 = 0.1;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Baselines {
+    /// Allowed fractional increase over each expected value, e.g. `0.1` for 10%.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// Expected values for each metric, keyed by run slug then metric name.
+    #[serde(default)]
+    pub scenario: BTreeMap<String, BTreeMap<String, Expected>>,
+}
+
+impl Default for Baselines {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_TOLERANCE,
+            scenario: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_tolerance() -> f64 {
+    DEFAULT_TOLERANCE
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Expected {
+    pub p50_ns: u32,
+    pub p99_ns: u32,
+    pub max_ns: u32,
+
+    /// Overrides the file's tolerance for this metric.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+}
+
+/// A value that exceeded its baseline by more than the tolerance.
+#[derive(Debug)]
+pub struct Regression {
+    pub slug: String,
+    pub metric: String,
+    pub stat: &'static str,
+    pub expected: u32,
+    pub actual: u32,
+    pub tolerance: f64,
+}
+
+impl Baselines {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Update baselines for every scenario configuration in `results` and write the whole file,
+    /// keeping existing entries for configurations that weren't run and any custom tolerances.
+    pub fn write(path: &Path, results: &[(&str, RunMetadata)]) -> anyhow::Result<()> {
+        let mut baselines = if path.exists() {
+            Self::load(path)?
+        } else {
+            Self::default()
+        };
+
+        for (slug, summary) in summaries_by_slug(results) {
+            let metrics = baselines.scenario.entry(slug.to_string()).or_default();
+
+            for (metric, histogram) in summary.metrics() {
+                let s = histogram.summary();
+
+                let tolerance = metrics.get(metric).and_then(|e| e.tolerance);
+
+                metrics.insert(
+                    metric.to_string(),
+                    Expected {
+                        p50_ns: s.p50_ns,
+                        p99_ns: s.p99_ns,
+                        max_ns: s.max_ns,
+                        tolerance,
+                    },
+                );
+            }
+        }
+
+        fs::write(path, toml::to_string_pretty(&baselines)?)?;
+
+        log::info!("Wrote baselines to {}", path.display());
+
+        Ok(())
+    }
+
+    /// Compare results against these baselines. Configurations or metrics without a baseline are
+    /// skipped.
+    pub fn check(&self, results: &[(&str, RunMetadata)]) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+
+        for (slug, summary) in summaries_by_slug(results) {
+            let Some(metrics) = self.scenario.get(slug) else {
+                log::warn!("No baseline for {}", slug);
+
+                continue;
+            };
+
+            for (metric, histogram) in summary.metrics() {
+                let Some(expected) = metrics.get(metric) else {
+                    continue;
+                };
+
+                let tolerance = expected.tolerance.unwrap_or(self.tolerance);
+                let s = histogram.summary();
+
+                for (stat, expected, actual) in [
+                    ("p50", expected.p50_ns, s.p50_ns),
+                    ("p99", expected.p99_ns, s.p99_ns),
+                    ("max", expected.max_ns, s.max_ns),
+                ] {
+                    if f64::from(actual) > f64::from(expected) * (1.0 + tolerance) {
+                        regressions.push(Regression {
+                            slug: slug.to_string(),
+                            metric: metric.to_string(),
+                            stat,
+                            expected,
+                            actual,
+                            tolerance,
+                        });
+                    }
+                }
+            }
+        }
+
+        regressions
+    }
+}
@@ -0,0 +1,46 @@
+//! Turn on kernel busy polling for the duration of a run.
+//!
+//! EtherCrab's TX/RX task opens its own socket, so `SO_BUSY_POLL` can't be set on it. The
+//! `net.core.busy_read` and `net.core.busy_poll` sysctls apply the same thing to every socket
+//! instead, for blocking reads and `poll`/`epoll` respectively. Needs root.
+
+use std::{fs, io};
+
+const BUSY_READ: &str = "/proc/sys/net/core/busy_read";
+const BUSY_POLL: &str = "/proc/sys/net/core/busy_poll";
+
+/// Busy polling is on while this is alive. The previous sysctl values are put back when it's
+/// dropped.
+pub struct BusyPoll {
+    previous: Vec<(&'static str, String)>,
+}
+
+impl BusyPoll {
+    /// Busy poll for up to `us` microseconds before sleeping when waiting for packets.
+    pub fn enable(us: u32) -> io::Result<Self> {
+        let mut busy_poll = Self {
+            previous: Vec::new(),
+        };
+
+        for path in [BUSY_READ, BUSY_POLL] {
+            let previous = fs::read_to_string(path)?;
+
+            fs::write(path, us.to_string())?;
+
+            // Only restore what was actually changed if a later write fails
+            busy_poll.previous.push((path, previous));
+        }
+
+        Ok(busy_poll)
+    }
+}
+
+impl Drop for BusyPoll {
+    fn drop(&mut self) {
+        for (path, previous) in self.previous.iter() {
+            if let Err(e) = fs::write(path, previous.trim()) {
+                log::warn!("Failed to restore {}: {}", path, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,1885 @@
+//! In-process packet capture of EtherCAT frames to pcapng.
+//!
+//! A thread reads every frame seen on the interface from an `AF_PACKET` socket, keeps EtherCAT
+//! ones, or those matching a tcpdump filter, and writes them with their kernel receive timestamps.
+//! [`sync`] sends a marker frame on the EtherCAT NIC and waits for the capture to see it, so a
+//! scenario only starts once frames are known to reach the capture, even from a mirror port. The
+//! socket is drained before [`stop`] returns, so the end of a capture isn't lost either.
+//!
+//! Where packets can't be captured at all, [`start_in_band`] records frames handed to a
+//! [`FrameRecorder`] by an instrumented TX/RX task instead, into the same pcapng format so they're
+//! ingested the same way. Their timestamps are taken in userspace around the send and receive
+//! syscalls, so they include a little more of the stack than a capture's.
+//!
+//! NIC hardware receive timestamps can be recorded too, where the driver supports them. pcapng only
+//! has room for one timestamp per packet, so they're stored in a packet comment that Wireshark
+//! shows and [`Tail`] reads back.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    mem,
+    ops::Range,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const ETHERCAT_ETHERTYPE: u16 = 0x88a4;
+
+/// EtherCrab's source address. Devices set the locally administered bit of the source address on
+/// frames they pass on, so responses never have it.
+pub const MASTER_MAC: [u8; 6] = [0x10; 6];
+
+/// IEEE 802 local experimental ethertype for [`sync`] marker frames. EtherCAT devices pass frames
+/// that aren't EtherCAT through untouched.
+const MARKER_ETHERTYPE: u16 = 0x88b5;
+
+/// How often [`sync`] sends a marker frame until one is seen.
+const MARKER_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often [`sync`] checks whether a marker frame has been seen.
+const MARKER_POLL: Duration = Duration::from_micros(100);
+
+/// How long [`sync`] waits for a marker frame to be captured before giving up.
+const MARKER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Biggest frame that's captured whole by default. Longer ones are truncated.
+pub const DEFAULT_SNAPLEN: usize = 65535;
+
+/// Kernel receive buffer to ask for, so bursts aren't dropped while the file is being written.
+const RECV_BUF_BYTES: libc::c_int = 8 * 1024 * 1024;
+
+/// Packet comment prefix for the hardware timestamp, in nanoseconds of the NIC's clock.
+const HARDWARE_TIMESTAMP_COMMENT: &str = "hw_timestamp_ns=";
+
+/// How often the capture thread checks whether it should stop when nothing is being received.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Frames a [`FrameRecorder`] can queue for the in-band writer thread before dropping them. Every
+/// slot holds a whole frame and is allocated up front, so this is about 6 MiB.
+const IN_BAND_QUEUE_LEN: usize = 4 * 1024;
+
+/// Longest frame a [`FrameRecorder`] keeps, large enough for any Ethernet II frame without jumbo
+/// frames or VLAN tags. Anything longer is cut short, as if by the snaplen.
+const RECORDED_FRAME_LEN: usize = 1514;
+
+/// Where TX/RX tasks get a [`FrameRecorder`] from while an in-band capture is running.
+static IN_BAND: Mutex<Option<SyncSender<RecordedFrame>>> = Mutex::new(None);
+
+/// Frames passed to a [`FrameRecorder`] since stats were last taken, including dropped ones.
+static IN_BAND_PACKETS: AtomicU64 = AtomicU64::new(0);
+
+/// Frames a [`FrameRecorder`] dropped because the writer thread fell behind.
+static IN_BAND_DROPS: AtomicU64 = AtomicU64::new(0);
+
+/// How network traffic is captured while scenarios run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Don't capture anything.
+    None,
+    /// Start and stop a separate capture around every run.
+    PerRun,
+    /// Capture the whole suite into one file, then split it into per-run dumps afterwards using
+    /// each run's start and end times.
+    Session,
+    /// Record frames from inside the TX/RX task of every run, for when packets can't be captured.
+    InBand,
+}
+
+/// Frames the kernel dropped instead of delivering to a capture. Nonzero counts make latency
+/// numbers look better than they are, as the dropped frames are missing from them.
+#[derive(serde::Serialize, Debug, Clone, Copy, Default)]
+pub struct CaptureStats {
+    /// Frames of any kind the capture socket received or dropped.
+    pub packets: u64,
+
+    /// Frames dropped because the capture socket's buffer was full.
+    pub drops: u64,
+
+    /// Change in the interface's `rx_dropped` and `rx_missed_errors` counters, which also count
+    /// frames the NIC or driver dropped before any socket saw them.
+    pub interface_drops: u64,
+}
+
+/// What [`start`] captures.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureOptions<'a> {
+    /// Record NIC hardware receive timestamps too, if the driver supports them.
+    pub hardware_timestamps: bool,
+
+    /// tcpdump filter expression choosing which frames to keep, e.g.
+    /// `ether proto 0x88a4 or ether proto 0x88f7` to keep PTP too. Only EtherCAT frames are kept
+    /// if not set.
+    pub filter: Option<&'a str>,
+
+    /// Most bytes of each frame to keep. Longer frames are truncated.
+    pub snaplen: usize,
+}
+
+/// A running capture started with [`start`] or [`start_in_band`].
+pub struct Capture {
+    source: Source,
+    stop: Arc<AtomicBool>,
+    /// Only taken when stopping.
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Drop for Capture {
+    /// Let the thread finish writing if the capture is abandoned without [`stop`], e.g. because
+    /// the scenario failed.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Source::InBand = self.source {
+            IN_BAND.lock().expect("In-band capture lock").take();
+        }
+    }
+}
+
+/// Where a [`Capture`] gets its frames from.
+enum Source {
+    Socket {
+        interface: String,
+        socket: Arc<CaptureSocket>,
+        /// Payload of this capture's marker frames.
+        marker: u64,
+        /// Set by the capture thread once it has seen a marker frame.
+        synced: Arc<AtomicBool>,
+        /// Interface drop counters when stats were last taken.
+        interface_drops: u64,
+        /// Restored when the capture is stopped.
+        _hardware_timestamping: Option<HardwareTimestamping>,
+    },
+    /// Frames passed to a [`FrameRecorder`].
+    InBand,
+}
+
+/// A frame passed to a [`FrameRecorder`], copied into a fixed size buffer so recording it doesn't
+/// allocate.
+struct RecordedFrame {
+    /// Nanoseconds since the Unix epoch.
+    time_ns: u64,
+    /// Length of the frame on the wire, which may be more than was kept.
+    original_len: usize,
+    /// Only the first `min(original_len, RECORDED_FRAME_LEN)` bytes are used.
+    data: [u8; RECORDED_FRAME_LEN],
+}
+
+/// Start capturing frames on `interface` into the pcapng file at `path`.
+pub fn start(interface: &str, path: &Path, options: CaptureOptions<'_>) -> io::Result<Capture> {
+    let CaptureOptions {
+        hardware_timestamps,
+        filter,
+        snaplen,
+    } = options;
+
+    // Anything shorter can't even hold the Ethernet header
+    if snaplen < 14 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Snap length {} is too short", snaplen),
+        ));
+    }
+
+    // Marker frames have to get through to be seen
+    let program = filter
+        .map(|filter| {
+            compile_filter(
+                interface,
+                &format!("({}) or ether proto {:#06x}", filter, MARKER_ETHERTYPE),
+                snaplen,
+            )
+        })
+        .transpose()?;
+
+    let hardware_timestamping = hardware_timestamps
+        .then(|| {
+            HardwareTimestamping::enable(interface)
+                .map_err(|e| {
+                    log::warn!(
+                        "Hardware timestamps unavailable on {}, only recording software timestamps: {}",
+                        interface,
+                        e
+                    )
+                })
+                .ok()
+        })
+        .flatten();
+
+    let socket = CaptureSocket::open(
+        interface,
+        hardware_timestamping.is_some(),
+        program.as_deref(),
+    )
+    .map(Arc::new)?;
+
+    // Counts from before the socket was opened aren't part of this capture
+    socket.take_statistics()?;
+
+    let writer = PcapngWriter::create(path, interface, filter, snaplen)?;
+
+    log::debug!("Capturing {} to {}", interface, path.display());
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let filtered = filter.is_some();
+
+    let marker = fastrand::u64(..);
+    let synced = Arc::new(AtomicBool::new(false));
+
+    let thread = std::thread::Builder::new()
+        .name("capture".to_string())
+        .spawn({
+            let socket = Arc::clone(&socket);
+            let stop = Arc::clone(&stop);
+            let synced = Arc::clone(&synced);
+
+            move || capture_loop(&socket, writer, &stop, snaplen, filtered, marker, &synced)
+        })?;
+
+    Ok(Capture {
+        source: Source::Socket {
+            interface: interface.to_string(),
+            socket,
+            marker,
+            synced,
+            interface_drops: interface_drops(interface),
+            _hardware_timestamping: hardware_timestamping,
+        },
+        stop,
+        thread: Some(thread),
+    })
+}
+
+/// Send marker frames on `nic` until the capture sees one, so everything the scenario sends after
+/// this returns is known to be captured. Fails if none is seen within [`MARKER_TIMEOUT`], e.g.
+/// because a mirror port isn't set up. In-band captures are always in sync.
+pub fn sync(capture: &Capture, nic: &str) -> io::Result<()> {
+    let Source::Socket {
+        interface,
+        marker,
+        synced,
+        ..
+    } = &capture.source
+    else {
+        return Ok(());
+    };
+
+    let start = Instant::now();
+
+    let name = CString::new(nic)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Bad interface name"))?;
+
+    let ifindex = match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => return Err(io::Error::last_os_error()),
+        ifindex => ifindex as i32,
+    };
+
+    // Send only, so no protocol to receive
+    let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let sockaddr = libc::sockaddr_ll {
+        sll_family: libc::AF_PACKET as u16,
+        sll_protocol: MARKER_ETHERTYPE.to_be(),
+        sll_ifindex: ifindex,
+        sll_hatype: 0,
+        sll_pkttype: 0,
+        sll_halen: 6,
+        sll_addr: [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0],
+    };
+
+    // Broadcast from a locally administered address, padded to the minimum frame length
+    let mut frame = [0u8; 60];
+    frame[0..6].fill(0xff);
+    frame[6..12].copy_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+    frame[12..14].copy_from_slice(&MARKER_ETHERTYPE.to_be_bytes());
+    frame[14..22].copy_from_slice(&marker.to_be_bytes());
+
+    let mut last_sent = None::<Instant>;
+
+    while !synced.load(Ordering::Relaxed) {
+        if start.elapsed() > MARKER_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "No marker frame sent on {} was captured on {} within {} ms",
+                    nic,
+                    interface,
+                    MARKER_TIMEOUT.as_millis()
+                ),
+            ));
+        }
+
+        if last_sent.is_none_or(|sent| sent.elapsed() >= MARKER_INTERVAL) {
+            check(unsafe {
+                libc::sendto(
+                    fd.as_raw_fd(),
+                    frame.as_ptr() as *const libc::c_void,
+                    frame.len(),
+                    0,
+                    &sockaddr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                ) as libc::c_int
+            })?;
+
+            last_sent = Some(Instant::now());
+        }
+
+        std::thread::sleep(MARKER_POLL);
+    }
+
+    log::debug!(
+        "Capture on {} in sync after {} us",
+        interface,
+        start.elapsed().as_micros()
+    );
+
+    Ok(())
+}
+
+/// Start writing frames passed to a [`FrameRecorder`] into the pcapng file at `path`, keeping at
+/// most `snaplen` bytes of each.
+pub fn start_in_band(path: &Path, snaplen: usize) -> io::Result<Capture> {
+    let mut writer = PcapngWriter::create(path, "in-band", None, snaplen)?;
+
+    let (frames_tx, frames_rx) = mpsc::sync_channel::<RecordedFrame>(IN_BAND_QUEUE_LEN);
+
+    let stop = Arc::new(AtomicBool::new(false));
+
+    log::debug!("Recording frames in-band to {}", path.display());
+
+    let thread = std::thread::Builder::new()
+        .name("capture".to_string())
+        .spawn({
+            let stop = stop.clone();
+
+            move || {
+                let mut write = |frame: RecordedFrame| {
+                    let kept = frame.original_len.min(RECORDED_FRAME_LEN).min(snaplen);
+
+                    writer.write_packet(
+                        frame.time_ns,
+                        None,
+                        &frame.data[0..kept],
+                        frame.original_len,
+                    )
+                };
+
+                // TX/RX tasks hold their own senders and may outlive the run, so stop when told to
+                // instead of waiting for every sender to be dropped
+                loop {
+                    match frames_rx.recv_timeout(STOP_POLL) {
+                        Ok(frame) => write(frame)?,
+                        Err(mpsc::RecvTimeoutError::Timeout) => (),
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if stop.load(Ordering::Relaxed) {
+                        // Anything recorded before the capture was stopped is already queued
+                        for frame in frames_rx.try_iter() {
+                            write(frame)?;
+                        }
+
+                        break;
+                    }
+                }
+
+                writer.finish()
+            }
+        })?;
+
+    IN_BAND_PACKETS.store(0, Ordering::Relaxed);
+    IN_BAND_DROPS.store(0, Ordering::Relaxed);
+
+    *IN_BAND.lock().expect("In-band capture lock") = Some(frames_tx);
+
+    Ok(Capture {
+        source: Source::InBand,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+/// Records frames into a running in-band capture. Each TX/RX task gets its own when it starts, so
+/// recording a frame doesn't take a lock or allocate.
+pub struct FrameRecorder {
+    frames_tx: SyncSender<RecordedFrame>,
+}
+
+impl FrameRecorder {
+    /// Record a frame the TX/RX task has just sent or received. Never blocks: frames are dropped
+    /// and counted if the writer falls behind.
+    pub fn record(&self, data: &[u8]) {
+        let time_ns = now_ns();
+
+        IN_BAND_PACKETS.fetch_add(1, Ordering::Relaxed);
+
+        let mut frame = RecordedFrame {
+            time_ns,
+            original_len: data.len(),
+            data: [0; RECORDED_FRAME_LEN],
+        };
+
+        let kept = data.len().min(RECORDED_FRAME_LEN);
+
+        frame.data[0..kept].copy_from_slice(&data[0..kept]);
+
+        if self.frames_tx.try_send(frame).is_err() {
+            IN_BAND_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A recorder for the in-band capture that's running, if there is one. Call this once when a TX/RX
+/// task starts, not for every frame.
+pub fn in_band_recorder() -> Option<FrameRecorder> {
+    IN_BAND
+        .lock()
+        .expect("In-band capture lock")
+        .clone()
+        .map(|frames_tx| FrameRecorder { frames_tx })
+}
+
+/// Stop a capture started with [`start`], writing out every frame received before this was
+/// called. Returns drop counts since it started or since [`take_stats`] was last called, or the
+/// error that stopped the capture early.
+pub fn stop(mut capture: Capture) -> io::Result<CaptureStats> {
+    capture.stop.store(true, Ordering::Relaxed);
+
+    if let Source::InBand = capture.source {
+        IN_BAND.lock().expect("In-band capture lock").take();
+    }
+
+    let thread = capture.thread.take().expect("Capture already stopped");
+
+    let result = match thread.join() {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e),
+    };
+
+    let stats = take_stats(&mut capture);
+
+    result.map(|()| stats)
+}
+
+/// Whether the capture is still writing frames, i.e. it hasn't been stopped or failed.
+pub fn is_running(capture: &Capture) -> bool {
+    capture
+        .thread
+        .as_ref()
+        .is_some_and(|thread| !thread.is_finished())
+}
+
+/// Drop counts of a running capture since it started or since this was last called, so a
+/// capture spanning several runs can be split up between them.
+pub fn take_stats(capture: &mut Capture) -> CaptureStats {
+    let stats = match &mut capture.source {
+        Source::Socket {
+            interface,
+            socket,
+            interface_drops: last_interface_drops,
+            ..
+        } => {
+            let (packets, drops) = socket.take_statistics().unwrap_or_else(|e| {
+                log::warn!("Failed to read capture statistics: {}", e);
+
+                (0, 0)
+            });
+
+            let interface_drops = interface_drops(interface);
+
+            let stats = CaptureStats {
+                packets,
+                drops,
+                interface_drops: interface_drops.saturating_sub(*last_interface_drops),
+            };
+
+            *last_interface_drops = interface_drops;
+
+            if stats.interface_drops > 0 {
+                log::warn!(
+                    "--> {} frames dropped by {}, latencies will look better than they are",
+                    stats.interface_drops,
+                    interface
+                );
+            }
+
+            stats
+        }
+        // Nothing is received from the NIC, so it can't drop anything that would be missed
+        Source::InBand => CaptureStats {
+            packets: IN_BAND_PACKETS.swap(0, Ordering::Relaxed),
+            drops: IN_BAND_DROPS.swap(0, Ordering::Relaxed),
+            interface_drops: 0,
+        },
+    };
+
+    if stats.drops > 0 {
+        log::warn!(
+            "--> {} frames dropped by the capture, latencies will look better than they are",
+            stats.drops
+        );
+    }
+
+    stats
+}
+
+/// Nanoseconds since the Unix epoch.
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/// Sum of the kernel's counters of frames `interface` dropped on receive. Zero if they can't be
+/// read.
+fn interface_drops(interface: &str) -> u64 {
+    ["rx_dropped", "rx_missed_errors"]
+        .iter()
+        .filter_map(|counter| {
+            fs::read_to_string(format!(
+                "/sys/class/net/{}/statistics/{}",
+                interface, counter
+            ))
+            .ok()?
+            .trim()
+            .parse::<u64>()
+            .ok()
+        })
+        .sum()
+}
+
+/// Copy every packet captured from `start` up to but not including `end` out of a session
+/// capture made by [`start`] into its own file. Every other block is copied as-is, so the new
+/// file has the same section and interface as the session.
+pub fn split(
+    session: &Path,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    out: &Path,
+) -> io::Result<()> {
+    let time_ns = |t: DateTime<Utc>| t.timestamp_nanos_opt().unwrap_or_default().max(0) as u64;
+
+    let (start_ns, end_ns) = (time_ns(start), time_ns(end));
+
+    let mut writer = BufWriter::new(File::create(out)?);
+
+    let res = for_each_block(session, |block_type, body| {
+        if block_type == PcapngWriter::ENHANCED_PACKET {
+            // Always nanosecond resolution, see `PcapngWriter::create`
+            let packet_ns = body
+                .get(4..12)
+                .map(|time| {
+                    let field =
+                        |i: usize| u32::from_le_bytes(time[i..i + 4].try_into().expect("4 bytes"));
+
+                    (u64::from(field(0)) << 32) | u64::from(field(4))
+                })
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad packet block"))?;
+
+            if !(start_ns..end_ns).contains(&packet_ns) {
+                return Ok(());
+            }
+        }
+
+        // Type and both lengths
+        let total_len = (body.len() + 12) as u32;
+
+        writer.write_all(&block_type.to_le_bytes())?;
+        writer.write_all(&total_len.to_le_bytes())?;
+        writer.write_all(body)?;
+        writer.write_all(&total_len.to_le_bytes())
+    })
+    .and_then(|()| writer.flush());
+
+    if res.is_err() {
+        // Don't leave a partial dump behind to be ingested
+        fs::remove_file(out).ok();
+    }
+
+    res
+}
+
+/// Compile a tcpdump filter expression to classic BPF for `interface`'s link type with
+/// `tcpdump -ddd`.
+fn compile_filter(
+    interface: &str,
+    filter: &str,
+    snaplen: usize,
+) -> io::Result<Vec<libc::sock_filter>> {
+    let output = Command::new("tcpdump")
+        .arg("-i")
+        .arg(interface)
+        .arg("-s")
+        .arg(snaplen.to_string())
+        .arg("-ddd")
+        .arg(filter)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid capture filter {:?}: {}",
+                filter,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Unexpected tcpdump -ddd output");
+
+    // Instruction count, then `code jt jf k` on each line
+    let mut lines = std::str::from_utf8(&output.stdout)
+        .map_err(|_| invalid())?
+        .lines();
+
+    let len = lines
+        .next()
+        .and_then(|line| line.trim().parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+
+    let program = lines
+        .take(len)
+        .map(|line| {
+            let mut fields = line.split_whitespace().map(str::parse::<u32>);
+
+            let mut field = || fields.next().and_then(Result::ok).ok_or_else(invalid);
+
+            Ok(libc::sock_filter {
+                code: field()? as u16,
+                jt: field()? as u8,
+                jf: field()? as u8,
+                k: field()?,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if program.len() != len {
+        return Err(invalid());
+    }
+
+    Ok(program)
+}
+
+/// Write frames from `socket` until `stop` is set. Frames are already filtered by the socket if
+/// `filtered` is set, otherwise only EtherCAT ones are kept. Marker frames are never written, and
+/// `synced` is set once one with the `marker` payload is seen.
+fn capture_loop(
+    socket: &CaptureSocket,
+    mut writer: PcapngWriter,
+    stop: &AtomicBool,
+    snaplen: usize,
+    filtered: bool,
+    marker: u64,
+    synced: &AtomicBool,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; snaplen];
+
+    loop {
+        // Everything sent before `stop` was called is already queued on the socket by now, so
+        // read until it's empty then finish
+        let stopping = stop.load(Ordering::Relaxed);
+
+        let frame = match socket.recv(&mut buf, stopping) {
+            Ok(frame) => frame,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::Interrupted =>
+            {
+                if stopping {
+                    break;
+                }
+
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let data = &buf[0..frame.captured_len];
+
+        let ethertype = data.get(12..14);
+
+        if ethertype == Some(&MARKER_ETHERTYPE.to_be_bytes()) {
+            if data.get(14..22) == Some(&marker.to_be_bytes()) {
+                synced.store(true, Ordering::Relaxed);
+            }
+        } else if filtered || ethertype == Some(&ETHERCAT_ETHERTYPE.to_be_bytes()) {
+            writer.write_packet(
+                frame.time_ns,
+                frame.hardware_time_ns,
+                data,
+                frame.original_len,
+            )?;
+        }
+    }
+
+    writer.finish()
+}
+
+/// One frame read by [`CaptureSocket::recv`].
+struct ReceivedFrame {
+    captured_len: usize,
+    original_len: usize,
+    /// Kernel receive time, nanoseconds since the Unix epoch.
+    time_ns: u64,
+    /// NIC receive time, in nanoseconds of the NIC's own clock. Never set for sent frames.
+    hardware_time_ns: Option<u64>,
+}
+
+/// An `AF_PACKET` socket receiving every frame sent or received on one interface.
+struct CaptureSocket {
+    fd: OwnedFd,
+}
+
+impl CaptureSocket {
+    fn open(
+        interface: &str,
+        hardware_timestamps: bool,
+        filter: Option<&[libc::sock_filter]>,
+    ) -> io::Result<Self> {
+        let name = CString::new(interface)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Bad interface name"))?;
+
+        let ifindex = match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+            0 => return Err(io::Error::last_os_error()),
+            ifindex => ifindex as i32,
+        };
+
+        // `ETH_P_ALL` rather than the EtherCAT ethertype, as only these sockets see sent frames
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                i32::from(protocol),
+            )
+        };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let socket = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+
+        // Attached before binding so no unfiltered frames are queued
+        if let Some(filter) = filter {
+            let program = libc::sock_fprog {
+                len: filter.len() as u16,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+
+            socket.set_option(libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &program)?;
+        }
+
+        let sockaddr = libc::sockaddr_ll {
+            sll_family: libc::AF_PACKET as u16,
+            sll_protocol: protocol,
+            sll_ifindex: ifindex,
+            sll_hatype: 0,
+            sll_pkttype: 0,
+            sll_halen: 0,
+            sll_addr: [0; 8],
+        };
+
+        check(unsafe {
+            libc::bind(
+                socket.fd.as_raw_fd(),
+                &sockaddr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        })?;
+
+        // Frames for other MACs still arrive when capturing from a tap or mirror port
+        let membership = libc::packet_mreq {
+            mr_ifindex: ifindex,
+            mr_type: libc::PACKET_MR_PROMISC as u16,
+            mr_alen: 0,
+            mr_address: [0; 8],
+        };
+
+        socket.set_option(libc::SOL_PACKET, libc::PACKET_ADD_MEMBERSHIP, &membership)?;
+
+        socket.set_option(libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, &1 as &libc::c_int)?;
+
+        // Delivered alongside the software timestamp from `SO_TIMESTAMPNS`
+        if hardware_timestamps {
+            let flags = libc::SOF_TIMESTAMPING_RX_HARDWARE | libc::SOF_TIMESTAMPING_RAW_HARDWARE;
+
+            socket.set_option(libc::SOL_SOCKET, libc::SO_TIMESTAMPING, &flags)?;
+        }
+
+        socket.set_option(libc::SOL_SOCKET, libc::SO_RCVBUF, &RECV_BUF_BYTES)?;
+
+        socket.set_option(
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &libc::timeval {
+                tv_sec: STOP_POLL.as_secs() as libc::time_t,
+                tv_usec: STOP_POLL.subsec_micros() as libc::suseconds_t,
+            },
+        )?;
+
+        Ok(socket)
+    }
+
+    fn set_option<T>(&self, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+        check(unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                level,
+                name,
+                value as *const T as *const libc::c_void,
+                mem::size_of::<T>() as libc::socklen_t,
+            )
+        })
+    }
+
+    /// Frames received and dropped since the last call, as the kernel resets them when read.
+    fn take_statistics(&self) -> io::Result<(u64, u64)> {
+        let mut stats = libc::tpacket_stats {
+            tp_packets: 0,
+            tp_drops: 0,
+        };
+
+        let mut len = mem::size_of::<libc::tpacket_stats>() as libc::socklen_t;
+
+        check(unsafe {
+            libc::getsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                &mut stats as *mut libc::tpacket_stats as *mut libc::c_void,
+                &mut len,
+            )
+        })?;
+
+        // `tp_packets` includes drops
+        Ok((u64::from(stats.tp_packets), u64::from(stats.tp_drops)))
+    }
+
+    /// Receive a frame into `buf`. Returns [`io::ErrorKind::WouldBlock`] if nothing arrived within
+    /// [`STOP_POLL`], or straight away if `nonblocking` is set.
+    fn recv(&self, buf: &mut [u8], nonblocking: bool) -> io::Result<ReceivedFrame> {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        // `u64`s to align control messages. Big enough for software and hardware timestamps.
+        let mut control = [0u64; 16];
+
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control);
+
+        let flags = libc::MSG_TRUNC | if nonblocking { libc::MSG_DONTWAIT } else { 0 };
+
+        let len = unsafe { libc::recvmsg(self.fd.as_raw_fd(), &mut msg, flags) };
+
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let original_len = len as usize;
+
+        let (time_ns, hardware_time_ns) = receive_times_ns(&msg);
+
+        Ok(ReceivedFrame {
+            captured_len: original_len.min(buf.len()),
+            original_len,
+            time_ns: time_ns.unwrap_or_else(now_ns),
+            hardware_time_ns,
+        })
+    }
+}
+
+/// The `SO_TIMESTAMPNS` software and raw `SO_TIMESTAMPING` hardware timestamps of a received
+/// message, if the kernel attached them.
+fn receive_times_ns(msg: &libc::msghdr) -> (Option<u64>, Option<u64>) {
+    let mut software = None;
+    let mut hardware = None;
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+
+    while !cmsg.is_null() {
+        let header = unsafe { &*cmsg };
+        let data = unsafe { libc::CMSG_DATA(cmsg) as *const libc::timespec };
+
+        match (header.cmsg_level, header.cmsg_type) {
+            (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => {
+                software = timespec_ns(unsafe { data.read_unaligned() });
+            }
+            // Software, deprecated, then raw hardware timestamps
+            (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING) => {
+                hardware = timespec_ns(unsafe { data.add(2).read_unaligned() });
+            }
+            _ => (),
+        }
+
+        cmsg = unsafe { libc::CMSG_NXTHDR(msg, cmsg) };
+    }
+
+    (software, hardware)
+}
+
+/// Nanoseconds in `time`, or `None` if it's zero which the kernel uses for "not set".
+fn timespec_ns(time: libc::timespec) -> Option<u64> {
+    let ns = time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64;
+
+    (ns != 0).then_some(ns)
+}
+
+/// NIC hardware timestamping of every received frame, while this is alive. The previous
+/// configuration is put back when it's dropped.
+struct HardwareTimestamping {
+    socket: OwnedFd,
+    interface: String,
+    previous: libc::hwtstamp_config,
+}
+
+impl HardwareTimestamping {
+    fn enable(interface: &str) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut previous = libc::hwtstamp_config {
+            flags: 0,
+            tx_type: libc::HWTSTAMP_TX_OFF as libc::c_int,
+            rx_filter: libc::HWTSTAMP_FILTER_NONE as libc::c_int,
+        };
+
+        hwtstamp_ioctl(&socket, interface, libc::SIOCGHWTSTAMP, &mut previous)?;
+
+        // Leave TX alone, as PTP may be using it
+        let mut config = libc::hwtstamp_config {
+            rx_filter: libc::HWTSTAMP_FILTER_ALL as libc::c_int,
+            ..previous
+        };
+
+        hwtstamp_ioctl(&socket, interface, libc::SIOCSHWTSTAMP, &mut config)?;
+
+        // Changed from here on, so restored if anything else fails
+        let hardware_timestamping = Self {
+            socket,
+            interface: interface.to_string(),
+            previous,
+        };
+
+        // Drivers can fall back to a narrower filter than asked for
+        if config.rx_filter != libc::HWTSTAMP_FILTER_ALL as libc::c_int {
+            return Err(io::Error::other(format!(
+                "driver only supports RX filter {}",
+                config.rx_filter
+            )));
+        }
+
+        Ok(hardware_timestamping)
+    }
+}
+
+impl Drop for HardwareTimestamping {
+    fn drop(&mut self) {
+        let mut previous = self.previous;
+
+        if let Err(e) = hwtstamp_ioctl(
+            &self.socket,
+            &self.interface,
+            libc::SIOCSHWTSTAMP,
+            &mut previous,
+        ) {
+            log::warn!(
+                "Failed to restore hardware timestamping on {}: {}",
+                self.interface,
+                e
+            );
+        }
+    }
+}
+
+/// Get or set the hardware timestamping configuration of `interface`.
+fn hwtstamp_ioctl(
+    socket: &OwnedFd,
+    interface: &str,
+    request: libc::c_ulong,
+    config: &mut libc::hwtstamp_config,
+) -> io::Result<()> {
+    let mut ifreq: libc::ifreq = unsafe { mem::zeroed() };
+
+    for (dst, src) in ifreq
+        .ifr_name
+        .iter_mut()
+        .zip(interface.bytes().take(libc::IFNAMSIZ - 1))
+    {
+        *dst = src as libc::c_char;
+    }
+
+    ifreq.ifr_ifru.ifru_data = config as *mut libc::hwtstamp_config as *mut libc::c_char;
+
+    check(unsafe { libc::ioctl(socket.as_raw_fd(), request, &mut ifreq) })
+}
+
+/// Check a finished capture is a whole pcapng file with at least one packet in it, so a capture
+/// that failed or was cut off is caught straight away instead of at ingest. Returns the number of
+/// packets.
+pub fn verify(path: &Path) -> io::Result<usize> {
+    let packets = count_packets(path).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is truncated", path.display()),
+        ),
+        _ => e,
+    })?;
+
+    if packets == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no packets", path.display()),
+        ));
+    }
+
+    Ok(packets)
+}
+
+/// Size, checksum and packet count of a dump, stored with its run so the dump can be checked
+/// later to be the same file that was ingested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpFingerprint {
+    pub bytes: u64,
+
+    /// SHA-256 of the whole file, in lowercase hex.
+    pub sha256: String,
+
+    pub packets: u64,
+}
+
+/// Fingerprint the pcapng file at `path`.
+pub fn fingerprint(path: &Path) -> io::Result<DumpFingerprint> {
+    let mut hasher = Sha256::new();
+
+    let bytes = io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+
+    Ok(DumpFingerprint {
+        bytes,
+        sha256: format!("{:x}", hasher.finalize()),
+        packets: count_packets(path)? as u64,
+    })
+}
+
+/// Number of packets in the pcapng file at `path`.
+fn count_packets(path: &Path) -> io::Result<usize> {
+    let mut packets = 0;
+
+    for_each_block(path, |block_type, _body| {
+        if matches!(
+            block_type,
+            PcapngWriter::ENHANCED_PACKET
+                | PcapngWriter::SIMPLE_PACKET
+                | PcapngWriter::OBSOLETE_PACKET
+        ) {
+            packets += 1;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(packets)
+}
+
+/// Call `f` with the type and body of every block in the pcapng file at `path`, in order, stopping
+/// at the first error.
+fn for_each_block(path: &Path, mut f: impl FnMut(u32, &[u8]) -> io::Result<()>) -> io::Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    let mut header = [0u8; 8];
+
+    // Only a clean end of file between blocks is the end, anything else is a truncated block
+    while !file.fill_buf()?.is_empty() {
+        file.read_exact(&mut header)?;
+
+        let block_type = u32::from_le_bytes(header[0..4].try_into().expect("4 bytes"));
+        let total_len = u32::from_le_bytes(header[4..8].try_into().expect("4 bytes")) as usize;
+
+        // Body then the repeated length
+        let mut block = vec![0u8; total_len.saturating_sub(8)];
+
+        file.read_exact(&mut block)?;
+
+        let body = &block[0..block.len().saturating_sub(4)];
+
+        // Only little endian files are written here or by `editcap` on x86
+        if block_type == PcapngWriter::SECTION_HEADER
+            && body.get(0..4) != Some(&0x1a2b_3c4du32.to_le_bytes())
+        {
+            return Err(io::Error::other("Big endian pcapng isn't supported"));
+        }
+
+        f(block_type, body)?;
+    }
+
+    Ok(())
+}
+
+/// A packet read by [`Tail`], borrowed from its read buffer.
+#[derive(Debug)]
+pub struct TailedPacket<'a> {
+    /// Wireshark packet number, starting from 1.
+    pub number: usize,
+
+    pub time_ns: u64,
+
+    /// NIC hardware timestamp, if one was recorded.
+    pub hardware_time_ns: Option<u64>,
+
+    pub data: &'a [u8],
+}
+
+/// Timestamp, hardware timestamp and data range of a packet found by [`Tail`].
+type PacketSpan = (u64, Option<u64>, Range<usize>);
+
+/// File format read by [`Tail`], known once the start of the file has been read.
+#[derive(Debug)]
+enum TailFormat {
+    /// `if_tsresol` of each interface in the current section, see [`timestamp_ns`].
+    Pcapng { resolutions: Vec<u8> },
+
+    /// Classic pcap, as saved by Wireshark or another master's tools.
+    Pcap { nanosecond: bool },
+}
+
+/// Reads packets from a capture made by [`start`] or [`start_in_band`] while it's still being
+/// written. Blocks are only returned once they've been written in full.
+///
+/// Finished captures from elsewhere can be read too, as either little endian pcapng with any
+/// timestamp resolution or classic pcap.
+pub struct Tail {
+    file: File,
+
+    /// Bytes read from `file`. Everything before `start` has already been returned.
+    buf: Vec<u8>,
+    start: usize,
+
+    format: Option<TailFormat>,
+
+    packet_number: usize,
+}
+
+impl Tail {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: File::open(path)?,
+            buf: Vec::new(),
+            start: 0,
+            format: None,
+            packet_number: 0,
+        })
+    }
+
+    /// The next packet written so far, or `None` if the writer hasn't got any further yet.
+    pub fn next_packet(&mut self) -> io::Result<Option<TailedPacket<'_>>> {
+        loop {
+            if let Some((time_ns, hardware_time_ns, data)) = self.next_in_buf()? {
+                return Ok(Some(TailedPacket {
+                    number: self.packet_number,
+                    time_ns,
+                    hardware_time_ns,
+                    data: &self.buf[data],
+                }));
+            }
+
+            // Keep only the partial block, if any, before reading more after it
+            self.buf.drain(0..self.start);
+            self.start = 0;
+
+            let len = self.buf.len();
+
+            self.buf.resize(len + 64 * 1024, 0);
+
+            let read = self.file.read(&mut self.buf[len..])?;
+
+            self.buf.truncate(len + read);
+
+            if read == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Timestamps and the range in `buf` of the next packet that's been read in full.
+    fn next_in_buf(&mut self) -> io::Result<Option<PacketSpan>> {
+        let format = match self.format {
+            Some(ref mut format) => format,
+            None => {
+                let Some((format, header_len)) = detect_format(&self.buf[self.start..])? else {
+                    return Ok(None);
+                };
+
+                self.start += header_len;
+
+                self.format.insert(format)
+            }
+        };
+
+        match format {
+            TailFormat::Pcapng { resolutions } => {
+                while let Some((block_type, block_len)) = next_block(&self.buf[self.start..])? {
+                    let block = self.start..self.start + block_len;
+
+                    self.start += block_len;
+
+                    // Type and length either side of the body
+                    let body_start = block.start + 8;
+                    let body = &self.buf[body_start..block.end - 4];
+
+                    match block_type {
+                        // Only little endian files are read
+                        PcapngWriter::SECTION_HEADER => {
+                            if body.get(0..4) != Some(&0x1a2b_3c4du32.to_le_bytes()) {
+                                return Err(io::Error::other("Big endian pcapng isn't supported"));
+                            }
+
+                            // Interface IDs start again in each section
+                            resolutions.clear();
+                        }
+                        PcapngWriter::INTERFACE_DESCRIPTION => {
+                            resolutions.push(interface_resolution(body)?);
+                        }
+                        PcapngWriter::ENHANCED_PACKET => {
+                            self.packet_number += 1;
+
+                            let (time_ns, hardware_time_ns, data) =
+                                enhanced_packet(body, resolutions)?;
+
+                            return Ok(Some((
+                                time_ns,
+                                hardware_time_ns,
+                                body_start + data.start..body_start + data.end,
+                            )));
+                        }
+                        // Simple and obsolete packet blocks are numbered by Wireshark too
+                        PcapngWriter::SIMPLE_PACKET | PcapngWriter::OBSOLETE_PACKET => {
+                            self.packet_number += 1;
+                        }
+                        _ => (),
+                    }
+                }
+
+                Ok(None)
+            }
+            TailFormat::Pcap { nanosecond } => {
+                // Seconds, fraction, captured and original lengths
+                let Some(header) = self.buf.get(self.start..self.start + 16) else {
+                    return Ok(None);
+                };
+
+                let field = |i: usize| {
+                    u64::from(u32::from_le_bytes(
+                        header[i..i + 4].try_into().expect("4 bytes"),
+                    ))
+                };
+
+                let captured_len = field(8) as usize;
+
+                if captured_len > MAX_PCAP_RECORD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Bad record length {}", captured_len),
+                    ));
+                }
+
+                let data = self.start + 16..self.start + 16 + captured_len;
+
+                if self.buf.len() < data.end {
+                    return Ok(None);
+                }
+
+                let fraction_ns = if *nanosecond {
+                    field(4)
+                } else {
+                    field(4) * 1_000
+                };
+
+                self.start = data.end;
+                self.packet_number += 1;
+
+                Ok(Some((field(0) * 1_000_000_000 + fraction_ns, None, data)))
+            }
+        }
+    }
+
+    /// Whether the file ends part way through a block. Only meaningful once the writer has stopped
+    /// and `next_packet` has returned `None`.
+    pub fn is_truncated(&self) -> bool {
+        self.buf.len() > self.start
+    }
+}
+
+/// Largest captured length accepted in a classic pcap record, so garbage isn't waited on forever.
+const MAX_PCAP_RECORD: usize = 256 * 1024;
+
+/// Work out the format from the start of a file, returning it and the length of any file header
+/// to skip. `None` if not enough has been read yet.
+fn detect_format(buf: &[u8]) -> io::Result<Option<(TailFormat, usize)>> {
+    let Some(magic) = buf.get(0..4) else {
+        return Ok(None);
+    };
+
+    let nanosecond = match u32::from_le_bytes(magic.try_into().expect("4 bytes")) {
+        // The section header block is read like any other
+        PcapngWriter::SECTION_HEADER => {
+            return Ok(Some((
+                TailFormat::Pcapng {
+                    resolutions: Vec::new(),
+                },
+                0,
+            )));
+        }
+        0xa1b2_c3d4 => false,
+        0xa1b2_3c4d => true,
+        0xd4c3_b2a1 | 0x4d3c_b2a1 => {
+            return Err(io::Error::other("Big endian pcap isn't supported"));
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a pcap or pcapng file",
+            ));
+        }
+    };
+
+    // Magic, version, time zone, sigfigs, snaplen, then link type
+    let Some(header) = buf.get(0..24) else {
+        return Ok(None);
+    };
+
+    check_link_type(u32::from_le_bytes(
+        header[20..24].try_into().expect("4 bytes"),
+    ))?;
+
+    Ok(Some((TailFormat::Pcap { nanosecond }, header.len())))
+}
+
+/// Type and total length of the pcapng block at the start of `buf`, if all of it has been read.
+fn next_block(buf: &[u8]) -> io::Result<Option<(u32, usize)>> {
+    let Some(header) = buf.get(0..8) else {
+        return Ok(None);
+    };
+
+    let block_type = u32::from_le_bytes(header[0..4].try_into().expect("4 bytes"));
+    let total_len = u32::from_le_bytes(header[4..8].try_into().expect("4 bytes")) as usize;
+
+    // Type and both lengths
+    if total_len < 12 || !total_len.is_multiple_of(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Bad block length {}", total_len),
+        ));
+    }
+
+    Ok((buf.len() >= total_len).then_some((block_type, total_len)))
+}
+
+/// Only Ethernet frames can be parsed into PDUs.
+fn check_link_type(link_type: u32) -> io::Result<()> {
+    if link_type != u32::from(PcapngWriter::LINKTYPE_ETHERNET) {
+        return Err(io::Error::other(format!(
+            "Link type {} isn't supported, only Ethernet",
+            link_type
+        )));
+    }
+
+    Ok(())
+}
+
+/// Check an interface description block body is for Ethernet, and find its `if_tsresol`.
+fn interface_resolution(body: &[u8]) -> io::Result<u8> {
+    let link_type = body
+        .get(0..2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Bad interface block"))?;
+
+    check_link_type(u32::from(link_type))?;
+
+    // Link type, reserved and snaplen come before the options
+    let mut options = body.get(8..).unwrap_or_default();
+
+    while options.len() >= 4 {
+        let code = u16::from_le_bytes([options[0], options[1]]);
+        let len = u16::from_le_bytes([options[2], options[3]]) as usize;
+
+        if code == PcapngWriter::OPT_END {
+            break;
+        }
+
+        if let (PcapngWriter::IF_TSRESOL, Some(&[resolution])) = (code, options.get(4..4 + len)) {
+            return Ok(resolution);
+        }
+
+        options = options
+            .get((4 + len).next_multiple_of(4)..)
+            .unwrap_or_default();
+    }
+
+    // Microseconds when not given
+    Ok(6)
+}
+
+/// Convert a pcapng timestamp in units given by `if_tsresol` to nanoseconds. The top bit picks a
+/// negative power of 2 instead of 10.
+fn timestamp_ns(ticks: u64, resolution: u8) -> u64 {
+    let exponent = u32::from(resolution & 0x7f);
+    let ticks = u128::from(ticks);
+
+    let ns = if resolution & 0x80 != 0 {
+        (ticks * 1_000_000_000) >> exponent
+    } else if exponent <= 9 {
+        ticks * 10u128.pow(9 - exponent)
+    } else {
+        10u128
+            .checked_pow(exponent - 9)
+            .map_or(0, |divisor| ticks / divisor)
+    };
+
+    ns as u64
+}
+
+/// Read the timestamps of the packet in an enhanced packet block body, and the range of its data
+/// in `body`.
+fn enhanced_packet(body: &[u8], resolutions: &[u8]) -> io::Result<PacketSpan> {
+    let field = |range: Range<usize>| {
+        body.get(range)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+    };
+
+    let bad = || io::Error::new(io::ErrorKind::InvalidData, "Bad packet block");
+
+    let interface = field(0..4).ok_or_else(bad)? as usize;
+    let captured_len = field(12..16).ok_or_else(bad)? as usize;
+
+    let data = 20..20 + captured_len;
+
+    if body.len() < data.end {
+        return Err(bad());
+    }
+
+    let ticks =
+        (u64::from(field(4..8).ok_or_else(bad)?) << 32) | u64::from(field(8..12).ok_or_else(bad)?);
+
+    let resolution = resolutions.get(interface).copied().ok_or_else(bad)?;
+
+    Ok((
+        timestamp_ns(ticks, resolution),
+        packet_hardware_timestamp(body),
+        data,
+    ))
+}
+
+/// Find the hardware timestamp comment in the options of an enhanced packet block body.
+fn packet_hardware_timestamp(body: &[u8]) -> Option<u64> {
+    let captured_len = u32::from_le_bytes(body.get(12..16)?.try_into().ok()?) as usize;
+
+    let mut options = body.get((20 + captured_len).next_multiple_of(4)..)?;
+
+    while options.len() >= 4 {
+        let code = u16::from_le_bytes([options[0], options[1]]);
+        let len = u16::from_le_bytes([options[2], options[3]]) as usize;
+        let value = options.get(4..4 + len)?;
+
+        if code == PcapngWriter::OPT_END {
+            break;
+        }
+
+        let time_ns = std::str::from_utf8(value)
+            .ok()
+            .filter(|_| code == PcapngWriter::OPT_COMMENT)
+            .and_then(|comment| comment.strip_prefix(HARDWARE_TIMESTAMP_COMMENT))
+            .and_then(|time_ns| time_ns.parse().ok());
+
+        if time_ns.is_some() {
+            return time_ns;
+        }
+
+        options = options.get((4 + len).next_multiple_of(4)..)?;
+    }
+
+    None
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    if res == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes a pcapng file with one Ethernet interface and nanosecond timestamps.
+struct PcapngWriter {
+    out: BufWriter<File>,
+}
+
+impl PcapngWriter {
+    const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+    const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+    const ENHANCED_PACKET: u32 = 0x0000_0006;
+    const SIMPLE_PACKET: u32 = 0x0000_0003;
+    const OBSOLETE_PACKET: u32 = 0x0000_0002;
+
+    const LINKTYPE_ETHERNET: u16 = 1;
+
+    const OPT_END: u16 = 0;
+    const OPT_COMMENT: u16 = 1;
+    const IF_NAME: u16 = 2;
+    const IF_TSRESOL: u16 = 9;
+    const IF_FILTER: u16 = 11;
+
+    fn create(
+        path: &Path,
+        interface: &str,
+        filter: Option<&str>,
+        snaplen: usize,
+    ) -> io::Result<Self> {
+        let mut writer = Self {
+            out: BufWriter::new(File::create(path)?),
+        };
+
+        let mut section = Vec::new();
+        // Byte order magic
+        section.extend_from_slice(&0x1a2b_3c4du32.to_le_bytes());
+        // Version 1.0
+        section.extend_from_slice(&1u16.to_le_bytes());
+        section.extend_from_slice(&0u16.to_le_bytes());
+        // Section length isn't known up front
+        section.extend_from_slice(&(-1i64).to_le_bytes());
+
+        writer.write_block(Self::SECTION_HEADER, &section)?;
+
+        let mut interface_description = Vec::new();
+        interface_description.extend_from_slice(&Self::LINKTYPE_ETHERNET.to_le_bytes());
+        // Reserved
+        interface_description.extend_from_slice(&0u16.to_le_bytes());
+        interface_description.extend_from_slice(&(snaplen as u32).to_le_bytes());
+        push_option(
+            &mut interface_description,
+            Self::IF_NAME,
+            interface.as_bytes(),
+        );
+
+        // Shown by Wireshark's capture file properties. A leading 0 means a libpcap expression.
+        if let Some(filter) = filter {
+            let mut value = vec![0];
+            value.extend_from_slice(filter.as_bytes());
+
+            push_option(&mut interface_description, Self::IF_FILTER, &value);
+        }
+        // 10^-9 second timestamps
+        push_option(&mut interface_description, Self::IF_TSRESOL, &[9]);
+        push_option(&mut interface_description, Self::OPT_END, &[]);
+
+        writer.write_block(Self::INTERFACE_DESCRIPTION, &interface_description)?;
+
+        Ok(writer)
+    }
+
+    fn write_packet(
+        &mut self,
+        time_ns: u64,
+        hardware_time_ns: Option<u64>,
+        data: &[u8],
+        original_len: usize,
+    ) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(20 + data.len() + 48);
+        // Interface ID
+        packet.extend_from_slice(&0u32.to_le_bytes());
+        packet.extend_from_slice(&((time_ns >> 32) as u32).to_le_bytes());
+        packet.extend_from_slice(&(time_ns as u32).to_le_bytes());
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&(original_len as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+        pad(&mut packet);
+
+        if let Some(hardware_time_ns) = hardware_time_ns {
+            let comment = format!("{}{}", HARDWARE_TIMESTAMP_COMMENT, hardware_time_ns);
+
+            push_option(&mut packet, Self::OPT_COMMENT, comment.as_bytes());
+            push_option(&mut packet, Self::OPT_END, &[]);
+        }
+
+        self.write_block(Self::ENHANCED_PACKET, &packet)
+    }
+
+    /// Write a block with `body` already padded to 32 bits.
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        // Type and both lengths
+        let total_len = (body.len() + 12) as u32;
+
+        self.out.write_all(&block_type.to_le_bytes())?;
+        self.out.write_all(&total_len.to_le_bytes())?;
+        self.out.write_all(body)?;
+        self.out.write_all(&total_len.to_le_bytes())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.flush()?;
+
+        self.out.get_ref().sync_all()
+    }
+}
+
+fn push_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    block.extend_from_slice(value);
+    pad(block);
+}
+
+/// Pad to a multiple of 32 bits, as every pcapng field must be.
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairing::{logical_pdus, test_frame};
+
+    #[test]
+    fn tail_waits_for_whole_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("latency-data-tail-{}.pcapng", std::process::id()));
+
+        let sent = test_frame(true, &[(0x0c, 1, 8), (0x0a, 2, 4)]);
+        let received = test_frame(false, &[(0x0c, 1, 8), (0x0a, 2, 4)]);
+
+        let mut writer = PcapngWriter::create(&path, "test", None, DEFAULT_SNAPLEN).unwrap();
+        writer
+            .write_packet(1_000, Some(900), &sent, sent.len())
+            .unwrap();
+        writer
+            .write_packet(2_000, None, &received, received.len())
+            .unwrap();
+        writer.finish().unwrap();
+
+        let dump = fs::read(&path).unwrap();
+
+        // Write everything but the end of the second packet
+        fs::write(&path, &dump[0..dump.len() - 10]).unwrap();
+
+        let mut tail = Tail::open(&path).unwrap();
+
+        let packet = tail.next_packet().unwrap().expect("First packet");
+
+        assert_eq!(
+            (packet.number, packet.time_ns, packet.hardware_time_ns),
+            (1, 1_000, Some(900))
+        );
+        assert_eq!(packet.data, sent);
+
+        let pdus = logical_pdus(
+            packet.data,
+            Duration::from_nanos(packet.time_ns),
+            packet.number,
+        )
+        .map(|pdu| (pdu.index, pdu.data_len, pdu.from_master))
+        .collect::<Vec<_>>();
+
+        assert_eq!(pdus, [(1, 8, true), (2, 4, true)]);
+
+        assert!(tail.next_packet().unwrap().is_none());
+        assert!(tail.is_truncated());
+
+        // The writer catches up
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&dump[dump.len() - 10..])
+            .unwrap();
+
+        let packet = tail.next_packet().unwrap().expect("Second packet");
+
+        assert_eq!((packet.number, packet.hardware_time_ns), (2, None));
+
+        let pdus = logical_pdus(
+            packet.data,
+            Duration::from_nanos(packet.time_ns),
+            packet.number,
+        )
+        .map(|pdu| (pdu.index, pdu.from_master))
+        .collect::<Vec<_>>();
+
+        assert_eq!(pdus, [(1, false), (2, false)]);
+
+        assert!(tail.next_packet().unwrap().is_none());
+        assert!(!tail.is_truncated());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn split_keeps_packets_in_range() {
+        let dir = std::env::temp_dir();
+        let session = dir.join(format!("latency-data-split-{}.pcapng", std::process::id()));
+        let out = dir.join(format!(
+            "latency-data-split-{}-out.pcapng",
+            std::process::id()
+        ));
+
+        let frame = test_frame(true, &[(0x0c, 1, 8)]);
+
+        let mut writer = PcapngWriter::create(&session, "test", None, DEFAULT_SNAPLEN).unwrap();
+
+        for time_ns in [1_000, 2_000, 3_000] {
+            writer
+                .write_packet(time_ns, Some(time_ns - 1), &frame, frame.len())
+                .unwrap();
+        }
+
+        writer.finish().unwrap();
+
+        split(
+            &session,
+            DateTime::from_timestamp_nanos(2_000),
+            DateTime::from_timestamp_nanos(3_000),
+            &out,
+        )
+        .unwrap();
+
+        let mut tail = Tail::open(&out).unwrap();
+
+        let packet = tail.next_packet().unwrap().expect("Packet in range");
+
+        assert_eq!(
+            (packet.number, packet.time_ns, packet.hardware_time_ns),
+            (1, 2_000, Some(1_999))
+        );
+        assert_eq!(packet.data, frame);
+
+        assert!(tail.next_packet().unwrap().is_none());
+        assert!(!tail.is_truncated());
+
+        fs::remove_file(&session).ok();
+        fs::remove_file(&out).ok();
+    }
+
+    #[test]
+    fn tail_reads_other_formats() {
+        let path = std::env::temp_dir().join(format!(
+            "latency-data-tail-formats-{}.pcap",
+            std::process::id()
+        ));
+
+        // Another master's address, without the locally administered bit
+        let mut sent = test_frame(true, &[(0x0c, 1, 8)]);
+        sent[6..12].copy_from_slice(&[0x00, 0x01, 0x05, 0x10, 0x20, 0x30]);
+
+        let block = |block_type: u32, body: &[u8]| {
+            let len = (12 + body.len().next_multiple_of(4)) as u32;
+            let mut block = [block_type.to_le_bytes(), len.to_le_bytes()].concat();
+            block.extend_from_slice(body);
+            pad(&mut block);
+            block.extend_from_slice(&len.to_le_bytes());
+            block
+        };
+
+        // pcapng with no `if_tsresol`, so microseconds
+        let mut pcapng = block(
+            PcapngWriter::SECTION_HEADER,
+            &[
+                0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+        );
+        pcapng.extend(block(
+            PcapngWriter::INTERFACE_DESCRIPTION,
+            &[1, 0, 0, 0, 0xff, 0xff, 0, 0],
+        ));
+        let mut packet = [0u32, 0, 1_500, sent.len() as u32, sent.len() as u32]
+            .map(u32::to_le_bytes)
+            .concat();
+        packet.extend_from_slice(&sent);
+        pcapng.extend(block(PcapngWriter::ENHANCED_PACKET, &packet));
+
+        // Classic pcap with microsecond timestamps
+        let mut pcap = [0xa1b2_c3d4u32, 4 << 16 | 2, 0, 0, 65535, 1]
+            .map(u32::to_le_bytes)
+            .concat();
+        pcap.extend(
+            [1, 1_500, sent.len() as u32, sent.len() as u32]
+                .map(u32::to_le_bytes)
+                .concat(),
+        );
+        pcap.extend_from_slice(&sent);
+
+        for (dump, time_ns) in [(pcapng, 1_500_000), (pcap, 1_001_500_000)] {
+            fs::write(&path, dump).unwrap();
+
+            let mut tail = Tail::open(&path).unwrap();
+
+            let packet = tail.next_packet().unwrap().expect("Packet");
+
+            assert_eq!((packet.number, packet.time_ns), (1, time_ns));
+
+            let pdus = logical_pdus(
+                packet.data,
+                Duration::from_nanos(packet.time_ns),
+                packet.number,
+            )
+            .map(|pdu| (pdu.index, pdu.from_master))
+            .collect::<Vec<_>>();
+
+            assert_eq!(pdus, [(1, true)]);
+
+            assert!(tail.next_packet().unwrap().is_none());
+            assert!(!tail.is_truncated());
+        }
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn tail_rejects_bad_block_length() {
+        let path = std::env::temp_dir().join(format!(
+            "latency-data-tail-bad-{}.pcapng",
+            std::process::id()
+        ));
+
+        fs::write(&path, [0x0a, 0x0d, 0x0d, 0x0a, 5, 0, 0, 0]).unwrap();
+
+        let mut tail = Tail::open(&path).unwrap();
+
+        assert_eq!(
+            tail.next_packet().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,131 @@
+//! Compress dumps with zstd once they've been ingested, and read them back for re-analysis.
+//!
+//! Captures are mostly repeated headers and compress very well, so a full matrix run shrinks from
+//! gigabytes to a fraction of that. Compressed dumps keep their name with `.zst` appended.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// zstd level to compress dumps with. Higher levels are much slower for little gain on captures.
+const LEVEL: i32 = 9;
+
+/// `path` with `.zst` appended.
+fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+
+    name.push(".zst");
+
+    PathBuf::from(name)
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "zst")
+}
+
+/// Compress `path` to `<path>.zst` and remove the original. Returns the compressed file's path.
+pub fn compress(path: &Path) -> io::Result<PathBuf> {
+    let out = compressed_path(path);
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut encoder = zstd::Encoder::new(BufWriter::new(File::create(&out)?), LEVEL)?;
+
+    io::copy(&mut reader, &mut encoder)?;
+
+    encoder.finish()?.into_inner()?.sync_all()?;
+
+    let before = fs::metadata(path)?.len();
+    let after = fs::metadata(&out)?.len();
+
+    fs::remove_file(path)?;
+
+    log::info!(
+        "--> Compressed {} to {:.1}% of {} MiB",
+        out.display(),
+        after as f64 / before.max(1) as f64 * 100.0,
+        before / 1024 / 1024
+    );
+
+    Ok(out)
+}
+
+/// A dump ready to be read, decompressed to a temporary file if it was compressed.
+#[derive(Debug)]
+pub struct Dump {
+    path: PathBuf,
+
+    /// Remove `path` when dropped.
+    temporary: bool,
+}
+
+impl Dump {
+    /// Open `path`, or `<path>.zst` if only the compressed dump is left. `.zst` files are
+    /// decompressed into the temp dir and removed again when the `Dump` is dropped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let path = if !path.exists() && compressed_path(path).exists() {
+            compressed_path(path)
+        } else {
+            path.to_path_buf()
+        };
+
+        if !is_compressed(&path) {
+            return Ok(Self {
+                path,
+                temporary: false,
+            });
+        }
+
+        let name = path
+            .file_stem()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name"))?;
+
+        let mut temp = std::env::temp_dir();
+
+        temp.push(format!(
+            "latency-data-{}-{}",
+            std::process::id(),
+            name.to_string_lossy()
+        ));
+
+        log::debug!("Decompressing {} to {}", path.display(), temp.display());
+
+        let mut decoder = zstd::Decoder::new(File::open(&path)?)?;
+        let mut writer = BufWriter::new(File::create(&temp)?);
+
+        // Build the guard before copying so a failed decompress cleans up after itself
+        let dump = Self {
+            path: temp,
+            temporary: true,
+        };
+
+        io::copy(&mut decoder, &mut writer)?;
+
+        writer.flush()?;
+
+        Ok(dump)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Dump {
+    fn drop(&mut self) {
+        if self.temporary {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// File name of a dump without its `.zst` and capture extensions, e.g. `run` for `run.pcapng.zst`.
+pub fn dump_stem(path: &Path) -> Option<&OsStr> {
+    if is_compressed(path) {
+        path.file_stem().map(Path::new).and_then(Path::file_stem)
+    } else {
+        path.file_stem()
+    }
+}
@@ -0,0 +1,291 @@
+//! Run matrix loaded from a TOML file, so benchmark campaigns can be checked in and repeated.
+//!
+//! Every top level key is optional and overrides the command line argument of the same name.
+//! `[scenario.<name>]` tables narrow down or change the matrix for a single scenario.
+//!
+//! ```toml
+//! scenarios = ["1thr-1task", "11thr-10task"]
+//! # Only used if `scenarios` is empty
+//! filter = "1thr"
+//! exclude = "-(spin|poll)$"
+//! select_tags = ["multi-thread"]
+//! skip_tags = ["custom-io"]
+//! cycle_times = [100, 250, 500, 1000]
+//! # `task:net` RT priorities
+//! prios = ["48:49", "90:91"]
+//! repeat = 3
+//! # Or "batch" to repeat each run back to back
+//! repeat_mode = "interleave"
+//! iterations = 10000
+//! warmup_cycles = 500
+//! abort_miss_rate = 0.05
+//! # Keep PTP frames too, and only the first 256 bytes of each frame
+//! capture_filter = "ether proto 0x88a4 or ether proto 0x88f7"
+//! capture_snaplen = 256
+//! tags = ["campaign-1"]
+//!
+//! # Only run at 1ms
+//! [scenario."11thr-10task"]
+//! cycle_times = [1000]
+//!
+//! [scenario."1thr-1task"]
+//! iterations = 50000
+//! repeat = 5
+//! # Misses are expected here, so be more lenient
+//! abort_miss_rate = 0.2
+//!
+//! # A scenario put together from a description instead of code. Every key is optional
+//! [[compose]]
+//! name = "3thr-4task-tokio-spin"
+//! # 0 runs TX/RX on the first task thread
+//! net_threads = 1
+//! task_threads = 2
+//! tasks_per_thread = 2
+//! # smol, async-std or tokio
+//! executor = "tokio"
+//! # async, spin or nanosleep
+//! timer = "spin"
+//! ```
+
+use crate::{
+    parse_prio_pair,
+    scenarios::{Composition, CompositionSpec, RepeatMode, ScenarioRun},
+    Args,
+};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RunConfig {
+    scenarios: Option<Vec<String>>,
+    filter: Option<String>,
+    exclude: Option<String>,
+    select_tags: Option<Vec<String>>,
+    skip_tags: Option<Vec<String>>,
+    cycle_times: Option<Vec<u32>>,
+    prios: Option<Vec<PrioPair>>,
+    repeat: Option<u32>,
+    repeat_mode: Option<RepeatMode>,
+    iterations: Option<usize>,
+    warmup_cycles: Option<usize>,
+    abort_miss_rate: Option<f64>,
+    abort_window: Option<usize>,
+    capture_filter: Option<String>,
+    capture_snaplen: Option<usize>,
+    tags: Option<Vec<String>>,
+
+    /// Overrides keyed by scenario name.
+    #[serde(default)]
+    scenario: BTreeMap<String, ScenarioOverride>,
+
+    /// Scenarios described here instead of in code.
+    #[serde(default)]
+    compose: Vec<CompositionSpec>,
+}
+
+/// Task and net thread priorities, written as `task:net`.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+struct PrioPair(u8, u8);
+
+impl TryFrom<String> for PrioPair {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        parse_prio_pair(&s).map(|(task, net)| Self(task, net))
+    }
+}
+
+/// Changes to the run matrix for one scenario.
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioOverride {
+    /// Only run the scenario at these of the matrix's cycle times.
+    cycle_times: Option<Vec<u32>>,
+
+    /// Only run the scenario with these of the matrix's priority pairs, on RT kernels.
+    prios: Option<Vec<PrioPair>>,
+
+    /// Run the scenario this many times instead of the global `repeat`.
+    repeat: Option<u32>,
+
+    iterations: Option<usize>,
+    warmup_cycles: Option<usize>,
+    abort_miss_rate: Option<f64>,
+    abort_window: Option<usize>,
+}
+
+/// Per-scenario overrides from a [`RunConfig`].
+#[derive(Debug, Default)]
+pub struct Overrides(BTreeMap<String, ScenarioOverride>);
+
+impl RunConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Replace any arguments set in this config, returning the per-scenario overrides to apply to
+    /// each run.
+    pub fn apply(self, args: &mut Args) -> anyhow::Result<Overrides> {
+        let Self {
+            scenarios,
+            filter,
+            exclude,
+            select_tags,
+            skip_tags,
+            cycle_times,
+            prios,
+            repeat,
+            repeat_mode,
+            iterations,
+            warmup_cycles,
+            abort_miss_rate,
+            abort_window,
+            capture_filter,
+            capture_snaplen,
+            tags,
+            scenario,
+            compose,
+        } = self;
+
+        if let Some(prios) = prios {
+            args.prios = prios
+                .into_iter()
+                .map(|PrioPair(task, net)| (task, net))
+                .collect();
+        }
+
+        if let Some(scenarios) = scenarios {
+            args.scenarios = scenarios;
+        }
+
+        if let Some(filter) = filter {
+            args.filter = Some(Regex::new(&filter)?);
+        }
+
+        if let Some(exclude) = exclude {
+            args.exclude = Some(Regex::new(&exclude)?);
+        }
+
+        if let Some(select_tags) = select_tags {
+            args.select_tags = select_tags;
+        }
+
+        if let Some(skip_tags) = skip_tags {
+            args.skip_tags = skip_tags;
+        }
+
+        if let Some(cycle_times) = cycle_times {
+            args.cycle_times = cycle_times;
+        }
+
+        if let Some(repeat) = repeat {
+            args.repeat = repeat;
+        }
+
+        if let Some(repeat_mode) = repeat_mode {
+            args.repeat_mode = repeat_mode;
+        }
+
+        if iterations.is_some() {
+            args.iterations = iterations;
+        }
+
+        if let Some(warmup_cycles) = warmup_cycles {
+            args.warmup_cycles = warmup_cycles;
+        }
+
+        if abort_miss_rate.is_some() {
+            args.abort_miss_rate = abort_miss_rate;
+        }
+
+        if let Some(abort_window) = abort_window {
+            args.abort_window = abort_window;
+        }
+
+        if capture_filter.is_some() {
+            args.capture_filter = capture_filter;
+        }
+
+        if let Some(capture_snaplen) = capture_snaplen {
+            args.capture_snaplen = capture_snaplen;
+        }
+
+        if let Some(tags) = tags {
+            args.tags = tags;
+        }
+
+        args.compositions = compose
+            .into_iter()
+            .map(Composition::new)
+            .collect::<Result<_, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid composed scenario: {}", e))?;
+
+        Ok(Overrides(scenario))
+    }
+}
+
+impl Overrides {
+    /// Scenarios with overrides that aren't in `names`, e.g. because of a typo.
+    pub fn unmatched<'a>(&'a self, names: &BTreeSet<&str>) -> Vec<&'a str> {
+        self.0
+            .keys()
+            .map(String::as_str)
+            .filter(|name| !names.contains(name))
+            .collect()
+    }
+
+    /// How many times to run `scenario`, if it's overridden.
+    pub fn repeat(&self, scenario: &str) -> Option<u32> {
+        self.0.get(scenario).and_then(|overrides| overrides.repeat)
+    }
+
+    /// Apply any overrides for `run`'s scenario, returning `false` if it shouldn't run at all.
+    pub fn apply(&self, run: &mut ScenarioRun) -> bool {
+        let Some(overrides) = self.0.get(run.name()) else {
+            return true;
+        };
+
+        let settings = run.settings_mut();
+
+        if overrides
+            .cycle_times
+            .as_ref()
+            .is_some_and(|cycle_times| !cycle_times.contains(&settings.cycle_time_us))
+        {
+            return false;
+        }
+
+        // Priorities aren't set without RT, so there's nothing to choose between
+        if settings.is_rt
+            && overrides.prios.as_ref().is_some_and(|prios| {
+                !prios.contains(&PrioPair(settings.task_prio, settings.net_prio))
+            })
+        {
+            return false;
+        }
+
+        if overrides.iterations.is_some() {
+            settings.iterations = overrides.iterations;
+        }
+
+        if let Some(warmup_cycles) = overrides.warmup_cycles {
+            settings.warmup_cycles = warmup_cycles;
+        }
+
+        if overrides.abort_miss_rate.is_some() {
+            settings.abort_miss_rate = overrides.abort_miss_rate;
+        }
+
+        if let Some(abort_window) = overrides.abort_window {
+            settings.abort_window = abort_window;
+        }
+
+        true
+    }
+}
@@ -12,6 +12,15 @@ create table if not exists "frames" (
   primary key ("id")
 );
 
+-- PDU payload length in bytes
+alter table "frames" add column if not exists "data_len" smallint;
+-- Position of the PDU in its Ethernet frame, as frames can hold more than one
+alter table "frames" add column if not exists "pdu_position" smallint;
+-- NIC hardware receive timestamps of the sent and received frames, in the NIC's own clock, if
+-- recorded. Sent frames only have one when captured on a separate mirror port.
+alter table "frames" add column if not exists "hw_tx_time_ns" bigint;
+alter table "frames" add column if not exists "hw_rx_time_ns" bigint;
+
 create index if not exists "frames_scenario" on "frames" ("run");
 create index if not exists "frames_run" on "frames" ("run" text_pattern_ops);
 
@@ -44,6 +53,33 @@ begin
   end;
 end $$;
 
+-- EtherCrab git revision the run was made with
+alter table "runs" add column if not exists "ethercrab_rev" character varying(64);
+
+-- EtherCAT master that produced the run, for comparing captures imported from other stacks
+alter table "runs" add column if not exists "master" character varying(32) not null default 'ethercrab';
+
+-- perf_event totals over every scenario thread, e.g. `{"context_switches": 12}`, if recorded
+alter table "runs" add column if not exists "perf_counters" jsonb;
+
+-- Tags of the scenario that was run, e.g. `{single-thread,smol}`
+alter table "runs" add column if not exists "scenario_tags" text[] not null default '{}';
+
+-- Whether the run was stopped early for missing too many deadlines
+alter table "runs" add column if not exists "aborted" boolean not null default false;
+
+-- Frames seen and dropped by the capture socket, and dropped by the interface before reaching it,
+-- while the run was captured. Null if it wasn't.
+alter table "runs" add column if not exists "capture_packets" bigint;
+alter table "runs" add column if not exists "capture_drops" bigint;
+alter table "runs" add column if not exists "interface_drops" bigint;
+
+-- Size, SHA-256 and packet count of the uncompressed dump frames were ingested from, to check a dump
+-- being re-analysed is the same file. Null if the run wasn't captured.
+alter table "runs" add column if not exists "dump_bytes" bigint;
+alter table "runs" add column if not exists "dump_sha256" text;
+alter table "runs" add column if not exists "dump_packets" bigint;
+
 create table if not exists "cycles" (
   "id" serial not null,
   primary key ("id"),
@@ -67,3 +103,314 @@ begin
     add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
   end if;
 end $$;
+
+create table if not exists "summaries" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  -- `processing_time`, `tick_wait`, `cycle_time_delta` or `frame_delta_time`
+  "metric" character varying(32) not null,
+  "count" bigint not null,
+  "min_ns" integer not null,
+  "max_ns" integer not null,
+  "mean_ns" double precision not null,
+  "stddev_ns" double precision not null,
+  "p25_ns" integer not null,
+  "p50_ns" integer not null,
+  "p75_ns" integer not null,
+  "p95_ns" integer not null,
+  "p99_ns" integer not null,
+  -- Non-empty histogram buckets as `[lower_bound_ns, count]` pairs
+  "histogram" json not null
+);
+
+create index if not exists "summaries_run" on "summaries" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'summaries_run_fkey') then
+    alter table "summaries"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Exact aggregates for every cycle in a bucket when only every Nth cycle is stored in `cycles`
+create table if not exists "cycle_buckets" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  "first_cycle" integer not null,
+  "count" integer not null,
+  "processing_time_min_ns" integer not null,
+  "processing_time_max_ns" integer not null,
+  "processing_time_mean_ns" double precision not null,
+  "tick_wait_min_ns" integer not null,
+  "tick_wait_max_ns" integer not null,
+  "tick_wait_mean_ns" double precision not null,
+  "cycle_time_delta_min_ns" integer not null,
+  "cycle_time_delta_max_ns" integer not null,
+  "cycle_time_delta_mean_ns" double precision not null
+);
+
+create index if not exists "cycle_buckets_run" on "cycle_buckets" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'cycle_buckets_run_fkey') then
+    alter table "cycle_buckets"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Time from sending an output change to reading it back on a wired input, for scenarios that
+-- measure it
+alter table "cycles" add column if not exists "reaction_latency_ns" integer;
+
+-- Alternative to `cycles` with one row per run. Each column holds the whole run's values in cycle
+-- order, which Postgres compresses transparently.
+create table if not exists "cycle_series" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  "cycle" integer[] not null,
+  "processing_time_ns" integer[] not null,
+  "tick_wait_ns" integer[] not null,
+  "cycle_time_delta_ns" integer[] not null
+);
+
+create index if not exists "cycle_series_run" on "cycle_series" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'cycle_series_run_fkey') then
+    alter table "cycle_series"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+alter table "cycle_series" add column if not exists "reaction_latency_ns" integer[];
+alter table "cycle_buckets" add column if not exists "reaction_latency_min_ns" integer;
+alter table "cycle_buckets" add column if not exists "reaction_latency_max_ns" integer;
+alter table "cycle_buckets" add column if not exists "reaction_latency_mean_ns" double precision;
+
+-- Time since the last SYNC0 edge on the DC reference clock when the cycle woke, for scenarios that
+-- measure it
+alter table "cycles" add column if not exists "dc_offset_ns" integer;
+alter table "cycle_series" add column if not exists "dc_offset_ns" integer[];
+alter table "cycle_buckets" add column if not exists "dc_offset_min_ns" integer;
+alter table "cycle_buckets" add column if not exists "dc_offset_max_ns" integer;
+alter table "cycle_buckets" add column if not exists "dc_offset_mean_ns" double precision;
+
+-- Largest DC system time difference from the reference clock across all devices, for scenarios that
+-- measure it. Negative when a device's clock is behind.
+alter table "cycles" add column if not exists "dc_drift_ns" integer;
+alter table "cycle_series" add column if not exists "dc_drift_ns" integer[];
+-- Aggregated by magnitude, so a bucket's minimum is the smallest drift either way
+alter table "cycle_buckets" add column if not exists "dc_drift_min_ns" integer;
+alter table "cycle_buckets" add column if not exists "dc_drift_max_ns" integer;
+alter table "cycle_buckets" add column if not exists "dc_drift_mean_ns" double precision;
+
+-- Group the cycle belongs to, for scenarios that run groups at different rates or priorities
+alter table "cycles" add column if not exists "group_index" integer;
+alter table "cycle_series" add column if not exists "group_index" integer[];
+alter table "cycle_buckets" add column if not exists "group_index" integer;
+
+-- Expand `cycle_series` back out into the same shape as `cycles`
+create or replace view "cycle_series_rows" as
+select
+  s."run",
+  c."cycle",
+  c."processing_time_ns",
+  c."tick_wait_ns",
+  c."cycle_time_delta_ns",
+  c."reaction_latency_ns",
+  c."dc_offset_ns",
+  c."dc_drift_ns",
+  c."group_index"
+from "cycle_series" s
+cross join lateral unnest(
+  s."cycle",
+  s."processing_time_ns",
+  s."tick_wait_ns",
+  s."cycle_time_delta_ns",
+  s."reaction_latency_ns",
+  s."dc_offset_ns",
+  s."dc_drift_ns",
+  s."group_index"
+) as c ("cycle", "processing_time_ns", "tick_wait_ns", "cycle_time_delta_ns", "reaction_latency_ns", "dc_offset_ns", "dc_drift_ns", "group_index");
+
+-- Statistics for each EtherCrab log call site that fired during a run, with `--ethercrab-events`
+create table if not exists "ethercrab_events" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  -- Module path and source line of the log call
+  "module" character varying(128) not null,
+  "line" integer not null,
+  "level" character varying(8) not null,
+  -- First message logged from this site
+  "example" text not null,
+  "count" bigint not null,
+  -- Time between consecutive events from this site
+  "interval_min_ns" integer not null,
+  "interval_max_ns" integer not null,
+  "interval_mean_ns" double precision not null,
+  "interval_p50_ns" integer not null,
+  "interval_p99_ns" integer not null
+);
+
+create index if not exists "ethercrab_events_run" on "ethercrab_events" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'ethercrab_events_run_fkey') then
+    alter table "ethercrab_events"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Files uploaded to object storage. `run` is null for files covering a whole suite.
+create table if not exists "artifacts" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128),
+  -- `dump`, `annotated-dump`, `cycles-csv`, `summaries-csv`, `bench`
+  "kind" character varying(32) not null,
+  "url" text not null,
+  "date" timestamptz not null default now()
+);
+
+create index if not exists "artifacts_run" on "artifacts" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'artifacts_run_fkey') then
+    alter table "artifacts"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- PHC to system clock offsets sampled during a run with `--phc-offset-ms`
+create table if not exists "phc_offsets" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  "time" timestamptz not null,
+  -- PHC - CLOCK_REALTIME
+  "offset_ns" bigint not null
+);
+
+create index if not exists "phc_offsets_run" on "phc_offsets" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'phc_offsets_run_fkey') then
+    alter table "phc_offsets"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Time each LRW frame spent in the host's network stack, recorded with `--kernel-probes`
+create table if not exists "kernel_residency" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  -- Send order of the LRW in the run, from 0
+  "seq" integer not null,
+  "index" smallint not null,
+  -- sendmsg to driver transmit
+  "tx_stack_ns" integer,
+  -- driver receive to packet socket delivery
+  "rx_stack_ns" integer
+);
+
+create index if not exists "kernel_residency_run" on "kernel_residency" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'kernel_residency_run_fkey') then
+    alter table "kernel_residency"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Captured frames alongside their kernel network stack times. Frames are matched by send order.
+-- PDUs packed into the same Ethernet frame share a packet number, so they share its stack times.
+-- Dropped first as new `frames` columns change the shape of `f.*`.
+drop view if exists "frame_kernel_residency";
+create view "frame_kernel_residency" as
+select f.*, k.tx_stack_ns, k.rx_stack_ns
+from (
+  select *, dense_rank() over (partition by run order by packet_number) - 1 as seq
+  from frames
+) f
+join kernel_residency k on k.run = f.run and k.seq = f.seq;
+
+-- Deadline misses, with scheduler events around them if traced with `--sched-trace-window-us`
+create table if not exists "spikes" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  "cycle" integer not null,
+  "cycle_time_delta_ns" integer not null,
+  -- CLOCK_MONOTONIC time the miss was detected at
+  "time_ns" bigint not null,
+  -- Thread that most often took the CPU from a scenario thread around the miss
+  "preempted_by" text,
+  -- Raw ftrace lines around the miss
+  "sched_trace" text
+);
+
+create index if not exists "spikes_run" on "spikes" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'spikes_run_fkey') then
+    alter table "spikes"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Time taken to move devices between states, for scenarios that measure it
+create table if not exists "transitions" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  -- Null for the whole network
+  "group_index" integer,
+  "from_state" text not null,
+  "to_state" text not null,
+  "duration_ns" bigint not null
+);
+
+create index if not exists "transitions_run" on "transitions" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'transitions_run_fkey') then
+    alter table "transitions"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
+
+-- Shortest cycle time that kept deadline misses under `max_miss_rate`, found by the minimum cycle
+-- time search scenario
+create table if not exists "min_cycle_times" (
+  "id" serial not null,
+  primary key ("id"),
+  "run" character varying(128) not null,
+  "cycle_time_us" integer not null,
+  "max_miss_rate" double precision not null,
+  -- Number of cycle times tried
+  "probes" integer not null
+);
+
+create index if not exists "min_cycle_times_run" on "min_cycle_times" ("run");
+
+do $$
+begin
+  if not exists (select 1 from pg_constraint where conname = 'min_cycle_times_run_fkey') then
+    alter table "min_cycle_times"
+    add foreign key ("run") references "runs" ("name") on delete cascade on update no action;
+  end if;
+end $$;
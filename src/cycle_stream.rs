@@ -0,0 +1,155 @@
+//! Stream process cycles to a file as they're recorded instead of keeping them in memory, so runs
+//! lasting hours don't grow without bound.
+//!
+//! Cycles are sent to a writer thread over a bounded channel. Sending never blocks: if the writer
+//! falls behind, cycles are dropped and counted instead of delaying the scenario. Nothing is
+//! streamed unless [`start`] has been called, and only cycles pushed to a [`Sink`] taken after
+//! that are streamed.
+
+use crate::scenarios::CycleMetadata;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Number of cycles buffered for the writer before they start being dropped.
+const CAPACITY: usize = 64 * 1024;
+
+/// Where [`sink`] gets a sender from while a stream is running.
+static SINK: Mutex<Option<SyncSender<CycleMetadata>>> = Mutex::new(None);
+
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+pub struct Stream {
+    path: PathBuf,
+    handle: JoinHandle<io::Result<u64>>,
+}
+
+/// Stream every cycle pushed from now on to `path`, one CSV line each.
+pub fn start(path: &Path) -> io::Result<Stream> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    let (tx, rx) = mpsc::sync_channel::<CycleMetadata>(CAPACITY);
+
+    DROPPED.store(0, Ordering::Relaxed);
+
+    let handle = thread::spawn(move || {
+        let mut written = 0u64;
+
+        // Ends once `stop` and every `Sink` have dropped their senders
+        for cycle in rx {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                cycle.cycle,
+                cycle.processing_time_ns,
+                cycle.tick_wait_ns,
+                cycle.cycle_time_delta_ns
+            )?;
+
+            written += 1;
+        }
+
+        out.flush()?;
+
+        Ok(written)
+    });
+
+    *SINK.lock().unwrap() = Some(tx);
+
+    Ok(Stream {
+        path: path.to_path_buf(),
+        handle,
+    })
+}
+
+/// Sends cycles to a running [`Stream`]. Held by a run's `Cycles` so pushing a cycle doesn't take
+/// a lock.
+#[derive(Debug, Clone)]
+pub struct Sink {
+    tx: SyncSender<CycleMetadata>,
+}
+
+impl Sink {
+    /// Stream a process cycle. Cheap enough to call from a scenario's cycle loop.
+    pub fn push(&self, cycle: &CycleMetadata) {
+        if self.tx.try_send(cycle.clone()).is_err() {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A sink for the running stream, if there is one.
+pub fn sink() -> Option<Sink> {
+    SINK.lock().unwrap().clone().map(|tx| Sink { tx })
+}
+
+impl Stream {
+    /// Stop streaming and wait for every buffered cycle to be written. Every [`Sink`] must have
+    /// been dropped first.
+    pub fn stop(self) -> io::Result<()> {
+        SINK.lock().unwrap().take();
+
+        let written = self.handle.join().expect("Cycle stream writer panicked")?;
+
+        let dropped = DROPPED.load(Ordering::Relaxed);
+
+        log::info!("--> Streamed {} cycles to {}", written, self.path.display());
+
+        if dropped > 0 {
+            log::warn!("--> Dropped {} cycles, writer couldn't keep up", dropped);
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads back cycles streamed to a file.
+pub struct Reader {
+    lines: io::Lines<BufReader<File>>,
+}
+
+impl Reader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            lines: BufReader::new(File::open(path)?).lines(),
+        })
+    }
+
+    /// Read up to `len` more cycles. Empty once every cycle has been read.
+    pub fn next_chunk(&mut self, len: usize) -> io::Result<Vec<CycleMetadata>> {
+        let mut chunk = Vec::with_capacity(len);
+
+        for line in self.lines.by_ref().take(len) {
+            let line = line?;
+
+            let mut fields = line.split(',').map(str::parse::<u64>);
+
+            let mut field = || {
+                fields.next().and_then(Result::ok).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Bad line {:?}", line))
+                })
+            };
+
+            chunk.push(CycleMetadata {
+                cycle: field()? as usize,
+                processing_time_ns: field()? as u32,
+                tick_wait_ns: field()? as u32,
+                cycle_time_delta_ns: field()? as u32,
+                reaction_latency_ns: None,
+                dc_offset_ns: None,
+                dc_drift_ns: None,
+                group_index: None,
+            });
+        }
+
+        Ok(chunk)
+    }
+}
@@ -2,11 +2,119 @@
 
 use sqlx::{Executor, PgPool};
 
+/// Connect to the Postgres DB without changing anything, for read-only users like `serve` and
+/// `export`.
+pub async fn connect(db_url: &str) -> anyhow::Result<PgPool> {
+    Ok(PgPool::connect(db_url).await?)
+}
+
 /// Connect to the Postgres DB and run the init script to create tables if they don't exist.
 pub async fn connect_and_init(db_url: &str) -> anyhow::Result<PgPool> {
-    let pool = PgPool::connect(db_url).await?;
+    let pool = connect(db_url).await?;
 
     pool.execute(include_str!("./create.sql")).await?;
 
     Ok(pool)
 }
+
+/// Rows encoded in Postgres' binary `COPY` format.
+///
+/// Values are written straight into a byte buffer in network byte order, so there's no per-row
+/// string formatting here or text parsing on the server. Fields must be written in the same order
+/// as the columns given to the `COPY` statement.
+pub struct BinaryCopy {
+    buf: Vec<u8>,
+    scratch: String,
+}
+
+impl BinaryCopy {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buf = Vec::with_capacity(capacity);
+
+        // Signature, flags, header extension length
+        buf.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+
+        Self {
+            buf,
+            scratch: String::new(),
+        }
+    }
+
+    /// Start a new row with the given number of fields.
+    pub fn row(&mut self, fields: i16) -> &mut Self {
+        self.buf.extend_from_slice(&fields.to_be_bytes());
+
+        self
+    }
+
+    pub fn int2(&mut self, value: i16) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    pub fn int4(&mut self, value: i32) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    pub fn int8(&mut self, value: i64) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    pub fn float8(&mut self, value: f64) -> &mut Self {
+        self.field(&value.to_be_bytes())
+    }
+
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        self.field(value.as_bytes())
+    }
+
+    /// Write a SQL `NULL`.
+    pub fn null(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&(-1i32).to_be_bytes());
+
+        self
+    }
+
+    /// Write a text field using the value's `Display` impl.
+    pub fn display(&mut self, value: impl std::fmt::Display) -> &mut Self {
+        use std::fmt::Write;
+
+        let mut scratch = std::mem::take(&mut self.scratch);
+
+        scratch.clear();
+
+        write!(scratch, "{}", value).expect("Display");
+
+        self.text(&scratch);
+
+        self.scratch = scratch;
+
+        self
+    }
+
+    fn field(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf
+            .extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+        self.buf.extend_from_slice(bytes);
+
+        self
+    }
+
+    /// Data encoded since the last call to [`clear`](BinaryCopy::clear).
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Clear data that has already been sent, keeping the allocation around for reuse.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Write the end of data marker and return any remaining encoded data.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.extend_from_slice(&(-1i16).to_be_bytes());
+
+        self.buf
+    }
+}
@@ -0,0 +1,197 @@
+//! Record EtherCrab's internal instrumentation while scenarios run.
+//!
+//! EtherCrab instruments frame allocation, PDU send/receive and slave state transitions with `log`
+//! calls rather than `tracing` spans, so each call site is treated as an event source. For every
+//! site that fires during a run, the number of events and the time between consecutive events is
+//! kept and stored in the `ethercrab_events` table.
+//!
+//! Recording raises the global log level to `trace`, which has a measurable cost inside the cycle
+//! loop. Only compare runs with recording enabled against each other.
+//!
+//! [`TraceLogging`] turns on EtherCrab's trace logging for a run without recording anything, so
+//! the cost of instrumentation itself can be measured against the same run without it.
+
+use crate::stats::Histogram;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Instant,
+};
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+static TRACING: AtomicBool = AtomicBool::new(false);
+
+static SITES: OnceLock<Mutex<HashMap<(&'static str, u32), Site>>> = OnceLock::new();
+
+/// Statistics for a single EtherCrab log call site during one run.
+#[derive(Debug, Clone)]
+pub struct EventSite {
+    /// Module the event was emitted from, e.g. `ethercrab::pdu_loop::storage`.
+    pub module: &'static str,
+
+    /// Source line of the log call.
+    pub line: u32,
+
+    pub level: log::Level,
+
+    /// First message logged from this site, to identify it.
+    pub example: String,
+
+    pub count: u64,
+
+    /// Time between consecutive events from this site.
+    pub intervals: Histogram,
+}
+
+#[derive(Debug)]
+struct Site {
+    event: EventSite,
+    last: Instant,
+}
+
+/// Wraps the normal logger, recording EtherCrab events when enabled.
+struct Logger {
+    inner: env_logger::Logger,
+    capture: bool,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+            || ((self.capture || TRACING.load(Ordering::Relaxed))
+                && metadata.target().starts_with("ethercrab"))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.capture
+            && RECORDING.load(Ordering::Relaxed)
+            && record.target().starts_with("ethercrab")
+        {
+            self.record(record);
+        }
+
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        } else if TRACING.load(Ordering::Relaxed) && record.target().starts_with("ethercrab") {
+            // Pay for formatting like a real log output would, but don't flood the terminal
+            write!(
+                io::sink(),
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            )
+            .ok();
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+impl Logger {
+    fn record(&self, record: &log::Record) {
+        let now = Instant::now();
+
+        let key = (
+            record.module_path_static().unwrap_or("ethercrab"),
+            record.line().unwrap_or(0),
+        );
+
+        let mut sites = SITES
+            .get_or_init(Default::default)
+            .lock()
+            .expect("Event sites lock");
+
+        let site = sites.entry(key).or_insert_with(|| Site {
+            event: EventSite {
+                module: key.0,
+                line: key.1,
+                level: record.level(),
+                example: record.args().to_string(),
+                count: 0,
+                intervals: Histogram::default(),
+            },
+            last: now,
+        });
+
+        if site.event.count > 0 {
+            site.event
+                .intervals
+                .record((now - site.last).as_nanos().min(u128::from(u32::MAX)) as u32);
+        }
+
+        site.event.count += 1;
+        site.last = now;
+    }
+}
+
+/// Enables EtherCrab's trace logging until dropped.
+pub struct TraceLogging {
+    max_level: log::LevelFilter,
+}
+
+impl TraceLogging {
+    pub fn enable() -> Self {
+        let max_level = log::max_level();
+
+        TRACING.store(true, Ordering::Relaxed);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        Self { max_level }
+    }
+}
+
+impl Drop for TraceLogging {
+    fn drop(&mut self) {
+        TRACING.store(false, Ordering::Relaxed);
+        log::set_max_level(self.max_level);
+    }
+}
+
+/// Install the global logger, optionally capturing EtherCrab's events.
+pub fn init_logger(inner: env_logger::Logger, capture: bool) {
+    let max_level = if capture {
+        log::LevelFilter::Trace
+    } else {
+        inner.filter()
+    };
+
+    log::set_boxed_logger(Box::new(Logger { inner, capture })).expect("Set logger");
+    log::set_max_level(max_level);
+}
+
+/// Start recording events for a new run.
+pub fn start() {
+    if let Some(sites) = SITES.get() {
+        sites.lock().expect("Event sites lock").clear();
+    }
+
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stop recording and return every site that fired since [`start`], busiest first.
+pub fn stop() -> Vec<EventSite> {
+    RECORDING.store(false, Ordering::Relaxed);
+
+    let Some(sites) = SITES.get() else {
+        return Vec::new();
+    };
+
+    let mut events = sites
+        .lock()
+        .expect("Event sites lock")
+        .drain()
+        .map(|(_key, site)| site.event)
+        .collect::<Vec<_>>();
+
+    events.sort_by_key(|event| std::cmp::Reverse(event.count));
+
+    events
+}
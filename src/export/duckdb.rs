@@ -0,0 +1,88 @@
+//! Export the database as a single DuckDB file.
+//!
+//! The runs are exported as a Parquet dataset first, then loaded into a new database with the
+//! `duckdb` CLI, so this doesn't need DuckDB linked into the binary.
+
+use std::{fs, path::Path, process::Command};
+
+/// Tables to create, and the Parquet files to load them from, relative to the dataset.
+const TABLES: &[(&str, &str)] = &[
+    ("runs", "read_parquet('{}/runs.parquet')"),
+    ("summaries", "read_parquet('{}/summaries.parquet')"),
+    (
+        "cycles",
+        "read_parquet('{}/cycles/*/*.parquet', hive_partitioning = true)",
+    ),
+    (
+        "frames",
+        "read_parquet('{}/frames/*/*.parquet', hive_partitioning = true)",
+    ),
+];
+
+/// Export every run whose name contains `filter`, or all runs, to a DuckDB database at `out`.
+pub async fn export(db: &str, out: &Path, filter: Option<&str>) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !out.exists(),
+        "{} already exists, remove it first",
+        out.display()
+    );
+
+    let dataset = out.with_extension("parquet-tmp");
+
+    // Ignore errors
+    let _ = fs::remove_dir_all(&dataset);
+
+    let result = load(db, &dataset, out, filter).await;
+
+    // The dataset is only needed to load from, whether that worked or not
+    let _ = fs::remove_dir_all(&dataset);
+
+    if result.is_err() {
+        // Don't leave a half-loaded database behind
+        let _ = fs::remove_file(out);
+    }
+
+    result?;
+
+    log::info!("Wrote {}", out.display());
+
+    Ok(())
+}
+
+/// Export runs to a Parquet dataset at `dataset`, then load it into a new DuckDB database at `out`.
+async fn load(db: &str, dataset: &Path, out: &Path, filter: Option<&str>) -> anyhow::Result<()> {
+    super::parquet::export(db, dataset, filter).await?;
+
+    let dataset_path = dataset.display().to_string();
+
+    let script = TABLES
+        .iter()
+        .filter(|(table, _)| {
+            // Partitioned tables have no files at all if every run was summary-only or uncaptured
+            *table == "runs"
+                || *table == "summaries"
+                || fs::read_dir(dataset.join(table)).is_ok_and(|mut dir| dir.next().is_some())
+        })
+        .map(|(table, source)| {
+            format!(
+                "create table {} as select * from {};",
+                table,
+                source.replace("{}", &dataset_path)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    log::debug!("Running DuckDB script\n{}", script);
+
+    let status = Command::new("duckdb")
+        .arg(out)
+        .arg("-c")
+        .arg(&script)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to run duckdb: {}", e))?;
+
+    anyhow::ensure!(status.success(), "duckdb exited with {}", status);
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! Export run results to files for analysis without Postgres.
+//!
+//! Each run is written by its own worker so serialisation of a large suite is spread across every
+//! core instead of happening in one loop alongside ingest.
+
+pub mod duckdb;
+pub mod parquet;
+
+use crate::{
+    pairing::{self, FRAMES_CSV_HEADER},
+    scenarios::{dump_path, summaries_by_slug, RunMetadata},
+    upload::Artifact,
+};
+use serde_json::json;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Write `<run>.cycles.csv` and `<run>.summaries.csv` for every run into `dir`, plus
+/// `<run>.frames.csv` for runs with a capture, returning every file written.
+pub fn export_csv(dir: &Path, results: &[(&str, RunMetadata)]) -> anyhow::Result<Vec<Artifact>> {
+    fs::create_dir_all(dir)?;
+
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(results.len());
+
+    let next = AtomicUsize::new(0);
+
+    // Each run's files, tagged with the run's position so they're returned in run order
+    let mut files = thread::scope(|s| {
+        let handles = (0..workers)
+            .map(|_| {
+                s.spawn(|| -> anyhow::Result<Vec<(usize, Artifact)>> {
+                    let mut files = Vec::new();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        let Some((_scenario, result)) = results.get(i) else {
+                            break;
+                        };
+
+                        write_cycles_csv(dir, result)?;
+                        write_summaries_csv(dir, result)?;
+
+                        let mut kinds = vec![
+                            ("cycles-csv", cycles_csv_path(dir, result)),
+                            ("summaries-csv", summaries_csv_path(dir, result)),
+                        ];
+
+                        if write_frames_csv(dir, result)? {
+                            kinds.push(("frames-csv", frames_csv_path(dir, result)));
+                        }
+
+                        files.extend(kinds.into_iter().map(|(kind, path)| {
+                            let artifact = Artifact {
+                                run: Some(result.name.clone()),
+                                kind,
+                                path,
+                            };
+
+                            (i, artifact)
+                        }));
+                    }
+
+                    Ok(files)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Export worker panicked"))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    files.sort_by_key(|(i, _)| *i);
+
+    log::info!("Exported {} runs to {}", results.len(), dir.display());
+
+    Ok(files.into_iter().map(|(_, artifact)| artifact).collect())
+}
+
+fn cycles_csv_path(dir: &Path, result: &RunMetadata) -> PathBuf {
+    dir.join(format!("{}.cycles.csv", result.name))
+}
+
+fn summaries_csv_path(dir: &Path, result: &RunMetadata) -> PathBuf {
+    dir.join(format!("{}.summaries.csv", result.name))
+}
+
+fn frames_csv_path(dir: &Path, result: &RunMetadata) -> PathBuf {
+    dir.join(format!("{}.frames.csv", result.name))
+}
+
+fn write_cycles_csv(dir: &Path, result: &RunMetadata) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(File::create(cycles_csv_path(dir, result))?);
+
+    writeln!(
+        out,
+        "cycle,processing_time_ns,tick_wait_ns,cycle_time_delta_ns"
+    )?;
+
+    for cycle in result.cycle_metadata.iter() {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            cycle.cycle, cycle.processing_time_ns, cycle.tick_wait_ns, cycle.cycle_time_delta_ns
+        )?;
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Pair the PDUs in a run's dump and write them out. Returns `false` without writing anything if
+/// the run has no dump, e.g. because it wasn't captured.
+fn write_frames_csv(dir: &Path, result: &RunMetadata) -> anyhow::Result<bool> {
+    let dump = dump_path(&result.name);
+
+    if !dump.exists() {
+        return Ok(false);
+    }
+
+    let mut out = BufWriter::new(File::create(frames_csv_path(dir, result))?);
+
+    writeln!(out, "{}", FRAMES_CSV_HEADER)?;
+
+    let mut written = Ok(());
+
+    pairing::pair_capture(&dump, result.settings.hardware_timestamps, |packet| {
+        // Pairing can't be stopped from here, so keep the first error
+        if written.is_ok() {
+            written = packet.write_csv(&mut out);
+        }
+    })?;
+
+    written?;
+
+    out.flush()?;
+
+    Ok(true)
+}
+
+fn write_summaries_csv(dir: &Path, result: &RunMetadata) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(File::create(summaries_csv_path(dir, result))?);
+
+    writeln!(
+        out,
+        "metric,count,min_ns,max_ns,mean_ns,stddev_ns,p25_ns,p50_ns,p75_ns,p95_ns,p99_ns"
+    )?;
+
+    for (metric, histogram) in result.cycle_summary.metrics() {
+        let s = histogram.summary();
+
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            metric,
+            s.count,
+            s.min_ns,
+            s.max_ns,
+            s.mean_ns,
+            s.stddev_ns,
+            s.p25_ns,
+            s.p50_ns,
+            s.p75_ns,
+            s.p95_ns,
+            s.p99_ns
+        )?;
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Output format for benchmark dashboards.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum BenchFormat {
+    /// `customSmallerIsBetter` JSON for `github-action-benchmark`.
+    #[default]
+    Github,
+    /// Bencher Metric Format.
+    Bencher,
+}
+
+/// Write p50/p99/max of every cycle metric, plus deadline misses, for each scenario configuration.
+///
+/// Benchmarks are named by run slug so the same configuration lines up across suites. Repeats of
+/// the same slug are merged into one set of values.
+pub fn export_bench(
+    path: &Path,
+    format: BenchFormat,
+    results: &[(&str, RunMetadata)],
+) -> anyhow::Result<()> {
+    let by_slug = summaries_by_slug(results);
+
+    // (benchmark, measure, value, unit)
+    let mut values = Vec::new();
+
+    for (slug, summary) in by_slug.iter() {
+        for (metric, histogram) in summary.metrics() {
+            let s = histogram.summary();
+
+            for (stat, value) in [("p50", s.p50_ns), ("p99", s.p99_ns), ("max", s.max_ns)] {
+                values.push((
+                    *slug,
+                    format!("{}_{}", metric, stat),
+                    f64::from(value),
+                    "ns",
+                ));
+            }
+        }
+
+        values.push((
+            *slug,
+            String::from("deadline_misses"),
+            summary.deadline_misses as f64,
+            "cycles",
+        ));
+    }
+
+    let out = match format {
+        BenchFormat::Github => values
+            .iter()
+            .map(|(slug, measure, value, unit)| {
+                json!({ "name": format!("{}/{}", slug, measure), "unit": unit, "value": value })
+            })
+            .collect::<serde_json::Value>(),
+        BenchFormat::Bencher => {
+            let mut benchmarks = serde_json::Map::new();
+
+            for (slug, measure, value, _unit) in values {
+                benchmarks
+                    .entry(slug)
+                    .or_insert_with(|| json!({}))
+                    .as_object_mut()
+                    .expect("Benchmark object")
+                    .insert(measure, json!({ "value": value }));
+            }
+
+            serde_json::Value::Object(benchmarks)
+        }
+    };
+
+    fs::write(path, serde_json::to_string_pretty(&out)?)?;
+
+    log::info!("Wrote {} benchmarks to {}", by_slug.len(), path.display());
+
+    Ok(())
+}
@@ -0,0 +1,664 @@
+//! Export the database as a partitioned Parquet dataset.
+//!
+//! ```text
+//! <out>/
+//!     runs.parquet
+//!     summaries.parquet
+//!     cycles/run=<name>/part-0.parquet
+//!     frames/run=<name>/part-0.parquet
+//! ```
+//!
+//! `cycles` and `frames` are partitioned Hive-style by run, so Polars, pandas/pyarrow and Spark
+//! add a `run` column when reading the directory. Every file carries a `latency_data.columns`
+//! key/value metadata entry with a JSON description of each column.
+//!
+//! Per-run files are written on blocking worker threads, one run per core, while the next runs are
+//! fetched from the database. Within a file, every column of every row group is compressed in
+//! parallel into memory, then spliced into the file in order.
+
+use crate::db::connect;
+use bytes::Bytes;
+use parquet::{
+    basic::{Compression, ZstdLevel},
+    column::writer::{get_column_writer, get_typed_column_writer, ColumnCloseResult, ColumnWriter},
+    data_type::{ByteArray, ByteArrayType, DataType, DoubleType, Int32Type, Int64Type},
+    file::{
+        properties::{WriterProperties, WriterPropertiesPtr},
+        writer::{SerializedFileWriter, SerializedPageWriter, TrackedWrite},
+    },
+    format::KeyValue,
+    schema::{parser::parse_message_type, types::ColumnDescPtr},
+};
+use sqlx::{query_as, PgPool};
+use std::{
+    fs::{self, File},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// Maximum number of rows in each Parquet row group.
+const ROW_GROUP_LEN: usize = 1024 * 1024;
+
+/// A Parquet file's schema and documentation for each of its columns.
+struct Table {
+    schema: &'static str,
+    columns: &'static [(&'static str, &'static str)],
+}
+
+const RUNS: Table = Table {
+    schema: "message runs {
+        required binary name (string);
+        required int64 date (timestamp(micros, true));
+        required binary scenario (string);
+        required binary slug (string);
+        required binary hostname (string);
+        required binary master (string);
+        required binary ethercrab_rev (string);
+        required int32 propagation_time_ns;
+        required binary settings (json);
+    }",
+    columns: &[
+        (
+            "name",
+            "Unique run name, used as the `run` key in every other table",
+        ),
+        ("date", "When the run started"),
+        ("scenario", "Scenario name, e.g. `1thr-1task`"),
+        (
+            "slug",
+            "Run name without the timestamp, identifying the configuration",
+        ),
+        ("hostname", "Machine the run was made on"),
+        (
+            "master",
+            "EtherCAT master, `ethercrab` unless imported from another stack",
+        ),
+        ("ethercrab_rev", "EtherCrab git revision, empty if unknown"),
+        (
+            "propagation_time_ns",
+            "EtherCAT network propagation time from DC",
+        ),
+        ("settings", "All test settings as JSON"),
+    ],
+};
+
+const SUMMARIES: Table = Table {
+    schema: "message summaries {
+        required binary run (string);
+        required binary metric (string);
+        required int64 count;
+        required int32 min_ns;
+        required int32 max_ns;
+        required double mean_ns;
+        required double stddev_ns;
+        required int32 p25_ns;
+        required int32 p50_ns;
+        required int32 p75_ns;
+        required int32 p95_ns;
+        required int32 p99_ns;
+        required binary histogram (json);
+    }",
+    columns: &[
+        ("run", "Run name"),
+        (
+            "metric",
+            "`processing_time`, `tick_wait`, `cycle_time_delta`, `reaction_latency`, `dc_offset`, \
+            `dc_drift` or `frame_delta_time`",
+        ),
+        ("count", "Number of values recorded"),
+        ("min_ns", "Minimum"),
+        ("max_ns", "Maximum"),
+        ("mean_ns", "Mean"),
+        ("stddev_ns", "Standard deviation"),
+        ("p25_ns", "25th percentile"),
+        ("p50_ns", "Median"),
+        ("p75_ns", "75th percentile"),
+        ("p95_ns", "95th percentile"),
+        ("p99_ns", "99th percentile"),
+        (
+            "histogram",
+            "Non-empty histogram buckets as `[lower_bound_ns, count]` pairs",
+        ),
+    ],
+};
+
+const CYCLES: Table = Table {
+    schema: "message cycles {
+        required int32 cycle;
+        required int32 processing_time_ns;
+        required int32 tick_wait_ns;
+        required int32 cycle_time_delta_ns;
+        optional int32 reaction_latency_ns;
+        optional int32 dc_offset_ns;
+        optional int32 dc_drift_ns;
+        optional int32 group_index;
+    }",
+    columns: &[
+        ("cycle", "Cycle number, starting from zero"),
+        (
+            "processing_time_ns",
+            "Time spent processing TX, RX and process data",
+        ),
+        ("tick_wait_ns", "Time spent waiting for the next tick"),
+        (
+            "cycle_time_delta_ns",
+            "Time since the same point in the previous cycle",
+        ),
+        (
+            "reaction_latency_ns",
+            "Time from an output change to reading it back on a wired input. HIL scenario only",
+        ),
+        (
+            "dc_offset_ns",
+            "Time since the last SYNC0 edge when the cycle woke. DC scenario only",
+        ),
+        (
+            "dc_drift_ns",
+            "Largest DC system time difference from the reference clock, negative when behind. \
+            DC drift scenario only",
+        ),
+        (
+            "group_index",
+            "Group the cycle exchanged, for scenarios running groups at different rates",
+        ),
+    ],
+};
+
+const FRAMES: Table = Table {
+    schema: "message frames {
+        required int32 packet_number;
+        required int32 index;
+        required binary command (string);
+        required int64 tx_time_ns;
+        required int64 rx_time_ns;
+        required int32 delta_time_ns;
+        optional int32 data_len;
+        optional int32 pdu_position;
+        optional int64 hw_tx_time_ns;
+        optional int64 hw_rx_time_ns;
+    }",
+    columns: &[
+        ("packet_number", "Wireshark packet number of the sent frame"),
+        ("index", "EtherCAT PDU index"),
+        ("command", "EtherCAT command, e.g. `LRW`"),
+        (
+            "tx_time_ns",
+            "Send time relative to the first cyclic frame in the capture",
+        ),
+        (
+            "rx_time_ns",
+            "Receive time relative to the first cyclic frame, 0 if no response",
+        ),
+        ("delta_time_ns", "Round trip time, 0 if no response"),
+        (
+            "data_len",
+            "PDU payload length in bytes, null for runs ingested before it was recorded",
+        ),
+        (
+            "pdu_position",
+            "Position of the PDU within its Ethernet frame, starting from zero",
+        ),
+        (
+            "hw_tx_time_ns",
+            "NIC hardware timestamp of the sent frame, if recorded",
+        ),
+        (
+            "hw_rx_time_ns",
+            "NIC hardware timestamp of the response, if recorded",
+        ),
+    ],
+};
+
+/// A column's values, in row order.
+enum Column {
+    Int32(Vec<i32>),
+    /// Values only some scenarios record, null for the rest.
+    OptionalInt32(Vec<Option<i32>>),
+    Int64(Vec<i64>),
+    OptionalInt64(Vec<Option<i64>>),
+    Double(Vec<f64>),
+    Text(Vec<ByteArray>),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Int32(v) => v.len(),
+            Column::OptionalInt32(v) => v.len(),
+            Column::Int64(v) => v.len(),
+            Column::OptionalInt64(v) => v.len(),
+            Column::Double(v) => v.len(),
+            Column::Text(v) => v.len(),
+        }
+    }
+}
+
+fn text(values: impl Iterator<Item = String>) -> Column {
+    Column::Text(values.map(|s| ByteArray::from(s.into_bytes())).collect())
+}
+
+/// Export every run whose name contains `filter`, or all runs, to a Parquet dataset in `out`.
+pub async fn export(db: &str, out: &Path, filter: Option<&str>) -> anyhow::Result<()> {
+    let db = connect(db).await?;
+
+    fs::create_dir_all(out)?;
+
+    let runs = query_as::<
+        _,
+        (
+            String,
+            i64,
+            String,
+            String,
+            String,
+            String,
+            String,
+            i32,
+            String,
+        ),
+    >(
+        r#"select
+            name,
+            (extract(epoch from date) * 1000000)::bigint,
+            scenario,
+            slug,
+            hostname,
+            master,
+            coalesce(ethercrab_rev, ''),
+            propagation_time_ns,
+            settings::text
+        from runs
+        where $1::text is null or strpos(name, $1) > 0
+        order by date"#,
+    )
+    .bind(filter)
+    .fetch_all(&db)
+    .await?;
+
+    let names = runs.iter().map(|run| run.0.clone()).collect::<Vec<_>>();
+
+    log::info!("Exporting {} runs to {}", names.len(), out.display());
+
+    let columns = vec![
+        text(runs.iter().map(|r| r.0.clone())),
+        Column::Int64(runs.iter().map(|r| r.1).collect()),
+        text(runs.iter().map(|r| r.2.clone())),
+        text(runs.iter().map(|r| r.3.clone())),
+        text(runs.iter().map(|r| r.4.clone())),
+        text(runs.iter().map(|r| r.5.clone())),
+        text(runs.iter().map(|r| r.6.clone())),
+        Column::Int32(runs.iter().map(|r| r.7).collect()),
+        text(runs.iter().map(|r| r.8.clone())),
+    ];
+
+    write_file(&out.join("runs.parquet"), &RUNS, columns)?;
+
+    export_summaries(&db, out, &names).await?;
+
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let permits = Arc::new(Semaphore::new(workers));
+    let mut writers = JoinSet::new();
+
+    for name in names {
+        let cycles = fetch_cycles(&db, &name).await?;
+        let frames = fetch_frames(&db, &name).await?;
+
+        let permit = permits.clone().acquire_owned().await?;
+        let out = out.to_path_buf();
+
+        writers.spawn_blocking(move || {
+            let _permit = permit;
+
+            let partition = format!("run={}", name);
+
+            if cycles[0].len() > 0 {
+                write_partition(&out, "cycles", &partition, &CYCLES, cycles)?;
+            }
+
+            if frames[0].len() > 0 {
+                write_partition(&out, "frames", &partition, &FRAMES, frames)?;
+            }
+
+            anyhow::Ok(())
+        });
+
+        // Surface write errors early instead of after fetching everything
+        while let Some(result) = writers.try_join_next() {
+            result??;
+        }
+    }
+
+    while let Some(result) = writers.join_next().await {
+        result??;
+    }
+
+    log::info!("Export done");
+
+    Ok(())
+}
+
+async fn export_summaries(db: &PgPool, out: &Path, runs: &[String]) -> anyhow::Result<()> {
+    let rows = query_as::<
+        _,
+        (
+            String,
+            String,
+            i64,
+            i32,
+            i32,
+            f64,
+            f64,
+            i32,
+            i32,
+            i32,
+            i32,
+            i32,
+            String,
+        ),
+    >(
+        r#"select run, metric, count, min_ns, max_ns, mean_ns, stddev_ns,
+            p25_ns, p50_ns, p75_ns, p95_ns, p99_ns, histogram::text
+        from summaries
+        where run = any($1)
+        order by run, metric"#,
+    )
+    .bind(runs)
+    .fetch_all(db)
+    .await?;
+
+    let column_i32 = |f: fn(&_) -> i32| Column::Int32(rows.iter().map(f).collect());
+    let column_f64 = |f: fn(&_) -> f64| Column::Double(rows.iter().map(f).collect());
+
+    let columns = vec![
+        text(rows.iter().map(|r| r.0.clone())),
+        text(rows.iter().map(|r| r.1.clone())),
+        Column::Int64(rows.iter().map(|r| r.2).collect()),
+        column_i32(|r| r.3),
+        column_i32(|r| r.4),
+        column_f64(|r| r.5),
+        column_f64(|r| r.6),
+        column_i32(|r| r.7),
+        column_i32(|r| r.8),
+        column_i32(|r| r.9),
+        column_i32(|r| r.10),
+        column_i32(|r| r.11),
+        text(rows.iter().map(|r| r.12.clone())),
+    ];
+
+    write_file(&out.join("summaries.parquet"), &SUMMARIES, columns)
+}
+
+/// A row of `cycles`, in [`CYCLES`] column order.
+type CycleRow = (
+    i32,
+    i32,
+    i32,
+    i32,
+    Option<i32>,
+    Option<i32>,
+    Option<i32>,
+    Option<i32>,
+);
+
+/// Fetch a run's cycles, whether they were stored as rows or arrays.
+async fn fetch_cycles(db: &PgPool, run: &str) -> anyhow::Result<Vec<Column>> {
+    let rows = query_as::<_, CycleRow>(
+        r#"select cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns,
+            reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index
+        from cycles where run = $1
+        union all
+        select cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns,
+            reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index
+        from cycle_series_rows where run = $1
+        order by cycle"#,
+    )
+    .bind(run)
+    .fetch_all(db)
+    .await?;
+
+    let column = |f: fn(&CycleRow) -> i32| Column::Int32(rows.iter().map(f).collect());
+    let optional =
+        |f: fn(&CycleRow) -> Option<i32>| Column::OptionalInt32(rows.iter().map(f).collect());
+
+    Ok(vec![
+        column(|r| r.0),
+        column(|r| r.1),
+        column(|r| r.2),
+        column(|r| r.3),
+        optional(|r| r.4),
+        optional(|r| r.5),
+        optional(|r| r.6),
+        optional(|r| r.7),
+    ])
+}
+
+/// A row of `frames`, in [`FRAMES`] column order.
+type FrameRow = (
+    i32,
+    i32,
+    String,
+    i64,
+    i64,
+    i32,
+    Option<i32>,
+    Option<i32>,
+    Option<i64>,
+    Option<i64>,
+);
+
+async fn fetch_frames(db: &PgPool, run: &str) -> anyhow::Result<Vec<Column>> {
+    let rows = query_as::<_, FrameRow>(
+        r#"select packet_number, index::int4, command, tx_time_ns, rx_time_ns, delta_time_ns,
+            data_len::int4, pdu_position::int4, hw_tx_time_ns, hw_rx_time_ns
+        from frames where run = $1
+        order by packet_number"#,
+    )
+    .bind(run)
+    .fetch_all(db)
+    .await?;
+
+    Ok(vec![
+        Column::Int32(rows.iter().map(|r| r.0).collect()),
+        Column::Int32(rows.iter().map(|r| r.1).collect()),
+        text(rows.iter().map(|r| r.2.clone())),
+        Column::Int64(rows.iter().map(|r| r.3).collect()),
+        Column::Int64(rows.iter().map(|r| r.4).collect()),
+        Column::Int32(rows.iter().map(|r| r.5).collect()),
+        Column::OptionalInt32(rows.iter().map(|r| r.6).collect()),
+        Column::OptionalInt32(rows.iter().map(|r| r.7).collect()),
+        Column::OptionalInt64(rows.iter().map(|r| r.8).collect()),
+        Column::OptionalInt64(rows.iter().map(|r| r.9).collect()),
+    ])
+}
+
+fn write_partition(
+    out: &Path,
+    table: &str,
+    partition: &str,
+    schema: &Table,
+    columns: Vec<Column>,
+) -> anyhow::Result<()> {
+    let dir: PathBuf = [out, Path::new(table), Path::new(partition)]
+        .iter()
+        .collect();
+
+    fs::create_dir_all(&dir)?;
+
+    write_file(&dir.join("part-0.parquet"), schema, columns)
+}
+
+/// Write columns to a zstd compressed Parquet file, splitting them into row groups. Column chunks
+/// are compressed on a thread per core.
+fn write_file(path: &Path, table: &Table, columns: Vec<Column>) -> anyhow::Result<()> {
+    let schema = Arc::new(parse_message_type(table.schema)?);
+
+    let docs = table
+        .columns
+        .iter()
+        .map(|(name, doc)| (name.to_string(), serde_json::Value::from(*doc)))
+        .collect::<serde_json::Map<_, _>>();
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .set_max_row_group_size(ROW_GROUP_LEN)
+        .set_key_value_metadata(Some(vec![KeyValue::new(
+            String::from("latency_data.columns"),
+            serde_json::Value::Object(docs).to_string(),
+        )]))
+        .build();
+
+    let properties = Arc::new(properties);
+
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties.clone())?;
+
+    let descriptors = writer.schema_descr().columns().to_vec();
+
+    anyhow::ensure!(
+        descriptors.len() == columns.len(),
+        "{} columns for a schema of {} in {}",
+        columns.len(),
+        descriptors.len(),
+        path.display()
+    );
+
+    let rows = columns.first().map_or(0, Column::len);
+
+    // Every column of every row group, in file order
+    let chunks = (0..rows)
+        .step_by(ROW_GROUP_LEN)
+        .flat_map(|start| {
+            let rows = start..(start + ROW_GROUP_LEN).min(rows);
+
+            columns
+                .iter()
+                .zip(descriptors.iter())
+                .map(move |(column, descriptor)| (column, descriptor, rows.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(chunks.len());
+
+    let next = AtomicUsize::new(0);
+
+    let mut encoded = thread::scope(|s| {
+        let handles = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut encoded = Vec::new();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        let Some((column, descriptor, rows)) = chunks.get(i) else {
+                            break;
+                        };
+
+                        let chunk = encode_chunk(
+                            (*descriptor).clone(),
+                            properties.clone(),
+                            column,
+                            rows.clone(),
+                        );
+
+                        encoded.push((i, chunk));
+                    }
+
+                    encoded
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Parquet encoder panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    encoded.sort_unstable_by_key(|(i, _)| *i);
+
+    let mut encoded = encoded.into_iter().map(|(_, chunk)| chunk);
+
+    for _ in (0..rows).step_by(ROW_GROUP_LEN) {
+        let mut row_group = writer.next_row_group()?;
+
+        for chunk in encoded.by_ref().take(columns.len()) {
+            let (data, close) = chunk?;
+
+            row_group.append_column(&data, close)?;
+        }
+
+        row_group.close()?;
+    }
+
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Compress the given rows of a column into a column chunk in memory, ready to be appended to a
+/// row group.
+fn encode_chunk(
+    descriptor: ColumnDescPtr,
+    properties: WriterPropertiesPtr,
+    column: &Column,
+    rows: Range<usize>,
+) -> parquet::errors::Result<(Bytes, ColumnCloseResult)> {
+    let mut buf = TrackedWrite::new(Vec::new());
+
+    let writer = get_column_writer(
+        descriptor,
+        properties,
+        Box::new(SerializedPageWriter::new(&mut buf)),
+    );
+
+    let close = match column {
+        Column::Int32(v) => write_typed::<Int32Type>(writer, &v[rows], None),
+        Column::OptionalInt32(v) => {
+            let (values, levels) = split_nulls(&v[rows]);
+
+            write_typed::<Int32Type>(writer, &values, Some(&levels))
+        }
+        Column::Int64(v) => write_typed::<Int64Type>(writer, &v[rows], None),
+        Column::OptionalInt64(v) => {
+            let (values, levels) = split_nulls(&v[rows]);
+
+            write_typed::<Int64Type>(writer, &values, Some(&levels))
+        }
+        Column::Double(v) => write_typed::<DoubleType>(writer, &v[rows], None),
+        Column::Text(v) => write_typed::<ByteArrayType>(writer, &v[rows], None),
+    }?;
+
+    Ok((Bytes::from(buf.into_inner()?), close))
+}
+
+fn write_typed<T: DataType>(
+    writer: ColumnWriter<'_>,
+    values: &[T::T],
+    levels: Option<&[i16]>,
+) -> parquet::errors::Result<ColumnCloseResult> {
+    let mut writer = get_typed_column_writer::<T>(writer);
+
+    writer.write_batch(values, levels, None)?;
+
+    writer.close()
+}
+
+/// Split nullable values into the non-null values and a definition level for every row, which is
+/// how Parquet stores optional columns.
+fn split_nulls<T: Copy>(rows: &[Option<T>]) -> (Vec<T>, Vec<i16>) {
+    let values = rows.iter().flatten().copied().collect();
+    let levels = rows
+        .iter()
+        .map(|value| i16::from(value.is_some()))
+        .collect();
+
+    (values, levels)
+}
@@ -0,0 +1,183 @@
+//! Pair frames while a run is still being captured, by tailing its dump as it's written.
+//!
+//! Paired PDUs are written to a file next to the dump in Postgres' binary `COPY` format, so ingest
+//! only has to send it to the database instead of parsing the whole capture again after every
+//! scenario has finished. Memory use stays bounded however long the run is.
+
+use crate::{
+    capture,
+    db::BinaryCopy,
+    pairing::{logical_pdus, Paired, Pairer},
+    stats::Histogram,
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How long to wait for more of the dump to be written.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Longest to wait for the capture to create the dump.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size of the buffer rows are encoded into before being written out.
+const BUF_LEN: usize = 64 * 1024;
+
+pub struct Stream {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<io::Result<Paired>>>,
+}
+
+/// Frames of a run paired while it was captured.
+#[derive(Debug, Clone)]
+pub struct StreamedFrames {
+    /// `frames` rows in binary `COPY` format.
+    pub path: PathBuf,
+
+    /// Round trip times of every PDU that received a response.
+    pub summary: Histogram,
+
+    /// Number of PDUs that didn't.
+    pub lost: u64,
+
+    /// Whether any PDUs were paired at all.
+    pub any: bool,
+}
+
+/// Start pairing frames of `run_name` as they're written to `dump`, writing them to `out`.
+pub fn start(run_name: &str, dump: &Path, out: &Path) -> Stream {
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let handle = {
+        let run_name = run_name.to_string();
+        let dump = dump.to_path_buf();
+        let out = out.to_path_buf();
+        let stop = stop.clone();
+
+        thread::spawn(move || pair_dump(&run_name, &dump, &out, &stop))
+    };
+
+    Stream {
+        path: out.to_path_buf(),
+        stop,
+        handle: Some(handle),
+    }
+}
+
+impl Stream {
+    /// Pair whatever is left in the dump and wait for every row to be written. The capture must
+    /// have been stopped first.
+    pub fn stop(mut self) -> io::Result<StreamedFrames> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        let paired = self
+            .handle
+            .take()
+            .expect("Stream thread")
+            .join()
+            .expect("Frame stream panicked")?;
+
+        log::info!(
+            "--> Paired {} PDUs while capturing, {} lost",
+            paired.summary.count() + paired.lost,
+            paired.lost
+        );
+
+        if paired.orphaned > 0 {
+            log::warn!(
+                "--> {} responses didn't match a sent PDU, e.g. duplicates or late responses",
+                paired.orphaned
+            );
+        }
+
+        Ok(StreamedFrames {
+            path: self.path.clone(),
+            summary: paired.summary,
+            lost: paired.lost,
+            any: paired.any,
+        })
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        // Don't leave the thread tailing forever if the run failed
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn pair_dump(run_name: &str, dump: &Path, out: &Path, stop: &AtomicBool) -> io::Result<Paired> {
+    let opened = Instant::now();
+
+    // The capture thread creates the dump, so it might not be there yet
+    let mut tail = loop {
+        match capture::Tail::open(dump) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && opened.elapsed() < OPEN_TIMEOUT => {
+                thread::sleep(POLL)
+            }
+            res => break res?,
+        }
+    };
+
+    let mut out = BufWriter::new(File::create(out)?);
+    let mut buf = BinaryCopy::with_capacity(BUF_LEN);
+    let mut pairer = Pairer::default();
+    let mut started = false;
+
+    loop {
+        // Checked before reading so anything written before the capture stopped is still paired
+        let finished = stop.load(Ordering::Relaxed);
+
+        while let Some(packet) = tail.next_packet()? {
+            let hardware_time_ns = packet.hardware_time_ns.map(|time_ns| time_ns as i64);
+
+            // Skip init until the first sent logical PDU, the same as ingesting a finished dump
+            let time = Duration::from_nanos(packet.time_ns);
+
+            for pdu in logical_pdus(packet.data, time, packet.number) {
+                started |= pdu.from_master;
+
+                if started {
+                    pairer.push(pdu, hardware_time_ns, |row| {
+                        row.write_row(run_name, &mut buf)
+                    });
+                }
+            }
+
+            if buf.as_bytes().len() >= BUF_LEN {
+                out.write_all(buf.as_bytes())?;
+
+                buf.clear();
+            }
+        }
+
+        if finished {
+            break;
+        }
+
+        thread::sleep(POLL);
+    }
+
+    if tail.is_truncated() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is truncated", dump.display()),
+        ));
+    }
+
+    let paired = pairer.finish(|row| row.write_row(run_name, &mut buf));
+
+    out.write_all(&buf.finish())?;
+    out.flush()?;
+
+    Ok(paired)
+}
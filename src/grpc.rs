@@ -0,0 +1,126 @@
+//! gRPC service streaming [`LiveEvent`]s, defined in `proto/live.proto`.
+
+use crate::live::{self, LiveEvent};
+use futures_lite::Stream;
+use proto::{
+    event,
+    live_server::{Live, LiveServer},
+    Cycle, Event, Lagged, ScenarioFinished, ScenarioStarted, SubscribeRequest,
+};
+use std::{net::SocketAddr, pin::Pin, thread};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{transport::Server, Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("latency_data.live.v1");
+}
+
+/// Serve the live event stream on `listen` from a background thread.
+pub fn start(listen: SocketAddr) {
+    live::enable();
+
+    thread::Builder::new()
+        .name("grpc".to_string())
+        .spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("gRPC runtime");
+
+            log::info!("Streaming live events over gRPC on {}", listen);
+
+            let server = Server::builder()
+                .add_service(LiveServer::new(LiveService))
+                .serve(listen);
+
+            if let Err(e) = rt.block_on(server) {
+                log::error!("gRPC server failed: {}", e);
+            }
+        })
+        .expect("Spawn gRPC thread");
+}
+
+struct LiveService;
+
+#[tonic::async_trait]
+impl Live for LiveService {
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let with_cycles = request.into_inner().cycles;
+
+        let rx = live::subscribe().ok_or_else(|| Status::unavailable("Live events disabled"))?;
+
+        // Cycles don't carry their run name, so remember the last one that started
+        let stream = futures_lite::stream::unfold(
+            (rx, String::new()),
+            move |(mut rx, mut run)| async move {
+                let event = loop {
+                    match rx.recv().await {
+                        Ok(LiveEvent::Cycle(_)) if !with_cycles => continue,
+                        Ok(event) => break to_proto(event, &mut run),
+                        Err(RecvError::Lagged(skipped)) => {
+                            break event::Event::Lagged(Lagged { skipped })
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                };
+
+                Some((Ok(Event { event: Some(event) }), (rx, run)))
+            },
+        );
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+fn to_proto(event: LiveEvent, current_run: &mut String) -> event::Event {
+    match event {
+        LiveEvent::ScenarioStarted {
+            scenario,
+            run,
+            hostname,
+            cycle_time_us,
+        } => {
+            current_run.clone_from(&run);
+
+            event::Event::ScenarioStarted(ScenarioStarted {
+                scenario,
+                run,
+                hostname,
+                cycle_time_us,
+            })
+        }
+        LiveEvent::ScenarioFinished {
+            scenario,
+            run,
+            cycles,
+            deadline_misses,
+            cycle_time_delta_p50_ns,
+            cycle_time_delta_p99_ns,
+            cycle_time_delta_max_ns,
+        } => event::Event::ScenarioFinished(ScenarioFinished {
+            scenario,
+            run,
+            cycles,
+            deadline_misses,
+            cycle_time_delta_p50_ns,
+            cycle_time_delta_p99_ns,
+            cycle_time_delta_max_ns,
+        }),
+        LiveEvent::Cycle(cycle) => event::Event::Cycle(Cycle {
+            run: current_run.clone(),
+            cycle: cycle.cycle as u64,
+            processing_time_ns: cycle.processing_time_ns,
+            tick_wait_ns: cycle.tick_wait_ns,
+            cycle_time_delta_ns: cycle.cycle_time_delta_ns,
+            reaction_latency_ns: cycle.reaction_latency_ns,
+            dc_offset_ns: cycle.dc_offset_ns,
+            dc_drift_ns: cycle.dc_drift_ns,
+            group_index: cycle.group_index.map(u32::from),
+        }),
+    }
+}
@@ -0,0 +1,1079 @@
+//! Import run results and packet captures into Postgres.
+//!
+//! Frames are processed in a pipeline so parsing, pairing and writing to the database overlap:
+//!
+//! ```text
+//! pcap parse thread -> pairing/summary thread -> async COPY writer
+//! ```
+//!
+//! Each stage is connected by a bounded channel, so a slow database applies backpressure all the
+//! way back to the parser instead of letting parsed frames pile up in memory.
+
+use crate::{
+    annotate, capture,
+    compress::{self, Dump},
+    cycle_stream,
+    db::{connect_and_init, BinaryCopy},
+    ethercrab_events::EventSite,
+    kernel_probes::KernelFrame,
+    otel::Span,
+    pairing::{logical_pdus, Packet, Pairer, Pdu, COPY_FRAMES},
+    phc::PhcOffset,
+    pushgateway,
+    scenarios::{
+        cycles_path, dump_path, CycleBucket, CycleMetadata, MinCycleTime, RunMetadata, Spike,
+        Transition, NULL_SCENARIO, SOAK_SCENARIO,
+    },
+    stats::{CycleSummary, Histogram},
+    upload::{Artifact, Upload},
+};
+use chrono::{DateTime, Utc};
+use sqlx::{query, types::Json, PgPool, QueryBuilder};
+use std::{
+    fs::File,
+    io::{self, Read},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Size of the buffer rows are batched into before being sent to Postgres with `COPY`.
+const COPY_BUF_LEN: usize = 64 * 1024;
+
+/// Number of streamed cycles read back and copied into the database at once.
+const CYCLE_STREAM_CHUNK_LEN: usize = 100_000;
+
+/// Number of frames sent between pipeline stages in one message.
+const FRAME_BATCH_LEN: usize = 4096;
+
+/// Number of batches each pipeline stage can buffer before the previous stage blocks.
+const PIPELINE_DEPTH: usize = 8;
+
+/// Which dumps to keep on disk once their run has been ingested.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeepDumps {
+    #[default]
+    All,
+    /// Only keep dumps of runs that were aborted, lost frames or dropped frames while capturing.
+    Failed,
+    None,
+}
+
+/// How per-cycle data is laid out in the database.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum CycleStorage {
+    /// One row per cycle in the `cycles` table.
+    #[default]
+    Rows,
+    /// One row per run in `cycle_series`, with each field stored as an array.
+    ///
+    /// Postgres compresses large arrays, so this is much smaller and faster to ingest. Use the
+    /// `cycle_series_rows` view to get one row per cycle back.
+    Arrays,
+}
+
+/// Options for [`ingest`].
+#[derive(Debug, Default)]
+pub struct IngestOptions {
+    /// Empty the database before inserting anything.
+    pub clean: bool,
+
+    pub cycle_storage: CycleStorage,
+
+    /// Prometheus Pushgateway URL to push run summaries to.
+    pub pushgateway: Option<String>,
+
+    /// Where to upload dumps and `artifacts` to, recording their URLs in the database.
+    pub upload: Option<Upload>,
+
+    /// Remove dumps from disk once they've been uploaded.
+    pub delete_uploaded: bool,
+
+    pub keep_dumps: KeepDumps,
+
+    /// Other files from the suite to upload, e.g. CSV exports.
+    pub artifacts: Vec<Artifact>,
+
+    /// Write a copy of each dump with lost, retried and slow frames commented.
+    pub annotate: bool,
+
+    /// Compress each dump, and its annotated copy, with zstd once ingested.
+    pub compress: bool,
+
+    /// Whether runs were captured. If not, there are no dumps and only cycle timings are stored.
+    pub captured: bool,
+}
+
+pub async fn ingest(
+    db: &str,
+    results: Vec<(&str, RunMetadata)>,
+    options: IngestOptions,
+) -> anyhow::Result<()> {
+    let IngestOptions {
+        clean,
+        cycle_storage,
+        pushgateway,
+        upload,
+        delete_uploaded,
+        keep_dumps,
+        artifacts,
+        annotate,
+        compress,
+        captured,
+    } = options;
+
+    let ingest_span = Span::new("ingest");
+
+    let db = connect_and_init(db).await?;
+
+    if clean {
+        // Postgres will cascade this through to the other tables
+        query("truncate runs cascade").execute(&db).await?;
+    }
+
+    for (scenario_name, result) in results {
+        log::info!(
+            "Ingesting data for scenario {}, run {}",
+            scenario_name,
+            result.name
+        );
+
+        let run_span = ingest_span
+            .child("ingest run")
+            .attr("scenario", scenario_name)
+            .attr("run", &result.name);
+
+        // Stored so the dump can be checked later to be the one its frames came from
+        let fingerprint = if captured && dump_path(&result.name).exists() {
+            let _span = run_span.child("fingerprint dump");
+
+            Some(capture::fingerprint(&dump_path(&result.name))?)
+        } else {
+            None
+        };
+
+        // Insert a record into `runs`
+        {
+            let _span = run_span.child("insert runs");
+
+            query(
+                r#"insert into runs
+                (date, scenario, name, slug, hostname, propagation_time_ns, settings, ethercrab_rev, perf_counters, scenario_tags, aborted, capture_packets, capture_drops, interface_drops, dump_bytes, dump_sha256, dump_packets)
+                values
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)"#,
+            )
+            .bind(result.date)
+            .bind(scenario_name)
+            .bind(&result.name)
+            .bind(&result.slug)
+            .bind(&result.hostname)
+            .bind(result.network_propagation_time_ns as i32)
+            .bind(Json(&result.settings))
+            .bind(&result.settings.ethercrab_rev)
+            .bind(
+                (!result.perf_counters.is_empty()).then_some(Json(&result.perf_counters)),
+            )
+            .bind(&result.scenario_tags)
+            .bind(result.aborted)
+            .bind(result.capture_stats.map(|stats| stats.packets as i64))
+            .bind(result.capture_stats.map(|stats| stats.drops as i64))
+            .bind(result.capture_stats.map(|stats| stats.interface_drops as i64))
+            .bind(fingerprint.as_ref().map(|f| f.bytes as i64))
+            .bind(fingerprint.as_ref().map(|f| &f.sha256))
+            .bind(fingerprint.as_ref().map(|f| f.packets as i64))
+            .execute(&db)
+            .await?;
+        }
+
+        {
+            let _span = run_span.child("copy cycles");
+
+            match cycle_storage {
+                // Far too many cycles to store as arrays
+                _ if result.scenario == SOAK_SCENARIO => {
+                    ingest_cycle_stream(&db, &result.name, &cycles_path(&result.name)).await?
+                }
+                CycleStorage::Rows => {
+                    ingest_cycles(&db, &result.name, &result.cycle_metadata).await?
+                }
+                CycleStorage::Arrays => {
+                    ingest_cycle_series(&db, &result.name, &result.cycle_metadata).await?
+                }
+            }
+
+            ingest_cycle_buckets(&db, &result.name, &result.cycle_buckets).await?;
+        }
+
+        log::info!("--> Cycles done");
+
+        // The null scenario sends nothing, so there are no frames to pair
+        let frames = if !captured || result.scenario == NULL_SCENARIO {
+            None
+        } else {
+            let _span = run_span.child("copy frames");
+
+            if let Some(streamed) = result.streamed_frames.as_ref() {
+                anyhow::ensure!(streamed.any, "Empty dump for run {}", result.name);
+
+                copy_streamed_frames(&db, &streamed.path).await?;
+
+                std::fs::remove_file(&streamed.path)?;
+
+                Some((streamed.summary.clone(), streamed.lost))
+            } else {
+                Some(
+                    ingest_frames(
+                        &db,
+                        &result.name,
+                        dump_path(&result.name),
+                        result.settings.hardware_timestamps,
+                    )
+                    .await?,
+                )
+            }
+        };
+
+        log::info!("--> Frames done");
+
+        let mut metrics = result.cycle_summary.metrics();
+
+        if let Some((frame_delta_time, _lost)) = frames.as_ref() {
+            metrics.push(("frame_delta_time", frame_delta_time));
+        }
+
+        {
+            let _span = run_span.child("insert summaries");
+
+            insert_summaries(&db, &result.name, &metrics).await?;
+            insert_ethercrab_events(&db, &result.name, &result.ethercrab_events).await?;
+            insert_phc_offsets(&db, &result.name, &result.phc_offsets).await?;
+            insert_kernel_frames(&db, &result.name, &result.kernel_frames).await?;
+            insert_spikes(&db, &result.name, &result.spikes).await?;
+            insert_transitions(&db, &result.name, &result.transitions).await?;
+
+            if let Some(min_cycle_time) = result.min_cycle_time.as_ref() {
+                insert_min_cycle_time(&db, &result.name, min_cycle_time).await?;
+            }
+        }
+
+        let annotated = if let (true, Some((frame_delta_time, _lost))) = (annotate, frames.as_ref())
+        {
+            let _span = run_span.child("annotate dump");
+
+            // Anything well outside the run's own p99 is worth a look
+            let spike_ns = frame_delta_time.summary().p99_ns.saturating_mul(2);
+
+            annotate::annotate(&db, &result.name, &dump_path(&result.name), spike_ns).await?
+        } else {
+            None
+        };
+
+        if let Some(url) = pushgateway.as_deref() {
+            let lost_frames = frames.as_ref().map_or(0, |(_, lost)| *lost);
+
+            // Monitoring being down shouldn't stop results being stored
+            if let Err(e) = pushgateway::push(url, scenario_name, &result, &metrics, lost_frames) {
+                log::warn!("--> Failed to push metrics to {}: {}", url, e);
+            }
+        }
+
+        // Everything that reads the dump is done with it by now, so uploads are compressed too
+        let (dump, annotated) = if compress && dump_path(&result.name).exists() {
+            let _span = run_span.child("compress dump");
+
+            (
+                compress::compress(&dump_path(&result.name))?,
+                annotated.as_deref().map(compress::compress).transpose()?,
+            )
+        } else {
+            (dump_path(&result.name), annotated)
+        };
+
+        // A dump that failed to upload is kept whatever the options say, so it can be uploaded by
+        // hand later. The rest of the runs are still ingested.
+        let mut upload_failed = false;
+
+        if let Some(upload) = upload.as_ref().filter(|_| dump.exists()) {
+            let _span = run_span.child("upload dump");
+
+            let dumps = std::iter::once(("dump", &dump))
+                .chain(annotated.as_ref().map(|path| ("annotated-dump", path)));
+
+            for (kind, path) in dumps {
+                match upload.put(path) {
+                    Ok(url) => insert_artifact(&db, Some(&result.name), kind, &url).await?,
+                    Err(e) => {
+                        log::warn!("--> Failed to upload {}: {}", path.display(), e);
+
+                        upload_failed = true;
+                    }
+                }
+            }
+
+            if delete_uploaded && !upload_failed {
+                for path in std::iter::once(&dump).chain(annotated.as_ref()) {
+                    std::fs::remove_file(path)?;
+
+                    log::debug!("Removed uploaded dump {}", path.display());
+                }
+            }
+        }
+
+        let failed = result.aborted
+            || frames.as_ref().is_some_and(|(_, lost)| *lost > 0)
+            || result
+                .capture_stats
+                .is_some_and(|stats| stats.drops > 0 || stats.interface_drops > 0);
+
+        let keep = match keep_dumps {
+            KeepDumps::All => true,
+            KeepDumps::Failed => failed,
+            KeepDumps::None => false,
+        };
+
+        if !keep && !upload_failed {
+            for path in std::iter::once(&dump)
+                .chain(annotated.as_ref())
+                .filter(|path| path.exists())
+            {
+                std::fs::remove_file(path)?;
+
+                log::debug!("Removed dump {}", path.display());
+            }
+        }
+    }
+
+    if let Some(upload) = upload.as_ref() {
+        let _span = ingest_span.child("upload artifacts");
+
+        for artifact in artifacts {
+            match upload.put(&artifact.path) {
+                Ok(url) => {
+                    insert_artifact(&db, artifact.run.as_deref(), artifact.kind, &url).await?
+                }
+                Err(e) => log::warn!("Failed to upload {}: {}", artifact.path.display(), e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Store results measured outside this tool so they can be compared with EtherCrab's on the same
+/// hardware.
+///
+/// - pcap/pcapng captures from another EtherCAT master, e.g. TwinCAT, SOEM or IgH, are paired like
+///   EtherCrab captures. Only `frames` and the `frame_delta_time` summary are stored.
+/// - `.csv` files of cycle timings from e.g. an oscilloscope or PLC log are stored in `cycles`
+///   with their summaries. See [`read_cycles_csv`] for the format.
+///
+/// Each file becomes a run named `<master>-<file name>`, or `run_name` if given, with `master` set
+/// to the given label.
+pub async fn import(
+    db: &str,
+    master: &str,
+    hostname: &str,
+    run_name: Option<&str>,
+    files: &[PathBuf],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        run_name.is_none() || files.len() == 1,
+        "A run name can only be given when importing a single file"
+    );
+
+    let db = connect_and_init(db).await?;
+
+    for path in files {
+        let stem = compress::dump_stem(path)
+            .ok_or_else(|| anyhow::anyhow!("No file name in {}", path.display()))?
+            .to_string_lossy();
+
+        let name = run_name.map_or_else(|| format!("{}-{}", master, stem), str::to_string);
+
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+        log::info!(
+            "Importing {} {} {} as {}",
+            master,
+            if is_csv { "cycles" } else { "master capture" },
+            path.display(),
+            name
+        );
+
+        let date: DateTime<Utc> = std::fs::metadata(path)?.modified()?.into();
+
+        query(
+            r#"insert into runs
+            (date, scenario, name, slug, hostname, propagation_time_ns, settings, master)
+            values
+            ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        )
+        .bind(date)
+        .bind("import")
+        .bind(&name)
+        .bind(format!("{}-{}", master, hostname))
+        .bind(hostname)
+        .bind(0i32)
+        .bind(Json(serde_json::json!({ "source": path })))
+        .bind(master)
+        .execute(&db)
+        .await?;
+
+        if is_csv {
+            let cycles = read_cycles_csv(path)?;
+
+            let mut summary = CycleSummary::default();
+
+            for cycle in cycles.iter() {
+                summary.record(cycle);
+            }
+
+            ingest_cycles(&db, &name, &cycles).await?;
+
+            log::info!("--> {} cycles", cycles.len());
+
+            insert_summaries(&db, &name, &summary.metrics()).await?;
+
+            continue;
+        }
+
+        let (frame_delta_time, lost_frames) =
+            ingest_frames(&db, &name, path.clone(), false).await?;
+
+        log::info!(
+            "--> {} frames paired, {} without a response",
+            frame_delta_time.count(),
+            lost_frames
+        );
+
+        insert_summaries(&db, &name, &[("frame_delta_time", &frame_delta_time)]).await?;
+    }
+
+    Ok(())
+}
+
+/// Read externally measured cycle timings from a CSV file with a header row.
+///
+/// Columns are matched by name, in any order, and others are ignored:
+///
+/// - `cycle_time_delta_ns`, or `timestamp_ns` of the same point in each cycle to derive it from
+/// - `processing_time_ns` and `tick_wait_ns`, optional, default 0
+/// - `cycle`, optional, defaults to the row number
+///
+/// Values may have a fractional part, as many instruments export them, and are rounded.
+fn read_cycles_csv(path: &std::path::Path) -> anyhow::Result<Vec<CycleMetadata>> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("{} is empty", path.display()))?
+        .split(',')
+        .map(|column| column.trim().trim_matches('"'))
+        .collect::<Vec<_>>();
+
+    let column = |name: &str| header.iter().position(|column| *column == name);
+
+    let cycle_col = column("cycle");
+    let processing_col = column("processing_time_ns");
+    let tick_wait_col = column("tick_wait_ns");
+    let delta_col = column("cycle_time_delta_ns");
+    let timestamp_col = column("timestamp_ns");
+
+    anyhow::ensure!(
+        delta_col.is_some() || timestamp_col.is_some(),
+        "{} needs a cycle_time_delta_ns or timestamp_ns column",
+        path.display()
+    );
+
+    let mut cycles = Vec::new();
+    let mut prev_timestamp = None;
+
+    for (row, line) in lines.enumerate() {
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+
+        let value = |col: Option<usize>| -> anyhow::Result<Option<f64>> {
+            col.map(|col| {
+                fields
+                    .get(col)
+                    .and_then(|field| field.parse::<f64>().ok())
+                    .ok_or_else(|| anyhow::anyhow!("Bad value on row {}: {:?}", row + 2, line))
+            })
+            .transpose()
+        };
+
+        let cycle_time_delta_ns = match (value(delta_col)?, value(timestamp_col)?) {
+            (Some(delta), _) => delta,
+            (None, Some(timestamp)) => {
+                let prev = prev_timestamp.replace(timestamp);
+
+                // The first timestamp only marks the start of the first cycle
+                match prev {
+                    Some(prev) => timestamp - prev,
+                    None => continue,
+                }
+            }
+            (None, None) => unreachable!(),
+        };
+
+        cycles.push(CycleMetadata {
+            cycle: value(cycle_col)?.map_or(cycles.len(), |cycle| cycle as usize),
+            processing_time_ns: value(processing_col)?.unwrap_or(0.0).round() as u32,
+            tick_wait_ns: value(tick_wait_col)?.unwrap_or(0.0).round() as u32,
+            cycle_time_delta_ns: cycle_time_delta_ns.round() as u32,
+            reaction_latency_ns: None,
+            dc_offset_ns: None,
+            dc_drift_ns: None,
+            group_index: None,
+        });
+    }
+
+    Ok(cycles)
+}
+
+/// Record where an uploaded file can be found.
+async fn insert_artifact(
+    db: &PgPool,
+    run: Option<&str>,
+    kind: &str,
+    url: &str,
+) -> anyhow::Result<()> {
+    log::info!("--> Uploaded {} to {}", kind, url);
+
+    query("insert into artifacts (run, kind, url) values ($1, $2, $3)")
+        .bind(run)
+        .bind(kind)
+        .bind(url)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Store summary statistics for each named metric of a run.
+async fn insert_summaries(
+    db: &PgPool,
+    run: &str,
+    metrics: &[(&str, &Histogram)],
+) -> anyhow::Result<()> {
+    QueryBuilder::new(
+        r#"insert into summaries
+        (run, metric, count, min_ns, max_ns, mean_ns, stddev_ns, p25_ns, p50_ns, p75_ns, p95_ns, p99_ns, histogram) "#,
+    )
+    .push_values(metrics, |mut b, (metric, histogram)| {
+        let summary = histogram.summary();
+
+        b.push_bind(run)
+            .push_bind(*metric)
+            .push_bind(summary.count as i64)
+            .push_bind(summary.min_ns as i32)
+            .push_bind(summary.max_ns as i32)
+            .push_bind(summary.mean_ns)
+            .push_bind(summary.stddev_ns)
+            .push_bind(summary.p25_ns as i32)
+            .push_bind(summary.p50_ns as i32)
+            .push_bind(summary.p75_ns as i32)
+            .push_bind(summary.p95_ns as i32)
+            .push_bind(summary.p99_ns as i32)
+            .push_bind(Json(histogram.buckets()));
+    })
+    .build()
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Store statistics for each EtherCrab log call site recorded during a run.
+async fn insert_ethercrab_events(
+    db: &PgPool,
+    run: &str,
+    events: &[EventSite],
+) -> anyhow::Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    QueryBuilder::new(
+        r#"insert into ethercrab_events
+        (run, module, line, level, example, count, interval_min_ns, interval_max_ns, interval_mean_ns, interval_p50_ns, interval_p99_ns) "#,
+    )
+    .push_values(events, |mut b, event| {
+        let intervals = event.intervals.summary();
+
+        b.push_bind(run)
+            .push_bind(event.module)
+            .push_bind(event.line as i32)
+            .push_bind(event.level.as_str())
+            .push_bind(&event.example)
+            .push_bind(event.count as i64)
+            .push_bind(intervals.min_ns as i32)
+            .push_bind(intervals.max_ns as i32)
+            .push_bind(intervals.mean_ns)
+            .push_bind(intervals.p50_ns as i32)
+            .push_bind(intervals.p99_ns as i32);
+    })
+    .build()
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Store PHC offsets sampled during a run.
+async fn insert_phc_offsets(db: &PgPool, run: &str, offsets: &[PhcOffset]) -> anyhow::Result<()> {
+    // Stay well under Postgres' bind parameter limit for long runs
+    for chunk in offsets.chunks(10_000) {
+        QueryBuilder::new("insert into phc_offsets (run, time, offset_ns) ")
+            .push_values(chunk, |mut b, offset| {
+                b.push_bind(run)
+                    .push_bind(offset.time)
+                    .push_bind(offset.offset_ns);
+            })
+            .build()
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Store kernel network stack times of each LRW frame in a run.
+async fn insert_kernel_frames(
+    db: &PgPool,
+    run: &str,
+    frames: &[KernelFrame],
+) -> anyhow::Result<()> {
+    for chunk in frames.chunks(10_000) {
+        QueryBuilder::new(
+            "insert into kernel_residency (run, seq, index, tx_stack_ns, rx_stack_ns) ",
+        )
+        .push_values(chunk, |mut b, frame| {
+            b.push_bind(run)
+                .push_bind(frame.seq as i32)
+                .push_bind(i16::from(frame.index))
+                .push_bind(frame.tx_stack_ns.map(|ns| ns as i32))
+                .push_bind(frame.rx_stack_ns.map(|ns| ns as i32));
+        })
+        .build()
+        .execute(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Store every deadline miss in a run.
+async fn insert_spikes(db: &PgPool, run: &str, spikes: &[Spike]) -> anyhow::Result<()> {
+    for chunk in spikes.chunks(5_000) {
+        QueryBuilder::new(
+            "insert into spikes (run, cycle, cycle_time_delta_ns, time_ns, preempted_by, sched_trace) ",
+        )
+        .push_values(chunk, |mut b, spike| {
+            b.push_bind(run)
+                .push_bind(spike.cycle as i32)
+                .push_bind(spike.cycle_time_delta_ns as i32)
+                .push_bind(spike.time_ns as i64)
+                .push_bind(&spike.preempted_by)
+                .push_bind(&spike.sched_trace);
+        })
+        .build()
+        .execute(db)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Store every state transition time measured in a run.
+async fn insert_transitions(
+    db: &PgPool,
+    run: &str,
+    transitions: &[Transition],
+) -> anyhow::Result<()> {
+    if transitions.is_empty() {
+        return Ok(());
+    }
+
+    QueryBuilder::new(
+        "insert into transitions (run, group_index, from_state, to_state, duration_ns) ",
+    )
+    .push_values(transitions, |mut b, transition| {
+        b.push_bind(run)
+            .push_bind(transition.group_index.map(i32::from))
+            .push_bind(transition.from)
+            .push_bind(transition.to)
+            .push_bind(transition.duration_ns as i64);
+    })
+    .build()
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Store the result of a minimum cycle time search.
+async fn insert_min_cycle_time(
+    db: &PgPool,
+    run: &str,
+    min_cycle_time: &MinCycleTime,
+) -> anyhow::Result<()> {
+    query(
+        "insert into min_cycle_times (run, cycle_time_us, max_miss_rate, probes) values ($1, $2, $3, $4)",
+    )
+    .bind(run)
+    .bind(min_cycle_time.cycle_time_us as i32)
+    .bind(min_cycle_time.max_miss_rate)
+    .bind(min_cycle_time.probes as i32)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// `COPY` every recorded process cycle of a run into the `cycles` table.
+async fn ingest_cycles(
+    db: &PgPool,
+    run_name: &str,
+    cycles: &[CycleMetadata],
+) -> anyhow::Result<()> {
+    let mut acq = db.acquire().await?;
+
+    let mut copy = acq
+        .copy_in_raw("copy cycles (run, cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns, reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index) from stdin (format binary)")
+        .await?;
+
+    let mut rows = BinaryCopy::with_capacity(COPY_BUF_LEN);
+
+    for cycle in cycles {
+        rows.row(9)
+            .text(run_name)
+            .int4(cycle.cycle as i32)
+            .int4(cycle.processing_time_ns as i32)
+            .int4(cycle.tick_wait_ns as i32)
+            .int4(cycle.cycle_time_delta_ns as i32);
+
+        // Only recorded by some scenarios
+        for value in [
+            cycle.reaction_latency_ns.map(|ns| ns as i32),
+            cycle.dc_offset_ns.map(|ns| ns as i32),
+            cycle.dc_drift_ns,
+            cycle.group_index.map(i32::from),
+        ] {
+            match value {
+                Some(value) => rows.int4(value),
+                None => rows.null(),
+            };
+        }
+
+        if rows.as_bytes().len() >= COPY_BUF_LEN {
+            copy.send(rows.as_bytes()).await?;
+
+            rows.clear();
+        }
+    }
+
+    copy.send(rows.finish()).await?;
+
+    copy.finish().await?;
+
+    Ok(())
+}
+
+/// `COPY` cycles streamed to `path` during a run into the `cycles` table, a chunk at a time.
+async fn ingest_cycle_stream(db: &PgPool, run_name: &str, path: &Path) -> anyhow::Result<()> {
+    let mut reader = cycle_stream::Reader::open(path)?;
+
+    loop {
+        let chunk = reader.next_chunk(CYCLE_STREAM_CHUNK_LEN)?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        ingest_cycles(db, run_name, &chunk).await?;
+    }
+
+    Ok(())
+}
+
+/// Store every recorded process cycle of a run as a single row of arrays in `cycle_series`.
+async fn ingest_cycle_series(
+    db: &PgPool,
+    run_name: &str,
+    cycles: &[CycleMetadata],
+) -> anyhow::Result<()> {
+    if cycles.is_empty() {
+        return Ok(());
+    }
+
+    let column = |f: fn(&CycleMetadata) -> i32| cycles.iter().map(f).collect::<Vec<_>>();
+
+    // Only scenarios that measure them have these, so don't store arrays of nulls
+    let optional_column = |f: fn(&CycleMetadata) -> Option<i32>| {
+        cycles
+            .iter()
+            .any(|c| f(c).is_some())
+            .then(|| cycles.iter().map(f).collect::<Vec<_>>())
+    };
+
+    query(
+        r#"insert into cycle_series
+        (run, cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns, reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index)
+        values
+        ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+    )
+    .bind(run_name)
+    .bind(column(|c| c.cycle as i32))
+    .bind(column(|c| c.processing_time_ns as i32))
+    .bind(column(|c| c.tick_wait_ns as i32))
+    .bind(column(|c| c.cycle_time_delta_ns as i32))
+    .bind(optional_column(|c| c.reaction_latency_ns.map(|ns| ns as i32)))
+    .bind(optional_column(|c| c.dc_offset_ns.map(|ns| ns as i32)))
+    .bind(optional_column(|c| c.dc_drift_ns))
+    .bind(optional_column(|c| c.group_index.map(i32::from)))
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// `COPY` aggregates for sampled cycles into the `cycle_buckets` table.
+async fn ingest_cycle_buckets(
+    db: &PgPool,
+    run_name: &str,
+    buckets: &[CycleBucket],
+) -> anyhow::Result<()> {
+    if buckets.is_empty() {
+        return Ok(());
+    }
+
+    let mut acq = db.acquire().await?;
+
+    let mut copy = acq
+        .copy_in_raw(
+            r#"copy cycle_buckets
+            (run, first_cycle, count,
+            processing_time_min_ns, processing_time_max_ns, processing_time_mean_ns,
+            tick_wait_min_ns, tick_wait_max_ns, tick_wait_mean_ns,
+            cycle_time_delta_min_ns, cycle_time_delta_max_ns, cycle_time_delta_mean_ns,
+            reaction_latency_min_ns, reaction_latency_max_ns, reaction_latency_mean_ns,
+            dc_offset_min_ns, dc_offset_max_ns, dc_offset_mean_ns,
+            dc_drift_min_ns, dc_drift_max_ns, dc_drift_mean_ns,
+            group_index)
+            from stdin (format binary)"#,
+        )
+        .await?;
+
+    let mut rows = BinaryCopy::with_capacity(COPY_BUF_LEN);
+
+    for bucket in buckets {
+        rows.row(22)
+            .text(run_name)
+            .int4(bucket.first_cycle as i32)
+            .int4(bucket.processing_time.count() as i32);
+
+        for aggregate in [
+            &bucket.processing_time,
+            &bucket.tick_wait,
+            &bucket.cycle_time_delta,
+        ] {
+            rows.int4(aggregate.min as i32)
+                .int4(aggregate.max as i32)
+                .float8(aggregate.mean());
+        }
+
+        // Only recorded by some scenarios
+        for aggregate in [
+            &bucket.reaction_latency,
+            &bucket.dc_offset,
+            &bucket.dc_drift,
+        ] {
+            if aggregate.count() > 0 {
+                rows.int4(aggregate.min as i32)
+                    .int4(aggregate.max as i32)
+                    .float8(aggregate.mean());
+            } else {
+                rows.null().null().null();
+            }
+        }
+
+        match bucket.group_index {
+            Some(index) => rows.int4(i32::from(index)),
+            None => rows.null(),
+        };
+
+        if rows.as_bytes().len() >= COPY_BUF_LEN {
+            copy.send(rows.as_bytes()).await?;
+
+            rows.clear();
+        }
+    }
+
+    copy.send(rows.finish()).await?;
+
+    copy.finish().await?;
+
+    Ok(())
+}
+
+/// Parse a run's capture, pair sent PDUs with their responses and `COPY` them into the `frames`
+/// table.
+///
+/// Returns a histogram of round trip times for every PDU that received a response, and the number
+/// of PDUs that didn't.
+async fn ingest_frames(
+    db: &PgPool,
+    run_name: &str,
+    path: PathBuf,
+    hardware_timestamps: bool,
+) -> anyhow::Result<(Histogram, u64)> {
+    // Kept until the parser has finished with it, so a decompressed copy isn't removed early
+    let dump = Dump::open(&path)?;
+
+    let path = dump.path().to_path_buf();
+
+    let (packets_tx, packets_rx) =
+        smol::channel::bounded::<Vec<(Pdu, Option<i64>)>>(PIPELINE_DEPTH);
+    let (rows_tx, rows_rx) = smol::channel::bounded::<Vec<Packet>>(PIPELINE_DEPTH);
+
+    let parser = thread::spawn(move || -> io::Result<()> {
+        let mut tail = capture::Tail::open(&path)?;
+        let mut batch = Vec::with_capacity(FRAME_BATCH_LEN);
+        let mut started = false;
+
+        // The dump is finished, so the first `None` is the end of it
+        while let Some(packet) = tail.next_packet()? {
+            let hardware_time_ns = packet
+                .hardware_time_ns
+                .filter(|_| hardware_timestamps)
+                .map(|time_ns| time_ns as i64);
+
+            let time = Duration::from_nanos(packet.time_ns);
+
+            // Skip all init packets by looking for a first sent logical PDU, which is a good
+            // canary for cyclic data start. Captures from other masters may start mid-cycle, so a
+            // response on its own doesn't count. Mailbox traffic during the cyclic phase uses
+            // FPRD/FPWR so `logical_pdus` drops it and it's never paired with cyclic PDUs.
+            for pdu in logical_pdus(packet.data, time, packet.number) {
+                started |= pdu.from_master;
+
+                if !started {
+                    continue;
+                }
+
+                batch.push((pdu, hardware_time_ns));
+
+                if batch.len() == FRAME_BATCH_LEN {
+                    let full = mem::replace(&mut batch, Vec::with_capacity(FRAME_BATCH_LEN));
+
+                    // Downstream stage has gone away, so there's no point parsing any more.
+                    if packets_tx.send_blocking(full).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if tail.is_truncated() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is truncated", path.display()),
+            ));
+        }
+
+        packets_tx.send_blocking(batch).ok();
+
+        Ok(())
+    });
+
+    let pairer = thread::spawn(move || {
+        let mut pairer = Pairer::default();
+        let mut rows = Vec::with_capacity(FRAME_BATCH_LEN);
+
+        while let Ok(batch) = packets_rx.recv_blocking() {
+            for (packet, hardware_time_ns) in batch {
+                pairer.push(packet, hardware_time_ns, |sent| rows.push(sent));
+
+                if rows.len() >= FRAME_BATCH_LEN {
+                    let full = mem::replace(&mut rows, Vec::with_capacity(FRAME_BATCH_LEN));
+
+                    if rows_tx.send_blocking(full).is_err() {
+                        return pairer.finish(|_| ());
+                    }
+                }
+            }
+        }
+
+        let paired = pairer.finish(|sent| rows.push(sent));
+
+        rows_tx.send_blocking(rows).ok();
+
+        paired
+    });
+
+    let mut acq = db.acquire().await?;
+
+    let mut copy = acq.copy_in_raw(COPY_FRAMES).await?;
+
+    let mut buf = BinaryCopy::with_capacity(COPY_BUF_LEN);
+
+    while let Ok(rows) = rows_rx.recv().await {
+        for row in rows {
+            row.write_row(run_name, &mut buf);
+
+            if buf.as_bytes().len() >= COPY_BUF_LEN {
+                copy.send(buf.as_bytes()).await?;
+
+                buf.clear();
+            }
+        }
+    }
+
+    copy.send(buf.finish()).await?;
+
+    copy.finish().await?;
+
+    parser.join().expect("Capture parser panicked")?;
+
+    let paired = pairer.join().expect("Frame pairing panicked");
+
+    anyhow::ensure!(paired.any, "Empty dump for run {}", run_name);
+
+    if paired.orphaned > 0 {
+        log::warn!(
+            "--> {} responses didn't match a sent PDU, e.g. duplicates or late responses",
+            paired.orphaned
+        );
+    }
+
+    Ok((paired.summary, paired.lost))
+}
+
+/// `COPY` frames already paired while their run was captured, see [`crate::frame_stream`].
+async fn copy_streamed_frames(db: &PgPool, path: &Path) -> anyhow::Result<()> {
+    let mut file = File::open(path)?;
+
+    let mut acq = db.acquire().await?;
+
+    let mut copy = acq.copy_in_raw(COPY_FRAMES).await?;
+
+    let mut buf = vec![0u8; COPY_BUF_LEN];
+
+    loop {
+        let len = file.read(&mut buf)?;
+
+        if len == 0 {
+            break;
+        }
+
+        copy.send(&buf[0..len]).await?;
+    }
+
+    copy.finish().await?;
+
+    Ok(())
+}
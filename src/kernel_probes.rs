@@ -0,0 +1,174 @@
+//! Time EtherCAT frames as they pass through the host's network stack with `bpftrace`.
+//!
+//! For every LRW frame on the interface, this records:
+//!
+//! - TX: from the master's `sendmsg` on its packet socket to the driver's `ndo_start_xmit`
+//! - RX: from the driver handing the frame to the stack (`netif_receive_skb`) to delivery to a
+//!   packet socket (`packet_rcv`)
+//!
+//! Only LRW frames are timed, in send order, to match the `frames` table, so the Nth frame here is
+//! the Nth frame of the run's capture. Stored in `kernel_residency` and joined to `frames` by the
+//! `frame_kernel_residency` view. If the capture dropped frames the two won't line up.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Kernel time spent on one LRW PDU.
+#[derive(Debug, Clone)]
+pub struct KernelFrame {
+    /// Send order of this LRW in the run, from 0.
+    pub seq: usize,
+
+    pub index: u8,
+
+    /// `sendmsg` to driver transmit. `None` if the frame was queued and sent from another context.
+    pub tx_stack_ns: Option<u64>,
+
+    /// Driver receive to packet socket delivery. `None` if no response was seen.
+    pub rx_stack_ns: Option<u64>,
+}
+
+pub struct Probes {
+    bpftrace: Child,
+    reader: JoinHandle<Vec<KernelFrame>>,
+}
+
+impl Probes {
+    /// Attach probes for frames on `interface`, waiting until they're active.
+    pub fn start(interface: &str) -> anyhow::Result<Self> {
+        let mut bpftrace = Command::new("bpftrace")
+            .args(["-e", &script(interface)])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        let stdout = bpftrace.stdout.take().expect("bpftrace stdout");
+
+        let (attached_tx, attached_rx) = mpsc::channel();
+
+        let reader = thread::spawn(move || {
+            let mut lines = BufReader::new(stdout).lines().map_while(Result::ok);
+
+            // bpftrace prints this once every probe is attached
+            for line in lines.by_ref() {
+                if line.starts_with("Attaching") {
+                    break;
+                }
+            }
+
+            attached_tx.send(()).ok();
+
+            pair(lines)
+        });
+
+        if attached_rx.recv_timeout(Duration::from_secs(30)).is_err() {
+            bpftrace.kill().ok();
+
+            anyhow::bail!("bpftrace failed to attach probes");
+        }
+
+        Ok(Self { bpftrace, reader })
+    }
+
+    /// Detach the probes and return every frame timed.
+    pub fn stop(self) -> Vec<KernelFrame> {
+        // SIGINT lets bpftrace run its END block and flush output
+        // SAFETY: Sending a signal to our own child process.
+        unsafe { libc::kill(self.bpftrace.id() as libc::pid_t, libc::SIGINT) };
+
+        let frames = self.reader.join().expect("bpftrace reader panicked");
+
+        let mut bpftrace = self.bpftrace;
+
+        bpftrace.wait().ok();
+
+        frames
+    }
+}
+
+/// Pair `tx` and `rx` lines by PDU index, the same way frames are paired when ingesting captures.
+fn pair(lines: impl Iterator<Item = String>) -> Vec<KernelFrame> {
+    let mut frames = Vec::<KernelFrame>::new();
+    let mut outstanding = HashMap::<u8, usize>::new();
+
+    for line in lines {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        let parse = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok());
+
+        match (fields.first(), parse(1), parse(2), parse(3)) {
+            (Some(&"tx"), Some(socket), Some(driver), Some(index)) => {
+                let seq = frames.len();
+                let index = index as u8;
+
+                outstanding.insert(index, seq);
+
+                frames.push(KernelFrame {
+                    seq,
+                    index,
+                    tx_stack_ns: (socket > 0).then(|| driver.saturating_sub(socket)),
+                    rx_stack_ns: None,
+                });
+            }
+            (Some(&"rx"), Some(driver), Some(socket), Some(index)) => {
+                if let Some(frame) = outstanding
+                    .remove(&(index as u8))
+                    .and_then(|seq| frames.get_mut(seq))
+                {
+                    frame.rx_stack_ns = Some(socket.saturating_sub(driver));
+                }
+            }
+            _ => log::debug!("Ignoring bpftrace output {:?}", line),
+        }
+    }
+
+    frames
+}
+
+fn script(interface: &str) -> String {
+    // EtherCAT frame: 14 byte Ethernet header, 2 byte EtherCAT header, then the first PDU's
+    // command (LRW = 0x0c) and index. 0x88a4 reads as 0xa488 little endian.
+    format!(
+        r#"
+kprobe:packet_sendmsg {{ @send[tid] = nsecs; }}
+
+tracepoint:net:net_dev_start_xmit /str(args->name) == "{iface}"/ {{
+    $skb = (struct sk_buff *)args->skbaddr;
+    $mac = $skb->head + $skb->mac_header;
+
+    if (*(uint16 *)($mac + 12) == 0xa488 && *(uint8 *)($mac + 16) == 0x0c) {{
+        printf("tx %llu %llu %u\n", @send[tid], nsecs, *(uint8 *)($mac + 17));
+    }}
+
+    delete(@send[tid]);
+}}
+
+tracepoint:net:netif_receive_skb /str(args->name) == "{iface}"/ {{
+    $skb = (struct sk_buff *)args->skbaddr;
+    $mac = $skb->head + $skb->mac_header;
+
+    if (*(uint16 *)($mac + 12) == 0xa488 && *(uint8 *)($mac + 16) == 0x0c) {{
+        @rx[args->skbaddr] = nsecs;
+    }}
+}}
+
+kprobe:packet_rcv /@rx[arg0]/ {{
+    $skb = (struct sk_buff *)arg0;
+    $mac = $skb->head + $skb->mac_header;
+
+    printf("rx %llu %llu %u\n", @rx[arg0], nsecs, *(uint8 *)($mac + 17));
+
+    delete(@rx[arg0]);
+}}
+
+END {{ clear(@send); clear(@rx); }}
+"#,
+        iface = interface
+    )
+}
@@ -0,0 +1,60 @@
+//! Events published while the suite is running, for live subscribers like the gRPC service.
+//!
+//! Publishing never blocks. If a subscriber falls behind it misses events instead of slowing
+//! scenarios down. Nothing is published unless [`enable`] has been called.
+
+use crate::scenarios::CycleMetadata;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+/// Number of events buffered for each subscriber before it starts missing them.
+const CAPACITY: usize = 64 * 1024;
+
+static BUS: OnceLock<broadcast::Sender<LiveEvent>> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    ScenarioStarted {
+        scenario: String,
+        run: String,
+        hostname: String,
+        cycle_time_us: u32,
+    },
+    ScenarioFinished {
+        scenario: String,
+        run: String,
+        cycles: u64,
+        deadline_misses: u64,
+        cycle_time_delta_p50_ns: u32,
+        cycle_time_delta_p99_ns: u32,
+        cycle_time_delta_max_ns: u32,
+    },
+    /// A process cycle of the currently running scenario.
+    Cycle(CycleMetadata),
+}
+
+/// Start publishing events.
+pub fn enable() {
+    BUS.get_or_init(|| broadcast::channel(CAPACITY).0);
+}
+
+/// Receive every event published from now on, or `None` if publishing isn't enabled.
+pub fn subscribe() -> Option<broadcast::Receiver<LiveEvent>> {
+    BUS.get().map(|bus| bus.subscribe())
+}
+
+pub fn publish(event: LiveEvent) {
+    if let Some(bus) = BUS.get() {
+        // No subscribers is fine
+        bus.send(event).ok();
+    }
+}
+
+/// Publish a process cycle. Cheap enough to call from a scenario's cycle loop.
+pub fn publish_cycle(cycle: &CycleMetadata) {
+    if let Some(bus) = BUS.get() {
+        if bus.receiver_count() > 0 {
+            bus.send(LiveEvent::Cycle(cycle.clone())).ok();
+        }
+    }
+}
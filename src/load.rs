@@ -0,0 +1,168 @@
+//! Flood a NIC with non-EtherCAT traffic while a scenario runs, to see how latency degrades when
+//! the network stack, driver and NIC queues are shared with other traffic.
+//!
+//! Frames are broadcast from a raw socket on a normal priority thread at a fixed bit rate. Sending
+//! on the EtherCAT NIC itself puts the frames on the EtherCAT network, where devices pass them
+//! along like any other frame. A sibling NIC on the same PCIe bus or driver only loads the host
+//! side.
+
+use std::{
+    ffi::CString,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// IEEE 802 local experimental ethertype, so nothing on the network tries to handle the frames.
+const LOAD_ETHERTYPE: u16 = 0x88b5;
+
+/// Full size Ethernet II frames.
+const FRAME_LEN: usize = 1514;
+
+/// Frames are sent in bursts this far apart.
+const BURST_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Background traffic to send during each run.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct NetworkLoad {
+    /// NIC to send the traffic from.
+    pub interface: String,
+
+    /// Target rate in megabits per second.
+    pub mbit_per_sec: u32,
+}
+
+pub struct Generator {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<u64>,
+    start: Instant,
+}
+
+impl Generator {
+    /// Start sending traffic as configured by `load`.
+    pub fn start(load: &NetworkLoad) -> io::Result<Self> {
+        let socket = open(&load.interface)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Whole frames per burst, so low rates are rounded down
+        let bytes_per_burst = u64::from(load.mbit_per_sec) * 1_000_000 / 8
+            * BURST_INTERVAL.as_micros() as u64
+            / 1_000_000;
+        let frames_per_burst = bytes_per_burst / FRAME_LEN as u64;
+
+        let handle = {
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                let mut frame = [0u8; FRAME_LEN];
+
+                // Broadcast destination, locally administered source
+                frame[0..6].fill(0xff);
+                frame[6..12].copy_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+                frame[12..14].copy_from_slice(&LOAD_ETHERTYPE.to_be_bytes());
+
+                let mut sent = 0u64;
+                let mut next_burst = Instant::now();
+
+                while !stop.load(Ordering::Relaxed) {
+                    for _ in 0..frames_per_burst {
+                        let res = unsafe {
+                            libc::send(
+                                socket.as_raw_fd(),
+                                frame.as_ptr() as *const libc::c_void,
+                                frame.len(),
+                                0,
+                            )
+                        };
+
+                        if res == -1 {
+                            log::warn!("Failed to send load frame: {}", io::Error::last_os_error());
+
+                            break;
+                        }
+
+                        sent += 1;
+                    }
+
+                    // Absolute deadlines so slow bursts don't lower the rate
+                    next_burst += BURST_INTERVAL;
+
+                    thread::sleep(next_burst.saturating_duration_since(Instant::now()));
+                }
+
+                sent
+            })
+        };
+
+        Ok(Self {
+            stop,
+            handle,
+            start: Instant::now(),
+        })
+    }
+
+    /// Stop sending and log the rate that was actually achieved.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        let elapsed = self.start.elapsed();
+
+        let sent = self.handle.join().expect("Load generator panicked");
+
+        log::info!(
+            "--> Sent {} load frames, {:.1} Mbit/s",
+            sent,
+            (sent * FRAME_LEN as u64 * 8) as f64 / elapsed.as_secs_f64() / 1_000_000.0
+        );
+    }
+}
+
+/// Open a send-only raw socket on `interface`.
+fn open(interface: &str) -> io::Result<OwnedFd> {
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Bad interface name"))?;
+
+    let ifindex = match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => return Err(io::Error::last_os_error()),
+        ifindex => ifindex,
+    };
+
+    // Protocol 0 so no frames are received on it
+    let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let sockaddr = libc::sockaddr_ll {
+        sll_family: libc::AF_PACKET as u16,
+        sll_protocol: 0,
+        sll_ifindex: ifindex as i32,
+        sll_hatype: 1,
+        sll_pkttype: 0,
+        sll_halen: 6,
+        sll_addr: [0; 8],
+    };
+
+    let res = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &sockaddr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    };
+
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(fd)
+}
@@ -0,0 +1,45 @@
+//! Lock the process's memory for the duration of a run, so page faults can't add to cycle latency.
+//!
+//! `mlockall(MCL_CURRENT | MCL_FUTURE)` faults in and locks everything mapped now and anything
+//! mapped later, including new thread stacks. Stack pages are prefaulted explicitly by each scenario
+//! thread as well in case the lock limit stops the kernel from populating them. Needs root or a
+//! large enough `RLIMIT_MEMLOCK`.
+
+use std::io;
+
+/// How much of each thread's stack to fault in before it starts cycling. Well under the 2MiB
+/// default stack size.
+const PREFAULT_STACK_BYTES: usize = 256 * 1024;
+
+/// All process memory is locked while this is alive. It's unlocked again when dropped.
+pub struct MemoryLock;
+
+impl MemoryLock {
+    pub fn enable() -> io::Result<Self> {
+        let res = unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) };
+
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self)
+    }
+}
+
+impl Drop for MemoryLock {
+    fn drop(&mut self) {
+        if unsafe { libc::munlockall() } != 0 {
+            log::warn!("Failed to unlock memory: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+/// Touch the next [`PREFAULT_STACK_BYTES`] of the current thread's stack so later deeper calls
+/// don't page fault.
+#[inline(never)]
+pub fn prefault_stack() {
+    let mut stack = [0u8; PREFAULT_STACK_BYTES];
+
+    // Stop the writes being optimised away
+    std::hint::black_box(&mut stack);
+}
@@ -0,0 +1,191 @@
+//! Publish rolling latency statistics and scenario status to an MQTT broker.
+//!
+//! Topics, under the configured prefix:
+//!
+//! - `<prefix>/status` (retained) - `{"state": "running" | "finished", "scenario": ..., "run": ...}`
+//! - `<prefix>/stats` - statistics of every cycle since the last message, sent every `interval`
+
+use crate::{
+    live::{self, LiveEvent},
+    stats::Histogram,
+};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde_json::json;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Connect to the broker at `broker` (`host:port`) and publish live events from a background
+/// thread.
+pub fn start(broker: &str, prefix: &str, interval: Duration) {
+    let (host, port) = broker
+        .rsplit_once(':')
+        .map(|(host, port)| (host, port.parse().expect("Invalid MQTT broker port")))
+        .unwrap_or((broker, 1883));
+
+    let mut options = MqttOptions::new(format!("latency-data-{}", std::process::id()), host, port);
+
+    options.set_keep_alive(Duration::from_secs(30));
+
+    let (client, mut connection) = Client::new(options, 64);
+
+    live::enable();
+
+    let mut rx = live::subscribe().expect("Live events enabled");
+
+    thread::Builder::new()
+        .name("mqtt-conn".to_string())
+        .spawn(move || {
+            for notification in connection.iter() {
+                if let Err(e) = notification {
+                    log::warn!("MQTT connection error: {}", e);
+
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        })
+        .expect("Spawn MQTT connection thread");
+
+    let prefix = prefix.trim_end_matches('/').to_string();
+
+    thread::Builder::new()
+        .name("mqtt".to_string())
+        .spawn(move || {
+            let mut publisher = Publisher {
+                client,
+                prefix,
+                run: String::new(),
+                cycle_time_ns: 0,
+                window: Window::default(),
+                last_publish: Instant::now(),
+            };
+
+            loop {
+                match rx.blocking_recv() {
+                    Ok(event) => publisher.handle(event, interval),
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::debug!("MQTT publisher skipped {} events", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+        .expect("Spawn MQTT thread");
+
+    log::info!("Publishing live stats to MQTT broker {}", broker);
+}
+
+struct Publisher {
+    client: Client,
+    prefix: String,
+    run: String,
+    cycle_time_ns: u32,
+    window: Window,
+    last_publish: Instant,
+}
+
+/// Statistics of cycles seen since the last `stats` message.
+#[derive(Default)]
+struct Window {
+    processing_time: Histogram,
+    cycle_time_delta: Histogram,
+    deadline_misses: u64,
+}
+
+impl Publisher {
+    fn handle(&mut self, event: LiveEvent, interval: Duration) {
+        match event {
+            LiveEvent::ScenarioStarted {
+                scenario,
+                run,
+                cycle_time_us,
+                ..
+            } => {
+                self.run.clone_from(&run);
+                self.cycle_time_ns = cycle_time_us.saturating_mul(1000);
+                self.window = Window::default();
+                self.last_publish = Instant::now();
+
+                self.publish(
+                    "status",
+                    true,
+                    json!({ "state": "running", "scenario": scenario, "run": run }),
+                );
+            }
+            LiveEvent::ScenarioFinished {
+                scenario,
+                run,
+                cycles,
+                deadline_misses,
+                ..
+            } => {
+                self.publish_stats();
+
+                self.publish(
+                    "status",
+                    true,
+                    json!({
+                        "state": "finished",
+                        "scenario": scenario,
+                        "run": run,
+                        "cycles": cycles,
+                        "deadline_misses": deadline_misses,
+                    }),
+                );
+            }
+            LiveEvent::Cycle(cycle) => {
+                self.window.processing_time.record(cycle.processing_time_ns);
+                self.window
+                    .cycle_time_delta
+                    .record(cycle.cycle_time_delta_ns);
+
+                if cycle.is_deadline_miss(self.cycle_time_ns) {
+                    self.window.deadline_misses += 1;
+                }
+
+                if self.last_publish.elapsed() >= interval {
+                    self.publish_stats();
+                }
+            }
+        }
+    }
+
+    fn publish_stats(&mut self) {
+        let window = std::mem::take(&mut self.window);
+
+        self.last_publish = Instant::now();
+
+        if window.cycle_time_delta.count() == 0 {
+            return;
+        }
+
+        let processing_time = window.processing_time.summary();
+        let cycle_time_delta = window.cycle_time_delta.summary();
+
+        self.publish(
+            "stats",
+            false,
+            json!({
+                "run": self.run,
+                "cycles": cycle_time_delta.count,
+                "deadline_misses": window.deadline_misses,
+                "processing_time": processing_time,
+                "cycle_time_delta": cycle_time_delta,
+            }),
+        );
+    }
+
+    fn publish(&mut self, topic: &str, retain: bool, payload: serde_json::Value) {
+        let topic = format!("{}/{}", self.prefix, topic);
+
+        // Never block on a slow broker, just drop the message
+        if let Err(e) =
+            self.client
+                .try_publish(&topic, QoS::AtMostOnce, retain, payload.to_string())
+        {
+            log::debug!("Dropped MQTT message to {}: {}", topic, e);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Post a suite result to a chat or alerting webhook with `curl`.
+
+use crate::baseline::Regression;
+use serde_json::json;
+use std::{
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+/// Send a summary of a finished suite to `url`.
+///
+/// The body has a `text` field so Slack, Mattermost and similar incoming webhooks display it
+/// directly, alongside structured fields for anything else.
+pub fn send(
+    url: &str,
+    hostname: &str,
+    runs: usize,
+    regressions: &[Regression],
+) -> anyhow::Result<()> {
+    let text = if regressions.is_empty() {
+        format!(
+            "latency-data on {}: {} runs, no regressions",
+            hostname, runs
+        )
+    } else {
+        let lines = regressions
+            .iter()
+            .map(|r| {
+                format!(
+                    "- {} {} {}: {} ns, baseline {} ns",
+                    r.slug, r.metric, r.stat, r.actual, r.expected
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "latency-data on {}: {} runs, {} regressions\n{}",
+            hostname,
+            runs,
+            regressions.len(),
+            lines
+        )
+    };
+
+    let body = json!({
+        "text": text,
+        "hostname": hostname,
+        "runs": runs,
+        "regressions": regressions
+            .iter()
+            .map(|r| json!({
+                "slug": r.slug,
+                "metric": r.metric,
+                "stat": r.stat,
+                "expected_ns": r.expected,
+                "actual_ns": r.actual,
+                "tolerance": r.tolerance,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    let mut curl = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--output",
+            "/dev/null",
+        ])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--data-binary", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    curl.stdin
+        .take()
+        .expect("curl stdin")
+        .write_all(body.to_string().as_bytes())?;
+
+    let status = curl.wait()?;
+
+    anyhow::ensure!(status.success(), "curl exited with {}", status);
+
+    Ok(())
+}
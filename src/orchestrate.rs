@@ -0,0 +1,168 @@
+//! Run the suite on several remote rigs over SSH.
+//!
+//! For each host, the binary is copied over with `scp` and run with `ssh`, ingesting straight into
+//! the central database. Output is saved locally per host, along with the host's dumps if asked
+//! for. Hosts run in parallel as they're separate machines.
+//!
+//! ```toml
+//! # Defaults to this binary
+//! binary = "target/release/latency-data"
+//!
+//! [[host]]
+//! address = "root@rig1"
+//! interface = "enp2s0"
+//! args = ["--repeat", "3", "--cycle-times", "1000"]
+//! collect_dumps = true
+//! ```
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+#[derive(serde::Deserialize, Debug)]
+struct Hosts {
+    /// Binary to deploy. Must be built for the remote hosts' architecture.
+    binary: Option<PathBuf>,
+
+    #[serde(rename = "host")]
+    hosts: Vec<Host>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Host {
+    /// SSH destination, e.g. `root@rig1`.
+    address: String,
+
+    /// EtherCAT network interface on the host.
+    interface: String,
+
+    /// Extra arguments for this host's suite.
+    #[serde(default)]
+    args: Vec<String>,
+
+    /// Remote working directory.
+    #[serde(default = "default_dir")]
+    dir: String,
+
+    /// Copy the host's `dumps/` folder back once the suite is done.
+    #[serde(default)]
+    collect_dumps: bool,
+}
+
+fn default_dir() -> String {
+    String::from("latency-data")
+}
+
+/// Run the suite on every host in the `hosts` TOML file, ingesting into `db`. Logs and dumps are
+/// saved to `out/<address>/`.
+pub fn orchestrate(hosts: &Path, db: &str, out: &Path) -> anyhow::Result<()> {
+    let Hosts { binary, hosts } = toml::from_str(&fs::read_to_string(hosts)?)?;
+
+    let binary = binary.map_or_else(std::env::current_exe, Ok)?;
+
+    log::info!("Deploying {} to {} hosts", binary.display(), hosts.len());
+
+    let failed = thread::scope(|s| {
+        let handles = hosts
+            .iter()
+            .map(|host| {
+                let binary = &binary;
+
+                s.spawn(move || {
+                    let result = run_host(host, binary, db, &out.join(&host.address));
+
+                    if let Err(e) = result.as_ref() {
+                        log::error!("Host {} failed: {}", host.address, e);
+                    }
+
+                    result.is_err()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Host thread panicked"))
+            .filter(|failed| *failed)
+            .count()
+    });
+
+    anyhow::ensure!(failed == 0, "{} of {} hosts failed", failed, hosts.len());
+
+    Ok(())
+}
+
+fn run_host(host: &Host, binary: &Path, db: &str, out: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(out)?;
+
+    let Host {
+        address,
+        interface,
+        args,
+        dir,
+        collect_dumps,
+    } = host;
+
+    log::info!("{}: deploying to {}", address, dir);
+
+    run(Command::new("ssh")
+        .arg(address)
+        .arg(format!("mkdir -p {}", quote(dir))))?;
+
+    run(Command::new("scp")
+        .arg("-q")
+        .arg(binary)
+        .arg(format!("{}:{}/latency-data", address, dir)))?;
+
+    let suite = ["--interface", interface.as_str(), "--db", db]
+        .into_iter()
+        .chain(args.iter().map(String::as_str))
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    log::info!("{}: running suite, output in {}", address, out.display());
+
+    let log = File::create(out.join("suite.log"))?;
+
+    run(Command::new("ssh")
+        .arg(address)
+        .arg(format!(
+            "cd {} && sudo ./latency-data {}",
+            quote(dir),
+            suite
+        ))
+        .stdout(log.try_clone()?)
+        .stderr(log))?;
+
+    if *collect_dumps {
+        log::info!("{}: collecting dumps", address);
+
+        run(Command::new("scp")
+            .args(["-q", "-r"])
+            .arg(format!("{}:{}/dumps", address, dir))
+            .arg(out))?;
+    }
+
+    log::info!("{}: done", address);
+
+    Ok(())
+}
+
+fn run(cmd: &mut Command) -> anyhow::Result<()> {
+    log::debug!("Running {:?}", cmd);
+
+    let status = cmd.stdin(Stdio::null()).status()?;
+
+    anyhow::ensure!(status.success(), "{:?} exited with {}", cmd, status);
+
+    Ok(())
+}
+
+/// Quote an argument for the remote shell.
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
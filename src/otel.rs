@@ -0,0 +1,273 @@
+//! Minimal OpenTelemetry export over OTLP/HTTP JSON.
+//!
+//! Spans and gauges are buffered in memory and sent to the collector with `curl` between runs, so
+//! nothing is exported while a scenario's cycle loop is executing. Everything here is a no-op
+//! unless [`init`] has been called.
+
+use crate::scenarios::RunMetadata;
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    io::Write as _,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+struct Exporter {
+    /// Collector base URL, e.g. `http://localhost:4318`.
+    endpoint: String,
+
+    /// One trace covers the whole suite.
+    trace_id: String,
+
+    /// Span every other span without an explicit parent is attached to.
+    root: Span,
+
+    next_span_id: AtomicU64,
+
+    /// Finished spans waiting to be exported.
+    spans: Mutex<Vec<Value>>,
+
+    /// Gauge data points waiting to be exported, keyed by metric name.
+    gauges: Mutex<BTreeMap<String, Vec<Value>>>,
+}
+
+/// Start exporting traces and metrics to the OTLP/HTTP collector at `endpoint`.
+pub fn init(endpoint: &str) {
+    let seed = RandomState::new().build_hasher().finish();
+    let trace_id = format!(
+        "{:016x}{:016x}",
+        seed,
+        RandomState::new().build_hasher().finish()
+    );
+
+    let exporter = Exporter {
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        trace_id,
+        root: Span {
+            id: format!("{:016x}", seed),
+            parent: None,
+            name: String::from("suite"),
+            start: now_ns(),
+            attributes: Vec::new(),
+        },
+        next_span_id: AtomicU64::new(seed.wrapping_add(1)),
+        spans: Mutex::new(Vec::new()),
+        gauges: Mutex::new(BTreeMap::new()),
+    };
+
+    if EXPORTER.set(exporter).is_err() {
+        log::warn!("OpenTelemetry export already initialised");
+    }
+}
+
+/// A span that is recorded when dropped.
+pub struct Span {
+    id: String,
+    parent: Option<String>,
+    name: String,
+    start: u64,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl Span {
+    /// Start a span under the suite's root span.
+    pub fn new(name: &str) -> Self {
+        Self::with_parent(name, EXPORTER.get().map(|e| e.root.id.clone()))
+    }
+
+    /// Start a span nested inside this one.
+    pub fn child(&self, name: &str) -> Self {
+        Self::with_parent(name, Some(self.id.clone()))
+    }
+
+    /// Add an attribute to this span.
+    pub fn attr(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.attributes.push((key, value.to_string()));
+
+        self
+    }
+
+    fn with_parent(name: &str, parent: Option<String>) -> Self {
+        let id = EXPORTER.get().map_or(0, |e| {
+            e.next_span_id
+                .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+        });
+
+        Self {
+            id: format!("{:016x}", id),
+            parent,
+            name: name.to_string(),
+            start: now_ns(),
+            attributes: Vec::new(),
+        }
+    }
+
+    fn to_json(&self, trace_id: &str) -> Value {
+        json!({
+            "traceId": trace_id,
+            "spanId": self.id,
+            "parentSpanId": self.parent.clone().unwrap_or_default(),
+            "name": self.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": self.start.to_string(),
+            "endTimeUnixNano": now_ns().to_string(),
+            "attributes": attributes(self.attributes.iter().map(|(k, v)| (*k, v.as_str()))),
+        })
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(exporter) = EXPORTER.get() {
+            let span = self.to_json(&exporter.trace_id);
+
+            exporter.spans.lock().expect("Span lock").push(span);
+        }
+    }
+}
+
+/// Record p50/p99/max gauges of each cycle metric for a finished run.
+pub fn record_run(result: &RunMetadata) {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+
+    let time = now_ns().to_string();
+    let mut gauges = exporter.gauges.lock().expect("Gauge lock");
+
+    for (metric, histogram) in result.cycle_summary.metrics() {
+        let summary = histogram.summary();
+
+        for (stat, value) in [
+            ("p50", summary.p50_ns),
+            ("p99", summary.p99_ns),
+            ("max", summary.max_ns),
+        ] {
+            gauges
+                .entry(format!("latency_data.cycle.{}.{}", metric, stat))
+                .or_default()
+                .push(json!({
+                    "asInt": value.to_string(),
+                    "timeUnixNano": time,
+                    "attributes": attributes([
+                        ("scenario", result.scenario.as_str()),
+                        ("hostname", result.hostname.as_str()),
+                        ("slug", result.slug.as_str()),
+                    ]),
+                }));
+        }
+    }
+}
+
+/// Send all buffered spans and gauges to the collector.
+pub fn flush() -> anyhow::Result<()> {
+    let Some(exporter) = EXPORTER.get() else {
+        return Ok(());
+    };
+
+    let spans = std::mem::take(&mut *exporter.spans.lock().expect("Span lock"));
+    let gauges = std::mem::take(&mut *exporter.gauges.lock().expect("Gauge lock"));
+
+    if !spans.is_empty() {
+        post(
+            &exporter.endpoint,
+            "traces",
+            json!({
+                "resourceSpans": [{
+                    "resource": resource(),
+                    "scopeSpans": [{ "scope": { "name": "latency-data" }, "spans": spans }],
+                }],
+            }),
+        )?;
+    }
+
+    if !gauges.is_empty() {
+        let metrics = gauges
+            .into_iter()
+            .map(|(name, points)| {
+                json!({ "name": name, "unit": "ns", "gauge": { "dataPoints": points } })
+            })
+            .collect::<Vec<_>>();
+
+        post(
+            &exporter.endpoint,
+            "metrics",
+            json!({
+                "resourceMetrics": [{
+                    "resource": resource(),
+                    "scopeMetrics": [{ "scope": { "name": "latency-data" }, "metrics": metrics }],
+                }],
+            }),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// End the suite's root span and send everything that's left.
+pub fn finish() -> anyhow::Result<()> {
+    if let Some(exporter) = EXPORTER.get() {
+        let root = exporter.root.to_json(&exporter.trace_id);
+
+        exporter.spans.lock().expect("Span lock").push(root);
+    }
+
+    flush()
+}
+
+fn post(endpoint: &str, signal: &str, body: Value) -> anyhow::Result<()> {
+    let url = format!("{}/v1/{}", endpoint, signal);
+
+    log::debug!("Exporting {} to {}", signal, url);
+
+    let mut curl = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--output",
+            "/dev/null",
+        ])
+        .args(["-H", "Content-Type: application/json"])
+        .args(["--data-binary", "@-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    curl.stdin
+        .take()
+        .expect("curl stdin")
+        .write_all(body.to_string().as_bytes())?;
+
+    let status = curl.wait()?;
+
+    anyhow::ensure!(status.success(), "curl exited with {}", status);
+
+    Ok(())
+}
+
+fn resource() -> Value {
+    json!({ "attributes": attributes([("service.name", "latency-data")]) })
+}
+
+fn attributes<'a>(attributes: impl IntoIterator<Item = (&'a str, &'a str)>) -> Value {
+    attributes
+        .into_iter()
+        .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+        .collect()
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_nanos() as u64)
+}
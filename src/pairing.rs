@@ -0,0 +1,542 @@
+//! Pair sent PDUs with their responses, in send order.
+//!
+//! Used by ingest on a finished dump, and by [`frame_stream`](crate::frame_stream) on a dump that's
+//! still being written. Memory use is bounded by [`MAX_PENDING`] however long the capture is.
+
+use crate::{
+    capture::{Tail, ETHERCAT_ETHERTYPE},
+    db::BinaryCopy,
+    stats::Histogram,
+};
+use ethercrab::{Command, Reads, Writes};
+use std::{
+    collections::HashMap,
+    collections::VecDeque,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Maximum number of sent PDUs waiting for a response before the oldest is written out as lost.
+const MAX_PENDING: usize = 1024;
+
+/// Header line of CSV rows written by [`Packet::write_csv`].
+pub const FRAMES_CSV_HEADER: &str = "packet_number,index,command,tx_time_ns,rx_time_ns,delta_time_ns,data_len,pdu_position,hw_tx_time_ns,hw_rx_time_ns";
+
+/// `COPY` statement for rows written by [`Packet::write_row`].
+pub const COPY_FRAMES: &str = "copy frames (run, packet_number, index, command, tx_time_ns, rx_time_ns, delta_time_ns, data_len, pdu_position, hw_tx_time_ns, hw_rx_time_ns) from stdin (format binary)";
+
+/// Whether `command` exchanges process data. Each PDU is paired with its response by index, so
+/// LRD and LWR are handled the same as LRW.
+pub fn is_logical(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Write(Writes::Lrw { .. } | Writes::Lwr { .. }) | Command::Read(Reads::Lrd { .. })
+    )
+}
+
+/// A PDU to pair. Only the length of its payload is kept, as that's all that's stored.
+#[derive(Debug, Clone, Copy)]
+pub struct Pdu {
+    pub index: u8,
+    pub command: Command,
+    pub data_len: usize,
+    /// Capture timestamp of the frame the PDU was in.
+    pub time: Duration,
+    pub from_master: bool,
+    /// Wireshark packet number of the frame the PDU was in, starting from 1.
+    pub packet_number: usize,
+}
+
+/// Logical PDUs in an Ethernet frame captured at `time`, without copying their payloads. Other
+/// PDUs and anything that isn't EtherCAT are skipped, and a PDU cut short ends the frame.
+///
+/// Devices set the locally administered bit of the source address on frames they pass on, so a
+/// frame without it was sent by the master. This works for captures from other masters too, unlike
+/// matching EtherCrab's [`MASTER_MAC`](crate::capture::MASTER_MAC).
+pub fn logical_pdus(
+    frame: &[u8],
+    time: Duration,
+    packet_number: usize,
+) -> impl Iterator<Item = Pdu> + '_ {
+    let from_master = frame.get(6).is_some_and(|first| first & 0x02 == 0);
+
+    // Ethernet header, then the 2 byte EtherCAT header
+    let mut rest = if frame.get(12..14) == Some(&ETHERCAT_ETHERTYPE.to_be_bytes()) {
+        frame.get(16..).unwrap_or_default()
+    } else {
+        &[]
+    };
+
+    std::iter::from_fn(move || {
+        // Command, index, address, length and flags, IRQ
+        let header = rest.get(0..10)?;
+
+        let flags = u16::from_le_bytes([header[6], header[7]]);
+        let data_len = usize::from(flags & 0x07ff);
+
+        // Data then working counter
+        let Some(next) = rest.get(10 + data_len + 2..) else {
+            rest = &[];
+
+            return None;
+        };
+
+        // Another PDU follows in the same frame
+        rest = if flags & 0x8000 != 0 { next } else { &[] };
+
+        let address = u32::from_le_bytes(header[2..6].try_into().expect("4 bytes"));
+
+        let command = match header[0] {
+            0x0a => Command::Read(Reads::Lrd { address }),
+            0x0b => Command::Write(Writes::Lwr { address }),
+            0x0c => Command::Write(Writes::Lrw { address }),
+            // Not logical, so filtered out below
+            _ => Command::Nop,
+        };
+
+        Some(Pdu {
+            index: header[1],
+            command,
+            data_len,
+            time,
+            from_master,
+            packet_number,
+        })
+    })
+    .filter(|pdu| is_logical(&pdu.command))
+}
+
+/// Database representation of a TX/RX cycle.
+#[derive(Debug)]
+pub struct Packet {
+    packet_number: i32,
+    index: i16,
+    command: Command,
+    tx_time_ns: i64,
+    rx_time_ns: i64,
+    delta_time_ns: i32,
+    data_len: i16,
+    /// Position of the PDU in its Ethernet frame, starting from zero.
+    pdu_position: i16,
+    /// NIC hardware receive timestamps, if recorded.
+    hw_tx_time_ns: Option<i64>,
+    hw_rx_time_ns: Option<i64>,
+}
+
+impl Packet {
+    /// Encode as a row of the `frames` table, in [`COPY_FRAMES`] column order.
+    pub fn write_row(&self, run_name: &str, buf: &mut BinaryCopy) {
+        buf.row(11)
+            .text(run_name)
+            .int4(self.packet_number)
+            .int2(self.index)
+            .display(self.command)
+            .int8(self.tx_time_ns)
+            .int8(self.rx_time_ns)
+            .int4(self.delta_time_ns)
+            .int2(self.data_len)
+            .int2(self.pdu_position);
+
+        for value in [self.hw_tx_time_ns, self.hw_rx_time_ns] {
+            match value {
+                Some(value) => buf.int8(value),
+                None => buf.null(),
+            };
+        }
+    }
+
+    /// Write as a CSV line in [`FRAMES_CSV_HEADER`] column order. Missing hardware timestamps are
+    /// left empty.
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            self.packet_number,
+            self.index,
+            self.command,
+            self.tx_time_ns,
+            self.rx_time_ns,
+            self.delta_time_ns,
+            self.data_len,
+            self.pdu_position
+        )?;
+
+        for value in [self.hw_tx_time_ns, self.hw_rx_time_ns] {
+            match value {
+                Some(value) => write!(out, ",{}", value)?,
+                None => write!(out, ",")?,
+            }
+        }
+
+        writeln!(out)
+    }
+}
+
+/// Pair every logical PDU in the finished capture at `path`, calling `out` with each one in send
+/// order. Everything before the first logical PDU sent by the master is skipped, the same as
+/// ingest.
+pub fn pair_capture(
+    path: &Path,
+    hardware_timestamps: bool,
+    mut out: impl FnMut(Packet),
+) -> io::Result<Paired> {
+    let mut tail = Tail::open(path)?;
+    let mut pairer = Pairer::default();
+    let mut started = false;
+
+    while let Some(packet) = tail.next_packet()? {
+        let hardware_time_ns = packet
+            .hardware_time_ns
+            .filter(|_| hardware_timestamps)
+            .map(|time_ns| time_ns as i64);
+
+        let time = Duration::from_nanos(packet.time_ns);
+
+        for pdu in logical_pdus(packet.data, time, packet.number) {
+            started |= pdu.from_master;
+
+            if started {
+                pairer.push(pdu, hardware_time_ns, &mut out);
+            }
+        }
+    }
+
+    if tail.is_truncated() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is truncated", path.display()),
+        ));
+    }
+
+    Ok(pairer.finish(out))
+}
+
+/// Pairs logical PDUs with their responses as they're pushed.
+///
+/// Only logical PDUs should be pushed, starting from the first one sent by the master: see
+/// [`is_logical`].
+#[derive(Debug, Default)]
+pub struct Pairer {
+    /// Time of the first PDU. All TX/RX times are relative to this.
+    start_offset: Option<Duration>,
+    summary: Histogram,
+    lost: u64,
+    orphaned: u64,
+
+    /// Sent PDUs in send order, waiting for a response
+    pending: VecDeque<Packet>,
+    /// Number of PDUs popped off the front of `pending` so far. A PDU's position in `pending` is its
+    /// send sequence number minus this.
+    popped: usize,
+    /// PDU index to the sequence number of the most recently sent PDU with that index still waiting
+    /// for a response. Indices wrap around, so a newer PDU replaces an older one with the same
+    /// index, matching how the master itself reuses them.
+    outstanding: HashMap<i16, usize>,
+    /// Packet number of the last sent frame and how many PDUs have been seen in it. Frames can hold
+    /// more than one PDU, which all share the frame's TX and RX times.
+    sent_frame: Option<usize>,
+    pdu_position: i16,
+}
+
+/// What's left once every PDU has been pushed.
+#[derive(Debug, Clone)]
+pub struct Paired {
+    /// Round trip times of every PDU that received a response.
+    pub summary: Histogram,
+
+    /// Number of PDUs that didn't.
+    pub lost: u64,
+
+    /// Responses that didn't match a sent PDU still waiting for one, e.g. duplicates or responses
+    /// arriving after their PDU was given up on.
+    pub orphaned: u64,
+
+    /// Whether any PDUs were pushed at all.
+    pub any: bool,
+}
+
+impl Pairer {
+    /// Add a PDU, calling `out` with any PDUs that are now complete, in send order.
+    /// `hardware_time_ns` is the NIC timestamp of the frame the PDU was in, if recorded.
+    pub fn push(
+        &mut self,
+        packet: Pdu,
+        hardware_time_ns: Option<i64>,
+        mut out: impl FnMut(Packet),
+    ) {
+        let start = *self.start_offset.get_or_insert(packet.time);
+
+        // Newly sent PDU
+        if packet.from_master {
+            if self.sent_frame == Some(packet.packet_number) {
+                self.pdu_position += 1;
+            } else {
+                self.sent_frame = Some(packet.packet_number);
+                self.pdu_position = 0;
+            }
+
+            self.outstanding
+                .insert(packet.index as i16, self.popped + self.pending.len());
+
+            self.pending.push_back(Packet {
+                packet_number: packet.packet_number as i32,
+                index: packet.index as i16,
+                tx_time_ns: (packet.time - start).as_nanos() as i64,
+                rx_time_ns: 0,
+                delta_time_ns: 0,
+                data_len: packet.data_len as i16,
+                pdu_position: self.pdu_position,
+                command: packet.command,
+                hw_tx_time_ns: hardware_time_ns,
+                hw_rx_time_ns: None,
+            });
+        }
+        // Response to the last sent PDU with the same index, if it's still waiting for one
+        else if let Some(sent) = self
+            .outstanding
+            .remove(&(packet.index as i16))
+            .and_then(|seq| seq.checked_sub(self.popped))
+            .and_then(|position| self.pending.get_mut(position))
+        {
+            sent.rx_time_ns = (packet.time - start).as_nanos() as i64;
+            sent.delta_time_ns = (sent.rx_time_ns - sent.tx_time_ns) as i32;
+            sent.hw_rx_time_ns = hardware_time_ns;
+        } else {
+            self.orphaned += 1;
+        }
+
+        // Pass completed PDUs on in send order. If the oldest one never got a response, don't hold
+        // everything else up forever waiting for it.
+        while self
+            .pending
+            .front()
+            .is_some_and(|sent| sent.rx_time_ns != 0 || self.pending.len() > MAX_PENDING)
+        {
+            let sent = self.pending.pop_front().expect("Pending PDU");
+
+            if sent.rx_time_ns != 0 {
+                self.summary.record(sent.delta_time_ns as u32);
+            } else {
+                self.lost += 1;
+
+                // Given up on, so a late response doesn't match it
+                if self.outstanding.get(&sent.index) == Some(&self.popped) {
+                    self.outstanding.remove(&sent.index);
+                }
+            }
+
+            self.popped += 1;
+
+            out(sent);
+        }
+    }
+
+    /// Pass on every PDU still waiting. Some may have been answered but held up behind one that
+    /// never was.
+    pub fn finish(mut self, mut out: impl FnMut(Packet)) -> Paired {
+        for sent in self.pending.drain(..) {
+            if sent.rx_time_ns != 0 {
+                self.summary.record(sent.delta_time_ns as u32);
+            } else {
+                self.lost += 1;
+            }
+
+            out(sent);
+        }
+
+        Paired {
+            summary: self.summary,
+            lost: self.lost,
+            orphaned: self.orphaned,
+            any: self.start_offset.is_some(),
+        }
+    }
+}
+
+/// An EtherCAT frame holding a PDU with each `(command code, index, payload length)`.
+#[cfg(test)]
+pub fn test_frame(from_master: bool, pdus: &[(u8, u8, usize)]) -> Vec<u8> {
+    let mut frame = vec![0xff; 6];
+
+    frame.extend_from_slice(&if from_master {
+        crate::capture::MASTER_MAC
+    } else {
+        [0x12, 0x10, 0x10, 0x10, 0x10, 0x10]
+    });
+    frame.extend_from_slice(&ETHERCAT_ETHERTYPE.to_be_bytes());
+    // EtherCAT header length isn't checked
+    frame.extend_from_slice(&[0, 0x10]);
+
+    for (position, (command, index, len)) in pdus.iter().enumerate() {
+        let more = if position + 1 < pdus.len() { 0x8000 } else { 0 };
+
+        frame.extend_from_slice(&[*command, *index]);
+        frame.extend_from_slice(&0x1000u32.to_le_bytes());
+        frame.extend_from_slice(&(*len as u16 | more).to_le_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend(std::iter::repeat_n(0xaa, *len));
+        // Working counter
+        frame.extend_from_slice(&[1, 0]);
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lrw(index: u8, from_master: bool, time_ns: u64, packet_number: usize) -> Pdu {
+        Pdu {
+            index,
+            command: Command::Write(Writes::Lrw { address: 0 }),
+            data_len: 4,
+            time: Duration::from_nanos(time_ns),
+            from_master,
+            packet_number,
+        }
+    }
+
+    /// Push every PDU then finish, returning rows in the order they were passed on.
+    fn pair(pdus: impl IntoIterator<Item = Pdu>) -> (Vec<Packet>, Paired) {
+        let mut pairer = Pairer::default();
+        let mut rows = Vec::new();
+
+        for pdu in pdus {
+            pairer.push(pdu, None, |row| rows.push(row));
+        }
+
+        let paired = pairer.finish(|row| rows.push(row));
+
+        (rows, paired)
+    }
+
+    #[test]
+    fn reused_index_pairs_with_latest_pdu() {
+        let (rows, paired) = pair([
+            lrw(1, true, 1000, 1),
+            lrw(1, false, 1100, 2),
+            // Reused once answered
+            lrw(1, true, 2000, 3),
+            lrw(1, false, 2300, 4),
+            // Reused while still outstanding, so the first one is lost
+            lrw(1, true, 3000, 5),
+            lrw(1, true, 4000, 6),
+            lrw(1, false, 4500, 7),
+        ]);
+
+        let times = rows
+            .iter()
+            .map(|row| (row.packet_number, row.delta_time_ns))
+            .collect::<Vec<_>>();
+
+        assert_eq!(times, [(1, 100), (3, 300), (5, 0), (6, 500)]);
+        assert_eq!(paired.summary.count(), 3);
+        assert_eq!(paired.lost, 1);
+        assert_eq!(paired.orphaned, 0);
+    }
+
+    #[test]
+    fn csv_matches_header() {
+        let (rows, _paired) = pair([lrw(3, true, 1000, 1), lrw(3, false, 1250, 2)]);
+
+        let mut csv = Vec::new();
+
+        for row in rows.iter() {
+            row.write_csv(&mut csv).unwrap();
+        }
+
+        let csv = String::from_utf8(csv).unwrap();
+
+        assert_eq!(csv, "1,3,LRW(addr 0),0,250,250,4,0,,\n");
+        assert_eq!(
+            FRAMES_CSV_HEADER.split(',').count(),
+            csv.trim_end().split(',').count()
+        );
+    }
+
+    #[test]
+    fn late_response_after_eviction_is_orphaned() {
+        // Index 0 is never reused, so once it's evicted nothing can match its response
+        let sent = std::iter::once(lrw(0, true, 0, 1))
+            .chain((0..MAX_PENDING).map(|n| lrw((n % 255 + 1) as u8, true, 10 + n as u64, n + 2)));
+
+        let mut pairer = Pairer::default();
+        let mut rows = Vec::new();
+
+        for pdu in sent {
+            pairer.push(pdu, None, |row| rows.push(row));
+        }
+
+        assert_eq!(rows.len(), 1, "Oldest PDU evicted");
+        assert_eq!(rows[0].rx_time_ns, 0);
+
+        pairer.push(lrw(0, false, 100_000, MAX_PENDING + 2), None, |row| {
+            rows.push(row)
+        });
+
+        let paired = pairer.finish(|row| rows.push(row));
+
+        assert_eq!(rows.len(), MAX_PENDING + 1);
+        assert_eq!(paired.lost, MAX_PENDING as u64 + 1);
+        assert_eq!(paired.orphaned, 1);
+    }
+
+    #[test]
+    fn duplicate_response_is_orphaned() {
+        let (rows, paired) = pair([
+            lrw(5, true, 1000, 1),
+            lrw(5, false, 1200, 2),
+            lrw(5, false, 1300, 3),
+        ]);
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].delta_time_ns, 200);
+        assert_eq!(paired.summary.count(), 1);
+        assert_eq!(paired.lost, 0);
+        assert_eq!(paired.orphaned, 1);
+    }
+
+    #[test]
+    fn logical_pdus_in_multi_pdu_frame() {
+        // LRW, FPRD, LRD
+        let frame = test_frame(true, &[(0x0c, 1, 8), (0x04, 2, 2), (0x0a, 3, 4)]);
+
+        let pdus = logical_pdus(&frame, Duration::from_nanos(5), 7)
+            .map(|pdu| (pdu.index, pdu.command, pdu.data_len, pdu.from_master))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            pdus,
+            [
+                (1, Command::Write(Writes::Lrw { address: 0x1000 }), 8, true),
+                (3, Command::Read(Reads::Lrd { address: 0x1000 }), 4, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn logical_pdus_in_truncated_frame() {
+        let frame = test_frame(false, &[(0x0c, 1, 8), (0x0b, 2, 16)]);
+
+        // Cut off part way through the second PDU's payload, e.g. by the capture's snaplen
+        let pdus = logical_pdus(&frame[0..frame.len() - 10], Duration::ZERO, 1)
+            .map(|pdu| (pdu.index, pdu.from_master))
+            .collect::<Vec<_>>();
+
+        assert_eq!(pdus, [(1, false)]);
+
+        // Not even a whole PDU header
+        assert_eq!(logical_pdus(&frame[0..20], Duration::ZERO, 1).count(), 0);
+    }
+
+    #[test]
+    fn logical_pdus_skips_other_ethertypes() {
+        let mut frame = test_frame(true, &[(0x0c, 1, 8)]);
+
+        frame[12..14].copy_from_slice(&0x88f7u16.to_be_bytes());
+
+        assert_eq!(logical_pdus(&frame, Duration::ZERO, 1).count(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! Count scheduler and cache events with `perf_event_open` while a scenario runs.
+//!
+//! Counters are opened on the thread that starts the scenario with `inherit` set, so every net and
+//! task thread the scenario spawns is counted too. Inherited counts are added to the parent's once
+//! a thread exits, which all scenario threads have by the time it returns.
+//!
+//! Hardware counters aren't available on every machine (e.g. most VMs) and are skipped with a
+//! warning. Opening any counter needs `kernel.perf_event_paranoid` <= 2, or root.
+
+use std::{collections::BTreeMap, fs::File, io::Read, os::fd::FromRawFd};
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+const PERF_COUNT_SW_PAGE_FAULTS: u64 = 2;
+const PERF_COUNT_SW_CONTEXT_SWITCHES: u64 = 3;
+const PERF_COUNT_SW_CPU_MIGRATIONS: u64 = 4;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// `inherit` bit of `perf_event_attr`'s flags.
+const ATTR_FLAG_INHERIT: u64 = 1 << 1;
+
+/// `perf_event_attr` up to `config2` (`PERF_ATTR_SIZE_VER1`).
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+}
+
+/// Counter name, perf type and config.
+const EVENTS: [(&str, u32, u64); 5] = [
+    (
+        "context_switches",
+        PERF_TYPE_SOFTWARE,
+        PERF_COUNT_SW_CONTEXT_SWITCHES,
+    ),
+    (
+        "cpu_migrations",
+        PERF_TYPE_SOFTWARE,
+        PERF_COUNT_SW_CPU_MIGRATIONS,
+    ),
+    ("page_faults", PERF_TYPE_SOFTWARE, PERF_COUNT_SW_PAGE_FAULTS),
+    (
+        "cache_misses",
+        PERF_TYPE_HARDWARE,
+        PERF_COUNT_HW_CACHE_MISSES,
+    ),
+    (
+        "instructions",
+        PERF_TYPE_HARDWARE,
+        PERF_COUNT_HW_INSTRUCTIONS,
+    ),
+];
+
+/// Counters opened for one scenario.
+pub struct Counters {
+    counters: Vec<(&'static str, File)>,
+}
+
+impl Counters {
+    /// Start counting for the current thread and any threads it spawns from now on.
+    pub fn start() -> Self {
+        let counters = EVENTS
+            .iter()
+            .filter_map(|(name, type_, config)| match open(*type_, *config) {
+                Ok(file) => Some((*name, file)),
+                Err(e) => {
+                    log::warn!("Can't count {}: {}", name, e);
+
+                    None
+                }
+            })
+            .collect();
+
+        Self { counters }
+    }
+
+    /// Read every counter's total, closing them.
+    pub fn stop(self) -> BTreeMap<&'static str, u64> {
+        self.counters
+            .into_iter()
+            .filter_map(|(name, mut file)| {
+                let mut buf = [0u8; 8];
+
+                match file.read_exact(&mut buf) {
+                    Ok(()) => Some((name, u64::from_ne_bytes(buf))),
+                    Err(e) => {
+                        log::warn!("Failed to read {} counter: {}", name, e);
+
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+fn open(type_: u32, config: u64) -> std::io::Result<File> {
+    let attr = PerfEventAttr {
+        type_,
+        size: std::mem::size_of::<PerfEventAttr>() as u32,
+        config,
+        flags: ATTR_FLAG_INHERIT,
+        ..PerfEventAttr::default()
+    };
+
+    // SAFETY: `attr` is a valid `perf_event_attr` whose `size` matches its layout. pid 0, cpu -1
+    // counts the calling thread on any CPU.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr as *const PerfEventAttr,
+            0 as libc::pid_t,
+            -1 as libc::c_int,
+            -1 as libc::c_int,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: The kernel just gave us this descriptor and nothing else owns it.
+    Ok(unsafe { File::from_raw_fd(fd as i32) })
+}
@@ -0,0 +1,101 @@
+//! Sample the offset between a NIC's PTP hardware clock and the system clock while a scenario runs.
+//!
+//! On rigs where `ptp4l` disciplines the PHC (and `phc2sys` the system clock), servo corrections
+//! show up as steps and slews in this offset, which can then be lined up against cycle jitter.
+//! Offsets are read with `phc_ctl <nic> cmp` from a normal priority thread, so sampling doesn't
+//! compete with the scenario's RT threads.
+
+use chrono::{DateTime, Utc};
+use std::{
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// A single PHC to system clock comparison.
+#[derive(Debug, Clone)]
+pub struct PhcOffset {
+    pub time: DateTime<Utc>,
+
+    /// `PHC - CLOCK_REALTIME`. Includes the TAI-UTC offset if the PHC runs on TAI.
+    pub offset_ns: i64,
+}
+
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Vec<PhcOffset>>,
+}
+
+impl Sampler {
+    /// Start sampling the PHC of `nic` every `interval`.
+    pub fn start(nic: &str, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let stop = stop.clone();
+            let nic = nic.to_string();
+
+            thread::spawn(move || {
+                let mut offsets = Vec::new();
+
+                while !stop.load(Ordering::Relaxed) {
+                    match read_offset(&nic) {
+                        Ok(offset_ns) => offsets.push(PhcOffset {
+                            time: Utc::now(),
+                            offset_ns,
+                        }),
+                        Err(e) => log::warn!("Failed to read PHC offset: {}", e),
+                    }
+
+                    thread::sleep(interval);
+                }
+
+                offsets
+            })
+        };
+
+        Self { stop, handle }
+    }
+
+    /// Stop sampling and return every offset recorded.
+    pub fn stop(self) -> Vec<PhcOffset> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        self.handle.join().expect("PHC sampler panicked")
+    }
+}
+
+/// Warn if nothing is disciplining the PHC, as the offsets will just show free-running drift.
+pub fn check_ptp4l() {
+    let running = Command::new("pgrep")
+        .args(["-x", "ptp4l"])
+        .output()
+        .is_ok_and(|out| out.status.success());
+
+    if !running {
+        log::warn!("ptp4l is not running, PHC offsets will show free-running drift");
+    }
+}
+
+fn read_offset(nic: &str) -> anyhow::Result<i64> {
+    let out = Command::new("phc_ctl").args([nic, "cmp"]).output()?;
+
+    anyhow::ensure!(
+        out.status.success(),
+        "phc_ctl exited with {}: {}",
+        out.status,
+        String::from_utf8_lossy(&out.stderr).trim()
+    );
+
+    // phc_ctl[1234.567]: offset from CLOCK_REALTIME is -37000000012ns
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    stdout
+        .split_once("offset from CLOCK_REALTIME is ")
+        .and_then(|(_, rest)| rest.trim().strip_suffix("ns")?.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Unexpected phc_ctl output {:?}", stdout.trim()))
+}
@@ -0,0 +1,104 @@
+//! Push run summaries to a Prometheus Pushgateway with `curl`.
+
+use crate::{
+    scenarios::RunMetadata,
+    stats::{Histogram, Summary},
+};
+use base64::{engine::general_purpose::URL_SAFE, Engine as _};
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+/// Name, help text and how to get a gauge's value from a metric's summary.
+type Gauge = (&'static str, &'static str, fn(&Summary) -> f64);
+
+/// Replace the metrics for a run's scenario/host/slug group on the Pushgateway at `url`.
+///
+/// Each `metrics` entry is exported with a `metric` label, so e.g. the p99 frame round trip time
+/// is `latency_data_p99_ns{metric="frame_delta_time"}`.
+pub fn push(
+    url: &str,
+    scenario: &str,
+    result: &RunMetadata,
+    metrics: &[(&str, &Histogram)],
+    lost_frames: u64,
+) -> anyhow::Result<()> {
+    let mut body = String::new();
+
+    let summaries = metrics
+        .iter()
+        .map(|(metric, histogram)| (metric, histogram.summary()))
+        .collect::<Vec<_>>();
+
+    let gauges: [Gauge; 4] = [
+        ("p50_ns", "Median value", |s| f64::from(s.p50_ns)),
+        ("p99_ns", "99th percentile", |s| f64::from(s.p99_ns)),
+        ("max_ns", "Maximum value", |s| f64::from(s.max_ns)),
+        ("mean_ns", "Mean value", |s| s.mean_ns),
+    ];
+
+    for (name, help, value) in gauges {
+        writeln!(body, "# HELP latency_data_{} {}", name, help)?;
+        writeln!(body, "# TYPE latency_data_{} gauge", name)?;
+
+        for (metric, summary) in summaries.iter() {
+            writeln!(
+                body,
+                "latency_data_{}{{metric=\"{}\"}} {}",
+                name,
+                metric,
+                value(summary)
+            )?;
+        }
+    }
+
+    writeln!(body, "# TYPE latency_data_deadline_misses gauge")?;
+    writeln!(
+        body,
+        "latency_data_deadline_misses {}",
+        result.cycle_summary.deadline_misses
+    )?;
+    writeln!(body, "# TYPE latency_data_lost_frames gauge")?;
+    writeln!(body, "latency_data_lost_frames {}", lost_frames)?;
+
+    let target = format!(
+        "{}/metrics/job/latency-data/scenario@base64/{}/hostname@base64/{}/slug@base64/{}",
+        url.trim_end_matches('/'),
+        label_value(scenario),
+        label_value(&result.hostname),
+        label_value(&result.slug)
+    );
+
+    log::debug!("Pushing metrics to {}", target);
+
+    let mut curl = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "-X", "PUT"])
+        .args(["--data-binary", "@-"])
+        .arg(&target)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    curl.stdin
+        .take()
+        .expect("curl stdin")
+        .write_all(body.as_bytes())?;
+
+    let status = curl.wait()?;
+
+    anyhow::ensure!(status.success(), "curl exited with {}", status);
+
+    Ok(())
+}
+
+/// Encode a grouping key label value so values containing `/` or other characters that can't go
+/// in a URL path segment arrive unchanged.
+fn label_value(value: &str) -> String {
+    // The Pushgateway reads a lone `=` as an empty value
+    if value.is_empty() {
+        "=".to_string()
+    } else {
+        URL_SAFE.encode(value)
+    }
+}
@@ -0,0 +1,148 @@
+use super::{
+    create_client, create_groups, loop_tick, make_net_thread, make_task_thread, pin_net_thread,
+    pin_task_thread, CycleMetadata, Cycles, Group, Storage, TestSettings,
+};
+use async_std::stream::StreamExt;
+use ethercrab::{self};
+use futures_lite::future;
+use std::time::{Duration, Instant};
+
+/// Single thread with TX/RX and one PDI loop running concurrently on async-std's thread-local
+/// executor, the same as [`single_thread`](super::single_thread::single_thread).
+pub fn async_std_single_thread(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                async_std::task::block_on(async {
+                    // TX/RX never finishes on its own, so this completes when the PDI loop does
+                    future::or(
+                        async {
+                            tx_rx.await?;
+
+                            unreachable!("TX/RX task stopped")
+                        },
+                        async {
+                            let mut groups = create_groups(settings, &client).await?;
+
+                            // The time it takes to traverse to the end of the EtherCAT network and
+                            // back again.
+                            let network_propagation_time_ns = groups
+                                .iter_mut()
+                                .flat_map(|group| group.iter(&client))
+                                .map(|device| device.propagation_delay())
+                                .max()
+                                .expect("Unable to compute prop time");
+
+                            let [group, ..] = groups;
+
+                            let cycles = task(group, &client, settings).await;
+
+                            Ok((cycles, network_propagation_time_ns))
+                        },
+                    )
+                    .await
+                })
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// TX/RX on one thread and one PDI loop on another, each driven by async-std, the same as
+/// [`two_threads`](super::thread_per_task::two_threads).
+pub fn async_std_two_threads(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let storage = Storage::new(settings);
+
+    let (client, tx_rx) = create_client(settings, &storage);
+
+    std::thread::scope(|s| {
+        let (net_tx, net_rx) = smol::channel::bounded(1);
+
+        make_net_thread(settings)
+            .spawn_scoped(s, move |_| {
+                pin_net_thread(settings);
+
+                async_std::task::block_on(future::or(tx_rx, async {
+                    net_rx.recv().await.ok();
+
+                    Ok(())
+                }))
+            })
+            .expect("TX/RX thread");
+
+        let mut groups = async_std::task::block_on(create_groups(settings, &client))?;
+
+        // The time it takes to traverse to the end of the EtherCAT network and back again.
+        let network_propagation_time_ns = groups
+            .iter_mut()
+            .flat_map(|group| group.iter(&client))
+            .map(|device| device.propagation_delay())
+            .max()
+            .expect("Unable to compute prop time");
+
+        let [group, ..] = groups;
+
+        let results = make_task_thread(settings)
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                async_std::task::block_on(task(group, &client, settings))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        // Stop net thread. Scoped thread hangs waiting on net task to join otherwise.
+        net_tx.send_blocking(()).ok();
+
+        Ok((results, network_propagation_time_ns))
+    })
+}
+
+async fn task(group: Group, client: &ethercrab::Client<'_>, settings: &TestSettings) -> Cycles {
+    let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
+    let mut tick =
+        async_std::stream::interval(Duration::from_micros(settings.cycle_time_us.into()));
+    let mut prev = Instant::now();
+
+    let iterations = settings.iterations(5000);
+
+    let mut cycles = Cycles::new(settings, iterations);
+
+    for cycle in 0..iterations {
+        let loop_start = Instant::now();
+
+        loop_tick(&mut group, client).await;
+
+        let processed = Instant::now();
+
+        tick.next().await;
+
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
+
+        prev = tick_end;
+
+        if cycles.is_aborted() {
+            break;
+        }
+    }
+
+    cycles
+}
@@ -0,0 +1,432 @@
+//! Scenarios put together from a description in the run matrix file, so new thread and task
+//! topologies can be benchmarked without writing a module for each one.
+
+use super::{
+    create_client, create_groups, loop_tick, make_net_thread, make_task_thread,
+    nanosleep::{advance, monotonic_now, sleep_until},
+    pin_net_thread, pin_task_thread, CycleMetadata, Cycles, Group, Storage, TestSettings, GROUPS,
+};
+use ethercrab::{self, Client};
+use futures_lite::{future, StreamExt};
+use std::{
+    fmt,
+    future::Future,
+    thread::ScopedJoinHandle,
+    time::{Duration, Instant},
+};
+
+/// A scenario as written in the `[[compose]]` tables of the run matrix file.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CompositionSpec {
+    /// Scenario name. Made from the other fields if not set, e.g. `3thr-4task-tokio-spin`.
+    name: Option<String>,
+
+    /// `1` to run TX/RX on its own thread, or `0` to run it on the first task thread.
+    #[serde(default = "one")]
+    net_threads: usize,
+
+    #[serde(default = "one")]
+    task_threads: usize,
+
+    #[serde(default = "one")]
+    tasks_per_thread: usize,
+
+    #[serde(default)]
+    executor: Executor,
+
+    #[serde(default)]
+    timer: Timer,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// Executor each thread of a composed scenario runs its futures on.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Executor {
+    /// `smol::LocalExecutor`.
+    #[default]
+    Smol,
+    /// `async_std::task::block_on`.
+    AsyncStd,
+    /// A current thread tokio runtime.
+    Tokio,
+}
+
+impl fmt::Display for Executor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Smol => "smol",
+            Self::AsyncStd => "async-std",
+            Self::Tokio => "tokio",
+        })
+    }
+}
+
+/// How tasks of a composed scenario wait for their next cycle.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Timer {
+    /// The executor's own interval timer.
+    #[default]
+    Async,
+    /// Yield to the executor until the deadline has passed, like `1thr-1task-spin`.
+    Spin,
+    /// Block the thread with `clock_nanosleep`, like `1thr-1task-nanosleep`.
+    Nanosleep,
+}
+
+impl fmt::Display for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Async => "async",
+            Self::Spin => "spin",
+            Self::Nanosleep => "nanosleep",
+        })
+    }
+}
+
+/// A validated [`CompositionSpec`], ready to run with [`composed`].
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Composition {
+    #[serde(skip)]
+    pub(super) name: &'static str,
+
+    #[serde(skip)]
+    pub(super) tags: &'static [&'static str],
+
+    net_threads: usize,
+    task_threads: usize,
+    tasks_per_thread: usize,
+    executor: Executor,
+    timer: Timer,
+}
+
+impl Composition {
+    pub fn new(spec: CompositionSpec) -> Result<Self, String> {
+        let CompositionSpec {
+            name,
+            net_threads,
+            task_threads,
+            tasks_per_thread,
+            executor,
+            timer,
+        } = spec;
+
+        if net_threads > 1 {
+            return Err(format!(
+                "net_threads must be 0 or 1, got {}. EtherCrab only has one TX/RX task",
+                net_threads
+            ));
+        }
+
+        let tasks = task_threads * tasks_per_thread;
+
+        if task_threads == 0 || tasks_per_thread == 0 || tasks > GROUPS {
+            return Err(format!(
+                "needs at least one task thread and task per thread, with at most {} tasks, got {} threads of {}",
+                GROUPS, task_threads, tasks_per_thread
+            ));
+        }
+
+        // Sleeping blocks the whole thread, so it would serialise tasks or starve TX/RX
+        if timer == Timer::Nanosleep && tasks_per_thread > 1 {
+            return Err("the nanosleep timer only supports one task per thread".to_string());
+        }
+
+        let threads = net_threads + task_threads;
+
+        let name =
+            name.unwrap_or_else(|| format!("{}thr-{}task-{}-{}", threads, tasks, executor, timer));
+
+        let mut tags = vec![
+            if threads == 1 {
+                "single-thread"
+            } else {
+                "multi-thread"
+            },
+            match executor {
+                Executor::Smol => "smol",
+                Executor::AsyncStd => "async-std",
+                Executor::Tokio => "tokio",
+            },
+        ];
+
+        if timer != Timer::Async {
+            tags.push("timer");
+        }
+
+        tags.push("composed");
+
+        // Only made once per suite, from the config file
+        Ok(Self {
+            name: Box::leak(name.into_boxed_str()),
+            tags: Box::leak(tags.into_boxed_slice()),
+            net_threads,
+            task_threads,
+            tasks_per_thread,
+            executor,
+            timer,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Run the scenario described by [`TestSettings::composition`].
+///
+/// Groups are dealt out to task threads in order, `tasks_per_thread` each. Every thread runs its
+/// own instance of the executor. TX/RX keeps running until every task thread has finished, even
+/// when it shares the first task thread.
+pub fn composed(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let composition = settings
+        .composition
+        .as_ref()
+        .expect("No composition configured");
+
+    let storage = Storage::new(settings);
+
+    let (client, tx_rx) = create_client(settings, &storage);
+
+    std::thread::scope(|s| {
+        let client = &client;
+
+        // Each task thread says when it's done, so TX/RX can stop once they all are
+        let (done_tx, done_rx) = smol::channel::unbounded::<()>();
+
+        let net = future::or(tx_rx, async move {
+            for _ in 0..composition.task_threads {
+                if done_rx.recv().await.is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        let mut shared_net = None;
+
+        if composition.net_threads == 0 {
+            shared_net = Some(net);
+        } else {
+            make_net_thread(settings)
+                .spawn_scoped(s, move |_| {
+                    pin_net_thread(settings);
+
+                    block_on(composition.executor, net)
+                })
+                .expect("TX/RX thread");
+        }
+
+        let mut groups_txs = Vec::with_capacity(composition.task_threads);
+
+        let handles = (0..composition.task_threads)
+            .map(|_| {
+                let (groups_tx, groups_rx) = smol::channel::bounded::<Vec<Group>>(1);
+
+                groups_txs.push(groups_tx);
+
+                let net = shared_net.take();
+                let done_tx = done_tx.clone();
+
+                make_task_thread(settings)
+                    .spawn_scoped_careless(s, move || {
+                        pin_task_thread(settings);
+
+                        block_on(composition.executor, async move {
+                            let tasks = async {
+                                // Init failed if the groups never come
+                                let cycles = match groups_rx.recv().await {
+                                    Ok(groups) => {
+                                        futures::future::join_all(groups.into_iter().map(|group| {
+                                            task(group, client, settings, composition)
+                                        }))
+                                        .await
+                                        .into_iter()
+                                        .collect::<Cycles>()
+                                    }
+                                    Err(_) => Cycles::default(),
+                                };
+
+                                done_tx.send(()).await.ok();
+
+                                cycles
+                            };
+
+                            match net {
+                                Some(net) => future::zip(net, tasks).await.1,
+                                None => tasks.await,
+                            }
+                        })
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<ScopedJoinHandle<'_, Cycles>>>();
+
+        let mut groups = smol::block_on(create_groups(settings, client))?;
+
+        // The time it takes to traverse to the end of the EtherCAT network and back again.
+        let network_propagation_time_ns = groups
+            .iter_mut()
+            .flat_map(|group| group.iter(client))
+            .map(|device| device.propagation_delay())
+            .max()
+            .expect("Unable to compute prop time");
+
+        let mut groups = groups.into_iter();
+
+        for groups_tx in groups_txs {
+            groups_tx
+                .send_blocking(groups.by_ref().take(composition.tasks_per_thread).collect())
+                .ok();
+        }
+
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Cycles>();
+
+        Ok((results, network_propagation_time_ns))
+    })
+}
+
+/// Run `future` to completion on the current thread with `executor`.
+fn block_on<F: Future>(executor: Executor, future: F) -> F::Output {
+    match executor {
+        Executor::Smol => {
+            let local_ex = smol::LocalExecutor::new();
+
+            futures_lite::future::block_on(local_ex.run(future))
+        }
+        Executor::AsyncStd => async_std::task::block_on(future),
+        Executor::Tokio => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Runtime")
+            .block_on(future),
+    }
+}
+
+/// Waits for the next cycle with one of the [`Timer`]s.
+enum Ticker {
+    Smol(smol::Timer),
+    AsyncStd(async_std::stream::Interval),
+    Tokio(tokio::time::Interval),
+    Spin {
+        next_tick: Instant,
+        cycle_time: Duration,
+    },
+    Nanosleep {
+        next_tick: libc::timespec,
+        cycle_time: Duration,
+    },
+}
+
+impl Ticker {
+    /// Must be called from inside `executor` for its async timer to work.
+    fn new(executor: Executor, timer: Timer, cycle_time: Duration) -> Self {
+        match (timer, executor) {
+            (Timer::Async, Executor::Smol) => Self::Smol(smol::Timer::interval(cycle_time)),
+            (Timer::Async, Executor::AsyncStd) => {
+                Self::AsyncStd(async_std::stream::interval(cycle_time))
+            }
+            (Timer::Async, Executor::Tokio) => Self::Tokio(tokio::time::interval(cycle_time)),
+            // Absolute deadlines so late cycles don't push every later one back
+            (Timer::Spin, _) => Self::Spin {
+                next_tick: Instant::now() + cycle_time,
+                cycle_time,
+            },
+            (Timer::Nanosleep, _) => {
+                let mut next_tick = monotonic_now();
+                advance(&mut next_tick, cycle_time);
+
+                Self::Nanosleep {
+                    next_tick,
+                    cycle_time,
+                }
+            }
+        }
+    }
+
+    async fn tick(&mut self) {
+        match self {
+            Self::Smol(timer) => {
+                timer.next().await;
+            }
+            Self::AsyncStd(interval) => {
+                interval.next().await;
+            }
+            Self::Tokio(interval) => {
+                interval.tick().await;
+            }
+            Self::Spin {
+                next_tick,
+                cycle_time,
+            } => {
+                while Instant::now() < *next_tick {
+                    futures_lite::future::yield_now().await;
+                }
+
+                *next_tick += *cycle_time;
+            }
+            Self::Nanosleep {
+                next_tick,
+                cycle_time,
+            } => {
+                sleep_until(next_tick);
+
+                advance(next_tick, *cycle_time);
+            }
+        }
+    }
+}
+
+async fn task(
+    group: Group,
+    client: &Client<'_>,
+    settings: &TestSettings,
+    composition: &Composition,
+) -> Cycles {
+    let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
+
+    let mut ticker = Ticker::new(
+        composition.executor,
+        composition.timer,
+        Duration::from_micros(settings.cycle_time_us.into()),
+    );
+
+    let mut prev = Instant::now();
+
+    let iterations = settings.iterations(5000);
+
+    let mut cycles = Cycles::new(settings, iterations);
+
+    for cycle in 0..iterations {
+        let loop_start = Instant::now();
+
+        loop_tick(&mut group, client).await;
+
+        let processed = Instant::now();
+
+        ticker.tick().await;
+
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
+
+        prev = tick_end;
+
+        if cycles.is_aborted() {
+            break;
+        }
+    }
+
+    cycles
+}
@@ -0,0 +1,106 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// The same as [`single_thread`](super::single_thread::single_thread), but every cycle does
+/// `--compute-us` of busy work after exchanging the PDI and before waiting for the next tick.
+///
+/// The work is included in `processing_time_ns`, so the slack left before the next tick is
+/// `cycle_time_us` minus that. Comparing runs with increasing compute times shows how quickly
+/// deadline misses go up as the slack shrinks.
+pub fn single_thread_compute(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let compute = Duration::from_micros(
+                        settings
+                            .compute_us
+                            .expect("No compute time configured")
+                            .into(),
+                    );
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        busy_work(compute);
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Keep the CPU busy for `duration` without yielding, like application logic would.
+pub(super) fn busy_work(duration: Duration) {
+    let start = Instant::now();
+
+    let mut acc = 0u64;
+
+    while start.elapsed() < duration {
+        // Stop the loop body being optimised away
+        acc = std::hint::black_box(acc.wrapping_mul(6364136223846793005).wrapping_add(1));
+    }
+}
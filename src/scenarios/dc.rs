@@ -0,0 +1,223 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, Client, Command, RegisterAddress};
+use std::time::{Duration, Instant};
+
+/// SYNC0 starts this long after it's configured, so every device has its start time before then.
+const SYNC0_START_DELAY_NS: u64 = 100_000_000;
+
+/// Single thread with TX/RX and one PDI loop, woken by the DC SYNC0 event instead of a host timer.
+///
+/// SYNC0 is enabled on every device with DC at the configured cycle time. Each cycle, the reference
+/// clock's system time is read back and the host sleeps until the next SYNC0 edge. How far past
+/// the edge the host actually woke is stored as `dc_offset_ns`, which includes the time the read
+/// takes to reach the reference device.
+pub fn single_thread_dc(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let addresses = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.configured_address())
+                        .collect::<Vec<_>>();
+
+                    let cycle_ns = u64::from(settings.cycle_time_us) * 1000;
+
+                    let sync0 = Sync0::configure(&client, &addresses, cycle_ns).await?;
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut prev = Instant::now();
+
+                    let mut dc_time = sync0.reference_time(&client).await?;
+                    let mut dc_read_at = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        // Extrapolate from the last reference clock read to find the next edge
+                        let until_edge = Duration::from_nanos(sync0.until_next_edge(dc_time))
+                            .saturating_sub(dc_read_at.elapsed());
+
+                        smol::Timer::after(until_edge).await;
+
+                        let tick_end = Instant::now();
+
+                        dc_time = sync0.reference_time(&client).await?;
+                        dc_read_at = Instant::now();
+
+                        cycles.push(CycleMetadata {
+                            dc_offset_ns: sync0.since_last_edge(dc_time).map(|ns| ns as u32),
+                            ..CycleMetadata::timed(cycle, loop_start, processed, tick_end, prev)
+                        });
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    sync0.disable(&client).await;
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// The devices in `addresses` that support DC, in the same order.
+pub(super) async fn dc_devices(
+    client: &Client<'_>,
+    addresses: &[u16],
+) -> Result<Vec<u16>, ethercrab::error::Error> {
+    let mut devices = Vec::new();
+
+    for &address in addresses {
+        // Devices without DC don't answer reads of the system time register
+        let (_, wkc) = Command::fprd(address, RegisterAddress::DcSystemTime.into())
+            .receive::<u64>(client)
+            .await?;
+
+        if wkc == 1 {
+            devices.push(address);
+        } else {
+            log::debug!("Device {:#06x} has no DC", address);
+        }
+    }
+
+    Ok(devices)
+}
+
+/// SYNC0 configuration shared by every DC capable device.
+struct Sync0 {
+    /// Configured addresses of every device SYNC0 was enabled on. The first is the reference clock.
+    devices: Vec<u16>,
+
+    /// First SYNC0 edge, in DC system time.
+    start: u64,
+
+    cycle_ns: u64,
+}
+
+impl Sync0 {
+    /// Enable cyclic SYNC0 on every device in `addresses` that supports DC.
+    async fn configure(
+        client: &Client<'_>,
+        addresses: &[u16],
+        cycle_ns: u64,
+    ) -> Result<Self, ethercrab::error::Error> {
+        let devices = dc_devices(client, addresses).await?;
+
+        assert!(!devices.is_empty(), "No devices support DC");
+
+        let mut sync0 = Self {
+            devices,
+            start: 0,
+            cycle_ns,
+        };
+
+        // Line edges up with whole cycles of system time
+        sync0.start =
+            (sync0.reference_time(client).await? + SYNC0_START_DELAY_NS).next_multiple_of(cycle_ns);
+
+        for &address in sync0.devices.iter() {
+            Command::fpwr(address, RegisterAddress::DcSyncActive.into())
+                .send(client, 0u8)
+                .await?;
+
+            Command::fpwr(address, RegisterAddress::DcSyncStartTime.into())
+                .send(client, sync0.start)
+                .await?;
+
+            Command::fpwr(address, RegisterAddress::DcSync0CycleTime.into())
+                .send(client, cycle_ns as u32)
+                .await?;
+
+            // Enable cyclic operation (0th bit) and SYNC0 (1st bit)
+            Command::fpwr(address, RegisterAddress::DcSyncActive.into())
+                .send(client, 0b11u8)
+                .await?;
+        }
+
+        log::info!(
+            "SYNC0 enabled on {} devices every {} ns",
+            sync0.devices.len(),
+            cycle_ns
+        );
+
+        Ok(sync0)
+    }
+
+    /// Read the reference clock's system time.
+    async fn reference_time(&self, client: &Client<'_>) -> Result<u64, ethercrab::error::Error> {
+        Command::fprd(self.devices[0], RegisterAddress::DcSystemTime.into())
+            .receive::<u64>(client)
+            .await
+            .map(|(time, _wkc)| time)
+    }
+
+    fn since_last_edge(&self, dc_time: u64) -> Option<u64> {
+        dc_time
+            .checked_sub(self.start)
+            .map(|since_start| since_start % self.cycle_ns)
+    }
+
+    fn until_next_edge(&self, dc_time: u64) -> u64 {
+        match self.since_last_edge(dc_time) {
+            Some(since) => self.cycle_ns - since,
+            // SYNC0 hasn't started yet
+            None => self.start - dc_time,
+        }
+    }
+
+    /// Stop SYNC0 so devices aren't left running it for the next scenario.
+    async fn disable(&self, client: &Client<'_>) {
+        for &address in self.devices.iter() {
+            if let Err(e) = Command::fpwr(address, RegisterAddress::DcSyncActive.into())
+                .send(client, 0u8)
+                .await
+            {
+                log::warn!("Failed to disable SYNC0 on {:#06x}: {}", address, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use super::{
+    create_client, create_groups, dc::dc_devices, loop_tick, make_task_thread, pin_task_thread,
+    CycleMetadata, Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, Client, Command, RegisterAddress};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Set in the system time difference register when the device's clock is behind the reference.
+const DIFFERENCE_NEGATIVE: u32 = 1 << 31;
+
+/// Single thread with TX/RX and one PDI loop, plus the FRMW that distributes the DC reference
+/// clock's system time every cycle, like a master keeping clocks in sync at runtime.
+///
+/// EtherCrab only distributes the reference time during init, so without this device clocks drift
+/// apart over a run. The FRMW is sent at the same time as the LRW. After each tick, every other DC
+/// device's system time difference is read and the one furthest from zero is stored as
+/// `dc_drift_ns`.
+pub fn single_thread_dc_drift(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let mut addresses = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.configured_address())
+                        .collect::<Vec<_>>();
+
+                    // Addresses are given out in network order, and EtherCrab uses the first
+                    // device with DC as the reference clock
+                    addresses.sort();
+
+                    let mut followers = dc_devices(&client, &addresses).await?;
+
+                    assert!(!followers.is_empty(), "No devices support DC");
+
+                    let reference = followers.remove(0);
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        let (_, sync) = futures_lite::future::zip(
+                            loop_tick(&mut group, &client),
+                            Command::frmw(reference, RegisterAddress::DcSystemTime.into())
+                                .receive::<u64>(&client),
+                        )
+                        .await;
+
+                        sync.expect("FRMW");
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        let drift = max_drift(&client, &followers).await?;
+
+                        cycles.push(CycleMetadata {
+                            dc_drift_ns: drift,
+                            ..CycleMetadata::timed(cycle, loop_start, processed, tick_end, prev)
+                        });
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// The system time difference furthest from zero across `devices`, or `None` if there are none.
+async fn max_drift(
+    client: &Client<'_>,
+    devices: &[u16],
+) -> Result<Option<i32>, ethercrab::error::Error> {
+    let mut max: Option<i32> = None;
+
+    for &address in devices {
+        let (raw, _wkc) = Command::fprd(address, RegisterAddress::DcSystemTimeDifference.into())
+            .receive::<u32>(client)
+            .await?;
+
+        // Sign and magnitude, not two's complement
+        let magnitude = (raw & !DIFFERENCE_NEGATIVE) as i32;
+
+        let difference = if raw & DIFFERENCE_NEGATIVE != 0 {
+            -magnitude
+        } else {
+            magnitude
+        };
+
+        if max.is_none_or(|max| difference.unsigned_abs() > max.unsigned_abs()) {
+            max = Some(difference);
+        }
+    }
+
+    Ok(max)
+}
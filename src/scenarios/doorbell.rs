@@ -0,0 +1,88 @@
+//! Wake a blocking TX/RX loop from the PDU loop, for scenarios that don't use an async executor for
+//! network IO.
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Wake,
+};
+
+/// An eventfd that becomes readable when a frame is ready to send or the loop should stop.
+///
+/// Pass it to [`ethercrab::PduTx::replace_waker`] as a [`std::task::Waker`] and wait on it
+/// alongside the socket.
+pub struct Doorbell {
+    fd: OwnedFd,
+    stop: AtomicBool,
+}
+
+impl Doorbell {
+    pub fn new() -> io::Result<Arc<Self>> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Arc::new(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            stop: AtomicBool::new(false),
+        }))
+    }
+
+    pub fn ring(&self) {
+        let one = 1u64;
+
+        // Only fails if the counter would overflow, in which case the loop is awake anyway.
+        unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &one as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+
+    /// Reset the eventfd so it's no longer readable.
+    pub fn clear(&self) {
+        let mut count = 0u64;
+
+        unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Release);
+
+        self.ring();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Acquire)
+    }
+}
+
+impl AsRawFd for Doorbell {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Wake for Doorbell {
+    fn wake(self: Arc<Self>) {
+        self.ring()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ring()
+    }
+}
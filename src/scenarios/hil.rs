@@ -0,0 +1,218 @@
+use super::{
+    create_client, create_groups, make_task_thread, pin_task_thread, CycleMetadata, Cycles, Group,
+    Storage, TestSettings,
+};
+use ethercrab::{self, slave_group::Op, Client};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Give up if the input hasn't followed the output after this many cycles.
+const MAX_REACTION_CYCLES: usize = 1000;
+
+/// A digital IO point: a bit in one slave device's inputs or outputs.
+#[derive(serde::Serialize, Debug, Clone, Copy)]
+pub struct IoBit {
+    /// Slave device index in discovery order.
+    pub slave: usize,
+
+    /// Bit offset into the device's inputs or outputs.
+    pub bit: usize,
+}
+
+impl std::str::FromStr for IoBit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once(':')
+            .and_then(|(slave, bit)| Some((slave.parse().ok()?, bit.parse().ok()?)));
+
+        match parsed {
+            Some((slave, bit)) => Ok(Self { slave, bit }),
+            None => Err(format!("expected `<slave>:<bit>`, got {:?}", s)),
+        }
+    }
+}
+
+/// Output and input wired together for [`hil`].
+#[derive(serde::Serialize, Debug, Clone, Copy)]
+pub struct HilWiring {
+    pub output: IoBit,
+    pub input: IoBit,
+}
+
+/// Single thread toggling a digital output that is wired back to a digital input on the same
+/// network.
+///
+/// The output is toggled as soon as the input reflects the previous change, and the time from the
+/// cycle that sent the change to the cycle that saw it come back is recorded as that cycle's
+/// reaction latency. This includes both devices' IO update times, unlike frame round trips.
+pub fn hil(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let wiring = settings.hil.expect("HIL scenario needs wiring");
+
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    // Each group holds one device, see `create_groups`
+                    let group_count = groups.len();
+                    let output_group = wiring.output.slave % group_count;
+                    let input_group = wiring.input.slave % group_count;
+
+                    let mut op = Vec::new();
+
+                    for (i, group) in groups.into_iter().enumerate() {
+                        if i == output_group || i == input_group {
+                            op.push((i, group.into_op(&client).await.expect("PRE-OP -> OP")));
+                        }
+                    }
+
+                    let find = |index: usize| {
+                        op.iter()
+                            .position(|(i, _)| *i == index)
+                            .expect("Group in OP")
+                    };
+
+                    let output_group = find(output_group);
+                    let input_group = find(input_group);
+
+                    let mut groups = op.into_iter().map(|(_, group)| group).collect::<Vec<_>>();
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+
+                    // Start from the opposite of whatever the input currently reads so the first
+                    // change is a real one.
+                    tx_rx_all(&groups, &client).await;
+
+                    let mut output = !read_input(&mut groups[input_group], &client, wiring.input);
+
+                    write_output(&mut groups[output_group], &client, wiring.output, output);
+
+                    // Cycle and time the current output value was first sent at
+                    let mut sent = None::<(usize, Instant)>;
+
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        let (sent_cycle, sent_at) = *sent.get_or_insert((cycle, loop_start));
+
+                        tx_rx_all(&groups, &client).await;
+
+                        let received = Instant::now();
+
+                        let input = read_input(&mut groups[input_group], &client, wiring.input);
+
+                        let reaction_latency_ns = if input == output {
+                            output = !output;
+
+                            write_output(
+                                &mut groups[output_group],
+                                &client,
+                                wiring.output,
+                                output,
+                            );
+
+                            sent = None;
+
+                            Some((received - sent_at).as_nanos() as u32)
+                        } else {
+                            assert!(
+                                cycle - sent_cycle < MAX_REACTION_CYCLES,
+                                "Input {:?} didn't follow output {:?} within {} cycles, check the loopback wiring",
+                                wiring.input,
+                                wiring.output,
+                                MAX_REACTION_CYCLES
+                            );
+
+                            None
+                        };
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata {
+                            reaction_latency_ns,
+                            ..CycleMetadata::timed(cycle, loop_start, processed, tick_end, prev)
+                        });
+
+                        prev = tick_end;
+
+
+                        if cycles.is_aborted() {
+
+                            break;
+
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+async fn tx_rx_all(groups: &[Group<Op>], client: &Client<'_>) {
+    for group in groups {
+        group.tx_rx(client).await.expect("TX/RX");
+    }
+}
+
+fn read_input(group: &mut Group<Op>, client: &Client<'_>, bit: IoBit) -> bool {
+    let slave = group.iter(client).next().expect("Input device");
+
+    let inputs = slave.inputs_raw();
+
+    let byte = inputs.get(bit.bit / 8).expect("Input bit out of range");
+
+    byte & (1 << (bit.bit % 8)) != 0
+}
+
+fn write_output(group: &mut Group<Op>, client: &Client<'_>, bit: IoBit, value: bool) {
+    let mut slave = group.iter(client).next().expect("Output device");
+
+    let outputs = slave.outputs_raw_mut();
+
+    let byte = outputs
+        .get_mut(bit.bit / 8)
+        .expect("Output bit out of range");
+
+    if value {
+        *byte |= 1 << (bit.bit % 8);
+    } else {
+        *byte &= !(1 << (bit.bit % 8));
+    }
+}
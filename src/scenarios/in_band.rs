@@ -0,0 +1,76 @@
+//! A TX/RX task that records every frame it sends and receives with a [`FrameRecorder`], used in
+//! place of EtherCrab's `tx_rx_task` for in-band captures.
+//!
+//! It does the same work as EtherCrab's task on the same kind of socket, so timings stay
+//! comparable, but the numbers include the cost of recording.
+
+use super::raw_socket::{RawSocket, FRAME_BUF_LEN};
+use crate::capture::{self, FrameRecorder};
+use ethercrab::{error::Error, PduRx, PduTx};
+use smol::Async;
+use std::task::Poll;
+
+/// Send and receive frames for EtherCrab on a non-blocking `socket`, recording each one with
+/// `recorder`.
+pub async fn recorded_tx_rx(
+    socket: Async<RawSocket>,
+    mut tx: PduTx<'_>,
+    mut rx: PduRx<'_>,
+    recorder: FrameRecorder,
+) -> Result<(), Error> {
+    let mut send_buf = [0u8; FRAME_BUF_LEN];
+
+    let send = futures_lite::future::poll_fn(|ctx| {
+        tx.replace_waker(ctx.waker());
+
+        while let Some(frame) = tx.next_sendable_frame() {
+            let res = frame.send_blocking(&mut send_buf, |data| {
+                socket.get_ref().send(data).map_err(|e| {
+                    log::error!("Send frame failed: {}", e);
+
+                    Error::SendFrame
+                })?;
+
+                recorder.record(data);
+
+                Ok(data.len())
+            });
+
+            if let Err(e) = res {
+                return Poll::Ready(Err(e));
+            }
+        }
+
+        Poll::Pending
+    });
+
+    let receive = async {
+        let mut recv_buf = [0u8; FRAME_BUF_LEN];
+
+        loop {
+            let len = socket
+                .read_with(|socket| socket.recv(&mut recv_buf))
+                .await
+                .map_err(|e| {
+                    log::error!("Receive frame failed: {}", e);
+
+                    Error::ReceiveFrame
+                })?;
+
+            let frame = &recv_buf[0..len];
+
+            // The socket sees sent frames again, which are already recorded
+            if frame.get(6..12) != Some(&capture::MASTER_MAC) {
+                recorder.record(frame);
+            }
+
+            rx.receive_frame(frame).map_err(|e| {
+                log::error!("Failed to receive frame: {}", e);
+
+                Error::ReceiveFrame
+            })?;
+        }
+    };
+
+    futures_lite::future::race(send, receive).await
+}
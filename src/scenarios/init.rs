@@ -0,0 +1,150 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings, Transition,
+};
+use ethercrab::{self};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Single thread with TX/RX and one PDI loop, timing every state transition on the way to OP.
+///
+/// INIT -> PRE-OP covers EtherCrab's whole init, including DC static sync, so it's only recorded
+/// for the whole network. Every group with devices is then moved PRE-OP -> SAFE-OP -> OP one after
+/// the other and timed separately. INIT -> OP is the total cold start time. The first group then
+/// runs the usual cyclic loop so the first cycles after startup can be compared with other
+/// scenarios.
+pub fn single_thread_init(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut transitions = Vec::new();
+
+                    let init_start = Instant::now();
+
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    transitions.push(Transition {
+                        group_index: None,
+                        from: "INIT",
+                        to: "PRE-OP",
+                        duration_ns: init_start.elapsed().as_nanos() as u64,
+                    });
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let mut op_groups = Vec::new();
+
+                    for (index, group) in groups.into_iter().enumerate() {
+                        if group.is_empty() {
+                            continue;
+                        }
+
+                        let start = Instant::now();
+
+                        let group = group
+                            .into_safe_op(&client)
+                            .await
+                            .expect("PRE-OP -> SAFE-OP");
+
+                        let safe_op = Instant::now();
+
+                        let group = group.into_op(&client).await.expect("SAFE-OP -> OP");
+
+                        let op = Instant::now();
+
+                        transitions.push(Transition {
+                            group_index: Some(index as u8),
+                            from: "PRE-OP",
+                            to: "SAFE-OP",
+                            duration_ns: (safe_op - start).as_nanos() as u64,
+                        });
+
+                        transitions.push(Transition {
+                            group_index: Some(index as u8),
+                            from: "SAFE-OP",
+                            to: "OP",
+                            duration_ns: (op - safe_op).as_nanos() as u64,
+                        });
+
+                        op_groups.push(group);
+                    }
+
+                    transitions.push(Transition {
+                        group_index: None,
+                        from: "INIT",
+                        to: "OP",
+                        duration_ns: init_start.elapsed().as_nanos() as u64,
+                    });
+
+                    for transition in transitions.iter() {
+                        log::debug!(
+                            "Group {:?} {} -> {} took {} us",
+                            transition.group_index,
+                            transition.from,
+                            transition.to,
+                            transition.duration_ns / 1000
+                        );
+                    }
+
+                    let mut group = op_groups.swap_remove(0);
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    cycles.transitions = transitions;
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
@@ -0,0 +1,125 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self};
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Single thread with EtherCrab's TX/RX task and the cycle loop polled in turn by hand.
+///
+/// There's no executor scheduling between the two: each trip round the loop polls the cycle loop,
+/// which queues its frames, then the TX/RX task, which sends them and picks up any responses. The
+/// thread spins the whole time, including until the next tick, so this is a lower bound for a
+/// deployment with one core to give to EtherCAT.
+pub fn single_thread_interleaved(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        make_task_thread(settings)
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let mut tx_rx = pin!(tx_rx);
+
+                let mut groups = interleave(tx_rx.as_mut(), create_groups(settings, &client))?;
+
+                // The time it takes to traverse to the end of the EtherCAT network and back again.
+                let network_propagation_time_ns = groups
+                    .iter_mut()
+                    .flat_map(|group| group.iter(&client))
+                    .map(|device| device.propagation_delay())
+                    .max()
+                    .expect("Unable to compute prop time");
+
+                let [group, ..] = groups;
+
+                let mut group =
+                    interleave(tx_rx.as_mut(), group.into_op(&client)).expect("PRE-OP -> OP");
+
+                let cycle_time = Duration::from_micros(settings.cycle_time_us.into());
+
+                let iterations = settings.iterations(5000);
+
+                let cycles = interleave(tx_rx.as_mut(), async {
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    let mut next_tick = Instant::now() + cycle_time;
+                    let mut prev = Instant::now();
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        spin_until(next_tick).await;
+
+                        next_tick += cycle_time;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    cycles
+                });
+
+                Ok((cycles, network_propagation_time_ns))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Poll `future` then `net` in turn until `future` completes.
+fn interleave<N, F>(mut net: Pin<&mut N>, future: F) -> F::Output
+where
+    N: Future<Output = Result<(), ethercrab::error::Error>>,
+    F: Future,
+{
+    let mut future = pin!(future);
+
+    // Nothing needs waking: both futures are polled again after every trip round the loop
+    let mut ctx = Context::from_waker(Waker::noop());
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+            return output;
+        }
+
+        if let Poll::Ready(result) = net.as_mut().poll(&mut ctx) {
+            panic!("TX/RX task stopped: {:?}", result);
+        }
+    }
+}
+
+/// Pending until `deadline` has passed. Only completes if it's polled in a loop.
+async fn spin_until(deadline: Instant) {
+    futures_lite::future::poll_fn(|_| {
+        if Instant::now() >= deadline {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
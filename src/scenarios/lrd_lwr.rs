@@ -0,0 +1,179 @@
+use super::{
+    create_client, create_groups, make_task_thread, pin_task_thread, CycleMetadata, Cycles,
+    Storage, TestSettings,
+};
+use ethercrab::{self, error::Error, slave_group::Op, Client, Command, Reads, SlaveGroup};
+use futures_lite::StreamExt;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// First FMMU's registers.
+const FMMU_BASE: u16 = 0x0600;
+/// Size of each FMMU's registers.
+const FMMU_LEN: u16 = 16;
+/// Most FMMUs a device can have.
+const MAX_FMMUS: u16 = 16;
+/// Offsets of the registers used from each FMMU.
+const FMMU_LOGICAL_START: u16 = 0x0;
+const FMMU_LENGTH: u16 = 0x4;
+const FMMU_TYPE: u16 = 0xb;
+const FMMU_ACTIVATE: u16 = 0xc;
+/// FMMU types for areas the master reads from and writes to.
+const FMMU_TYPE_READ: u8 = 1;
+const FMMU_TYPE_WRITE: u8 = 2;
+
+/// Single thread with TX/RX and one PDI loop, exchanging the PDI with an LRD for inputs and an LWR
+/// for outputs instead of a single LRW.
+///
+/// Some topologies, e.g. with devices that don't support LRW, need the split. EtherCrab only sends
+/// LRW, so the group's logical input and output areas are read back from each device's FMMUs and
+/// exchanged here. Inputs are read, then outputs written, each in its own frame.
+pub fn single_thread_lrd_lwr(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let (inputs_area, outputs_area) = logical_areas(&mut group, &client).await?;
+
+                    log::debug!(
+                        "Logical inputs {:?}, outputs {:?}",
+                        inputs_area,
+                        outputs_area
+                    );
+
+                    let mut inputs = vec![0u8; area_len(&inputs_area)];
+                    let mut outputs = vec![0u8; area_len(&outputs_area)];
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        if let Some(area) = &inputs_area {
+                            let (data, _wkc) = Reads::Lrd {
+                                address: area.start,
+                            }
+                            .receive_slice(&client, inputs.len() as u16)
+                            .await
+                            .expect("LRD");
+
+                            inputs.copy_from_slice(&data);
+                        }
+
+                        // Increment every output byte by one, like `loop_tick`
+                        for byte in outputs.iter_mut() {
+                            *byte = byte.wrapping_add(1);
+                        }
+
+                        if let Some(area) = &outputs_area {
+                            Command::lwr(area.start)
+                                .send_slice(&client, &outputs)
+                                .await
+                                .expect("LWR");
+                        }
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Logical areas mapped to inputs and outputs by the FMMUs of every device in `group`. `None` if
+/// the group has nothing mapped in that direction.
+async fn logical_areas<const N: usize, const PDI: usize>(
+    group: &mut SlaveGroup<N, PDI, Op>,
+    client: &Client<'_>,
+) -> Result<(Option<Range<u32>>, Option<Range<u32>>), Error> {
+    let mut inputs: Option<Range<u32>> = None;
+    let mut outputs: Option<Range<u32>> = None;
+
+    for device in group.iter(client) {
+        for fmmu in 0..MAX_FMMUS {
+            let base = FMMU_BASE + fmmu * FMMU_LEN;
+
+            if device.register_read::<u8>(base + FMMU_ACTIVATE).await? & 1 == 0 {
+                continue;
+            }
+
+            let start = device
+                .register_read::<u32>(base + FMMU_LOGICAL_START)
+                .await?;
+            let len = device.register_read::<u16>(base + FMMU_LENGTH).await?;
+
+            let area = match device.register_read::<u8>(base + FMMU_TYPE).await? {
+                FMMU_TYPE_READ => &mut inputs,
+                FMMU_TYPE_WRITE => &mut outputs,
+                // Sync manager status FMMUs and read/write areas aren't used by EtherCrab
+                _ => continue,
+            };
+
+            let end = start + u32::from(len);
+
+            *area = Some(match area.take() {
+                Some(area) => area.start.min(start)..area.end.max(end),
+                None => start..end,
+            });
+        }
+    }
+
+    Ok((inputs, outputs))
+}
+
+fn area_len(area: &Option<Range<u32>>) -> usize {
+    area.as_ref().map_or(0, |area| area.len())
+}
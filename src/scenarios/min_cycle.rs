@@ -0,0 +1,171 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Group, MinCycleTime, Storage, TestSettings,
+};
+use ethercrab::{self, slave_group::Op, Client};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Shortest cycle time to try.
+const MIN_CYCLE_US: u32 = 5;
+
+/// Stop searching once the range left is this narrow.
+const RESOLUTION_US: u32 = 1;
+
+/// Cycles to run at each cycle time tried.
+const PROBE_CYCLES: usize = 2000;
+
+/// Cycles to run at the cycle time that's found, to confirm it and record its cycles.
+const CONFIRM_CYCLES: usize = 5000;
+
+/// Single thread with TX/RX and one PDI loop, binary searching for the shortest cycle time that
+/// keeps the fraction of deadline misses at or under `--max-miss-rate`.
+///
+/// The search starts from the configured cycle time, which has to be sustainable itself. The cycle
+/// time found is then run again for longer to confirm it, and those cycles are the ones recorded.
+/// If the configured cycle time isn't sustainable, its cycles are recorded with no result.
+pub fn min_cycle_time(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let max_miss_rate = settings.max_miss_rate.expect("No miss rate configured");
+
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut probes = 1;
+
+                    let start = probe(
+                        &mut group,
+                        &client,
+                        settings,
+                        settings.cycle_time_us,
+                        PROBE_CYCLES,
+                    )
+                    .await;
+
+                    if miss_rate(&start) > max_miss_rate {
+                        log::warn!(
+                            "--> {} us isn't sustainable, not searching for a shorter cycle time",
+                            settings.cycle_time_us
+                        );
+
+                        return Ok((start, network_propagation_time_ns));
+                    }
+
+                    // `fastest` is always sustainable, and nothing at or below `too_fast` is
+                    let mut fastest = settings.cycle_time_us;
+                    let mut too_fast = MIN_CYCLE_US.min(fastest) - 1;
+
+                    while fastest - too_fast > RESOLUTION_US {
+                        let cycle_time_us = too_fast + (fastest - too_fast) / 2;
+
+                        let cycles =
+                            probe(&mut group, &client, settings, cycle_time_us, PROBE_CYCLES).await;
+
+                        probes += 1;
+
+                        let rate = miss_rate(&cycles);
+
+                        log::debug!("{} us: miss rate {}", cycle_time_us, rate);
+
+                        if rate <= max_miss_rate {
+                            fastest = cycle_time_us;
+                        } else {
+                            too_fast = cycle_time_us;
+                        }
+                    }
+
+                    let mut cycles =
+                        probe(&mut group, &client, settings, fastest, CONFIRM_CYCLES).await;
+
+                    log::info!(
+                        "--> Minimum cycle time {} us after {} probes, confirmed with miss rate {}",
+                        fastest,
+                        probes,
+                        miss_rate(&cycles)
+                    );
+
+                    cycles.min_cycle_time = Some(MinCycleTime {
+                        cycle_time_us: fastest,
+                        max_miss_rate,
+                        probes,
+                    });
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Run `iterations` cycles at `cycle_time_us`.
+async fn probe(
+    group: &mut Group<Op>,
+    client: &Client<'_>,
+    settings: &TestSettings,
+    cycle_time_us: u32,
+    iterations: usize,
+) -> Cycles {
+    // Deadline misses are relative to this run's cycle time, and are expected while searching
+    let settings = TestSettings {
+        cycle_time_us,
+        abort_miss_rate: None,
+        ..settings.clone()
+    };
+
+    let mut tick = smol::Timer::interval(Duration::from_micros(cycle_time_us.into()));
+    let mut prev = Instant::now();
+
+    let mut cycles = Cycles::new(&settings, iterations);
+
+    for cycle in 0..iterations {
+        let loop_start = Instant::now();
+
+        loop_tick(group, client).await;
+
+        let processed = Instant::now();
+
+        tick.next().await;
+
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
+
+        prev = tick_end;
+    }
+
+    cycles
+}
+
+fn miss_rate(cycles: &Cycles) -> f64 {
+    cycles.summary.deadline_misses as f64 / cycles.summary.count().max(1) as f64
+}
@@ -0,0 +1,151 @@
+use super::{
+    create_client, create_groups, loop_tick, make_net_thread, make_task_thread, pin_net_thread,
+    pin_task_thread, CycleMetadata, Cycles, Group, Storage, TestSettings,
+};
+use ethercrab::{self, Client};
+use futures_lite::{future, StreamExt};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Cycle time of the fast, high priority loop.
+const FAST_CYCLE_US: u32 = 100;
+
+/// Cycle time of the slow, low priority loop.
+const SLOW_CYCLE_US: u32 = 1000;
+
+/// How far below the task priority the slow loop's thread runs.
+const SLOW_PRIO_STEP: u8 = 10;
+
+/// How long both loops run for.
+const RUN_TIME: Duration = Duration::from_millis(500);
+
+/// 1 tx/rx thread and 2 task threads: a fast loop at the task priority every 100 µs and a slow loop
+/// at a lower priority every 1 ms, each exchanging its own group.
+///
+/// Both loops run for the same length of time. Each cycle's `group_index` says which loop it came
+/// from: 0 for fast and 1 for slow. The fast loop's jitter shouldn't change with the slow loop
+/// running if priorities isolate them properly. The `--cycle-times` axis doesn't apply.
+pub fn mixed_prio(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let fast_settings = TestSettings {
+        cycle_time_us: FAST_CYCLE_US,
+        ..settings.clone()
+    };
+
+    let slow_settings = TestSettings {
+        cycle_time_us: SLOW_CYCLE_US,
+        // 0 means no RT priority, so leave it that way, otherwise stay RT
+        task_prio: match settings.task_prio {
+            0 => 0,
+            prio => prio.saturating_sub(SLOW_PRIO_STEP).max(1),
+        },
+        ..settings.clone()
+    };
+
+    let storage = Storage::new(settings);
+
+    let (client, tx_rx) = create_client(settings, &storage);
+
+    std::thread::scope(|s| {
+        let client = Arc::new(client);
+
+        let (net_tx, net_rx) = smol::channel::bounded(1);
+
+        make_net_thread(settings)
+            .spawn_scoped(s, move |_| {
+                pin_net_thread(settings);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                futures_lite::future::block_on(local_ex.run(future::or(tx_rx, async {
+                    net_rx.recv().await.ok();
+
+                    Ok(())
+                })))
+            })
+            .expect("TX/RX thread");
+
+        let mut groups = smol::block_on(create_groups(settings, &client))?;
+
+        // The time it takes to traverse to the end of the EtherCAT network and back again.
+        let network_propagation_time_ns = groups
+            .iter_mut()
+            .flat_map(|group| group.iter(&client))
+            .map(|device| device.propagation_delay())
+            .max()
+            .expect("Unable to compute prop time");
+
+        let [fast_group, slow_group, ..] = groups;
+
+        let handles = [(fast_group, &fast_settings), (slow_group, &slow_settings)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, (group, settings))| {
+                let client = client.clone();
+
+                make_task_thread(settings)
+                    .spawn_scoped_careless(s, move || {
+                        pin_task_thread(settings);
+
+                        let local_ex = smol::LocalExecutor::new();
+
+                        futures_lite::future::block_on(local_ex.run(task(
+                            group,
+                            &client,
+                            settings,
+                            index as u8,
+                        )))
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Cycles>();
+
+        // Stop net thread. Scoped thread hangs waiting on net task to join otherwise.
+        net_tx.send_blocking(()).ok();
+
+        Ok((results, network_propagation_time_ns))
+    })
+}
+
+async fn task(group: Group, client: &Client<'_>, settings: &TestSettings, index: u8) -> Cycles {
+    let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
+
+    let cycle_time = Duration::from_micros(settings.cycle_time_us.into());
+    let mut tick = smol::Timer::interval(cycle_time);
+    let mut prev = Instant::now();
+
+    let iterations = (RUN_TIME.as_micros() / cycle_time.as_micros()) as usize;
+
+    let mut cycles = Cycles::new(settings, iterations);
+
+    for cycle in 0..iterations {
+        let loop_start = Instant::now();
+
+        loop_tick(&mut group, client).await;
+
+        let processed = Instant::now();
+
+        tick.next().await;
+
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata {
+            group_index: Some(index),
+            ..CycleMetadata::timed(cycle, loop_start, processed, tick_end, prev)
+        });
+
+        prev = tick_end;
+
+        if cycles.is_aborted() {
+            break;
+        }
+    }
+
+    cycles
+}
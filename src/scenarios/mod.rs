@@ -1,42 +1,134 @@
 //! Different application scenarios to (hopefully) represent somewhat realistic scenarios.
 
+mod async_std;
+mod composed;
+mod compute;
+mod dc;
+mod dc_drift;
+mod doorbell;
+mod hil;
+mod in_band;
+mod init;
+mod interleaved;
+mod lrd_lwr;
+mod min_cycle;
+mod mixed_prio;
+mod multi_pdu;
+mod nanosleep;
+mod null;
+mod numa;
+mod pdi;
+mod pipeline;
+mod poll_mode;
+mod raw_socket;
+mod sdo;
+mod single_group;
 mod single_thread;
 mod single_thread_10_tasks;
 mod single_thread_2_tasks;
 mod smol;
+mod soak;
+mod spin;
+mod split_tx_rx;
+mod thread_per_core;
 mod thread_per_task;
 mod tokio;
 mod two_threads_10_tasks;
-
+mod unplug;
+mod uring;
+mod xdp;
+
+use self::async_std::{async_std_single_thread, async_std_two_threads};
+use crate::{
+    busy_poll::BusyPoll,
+    capture::{self, CaptureMode, CaptureOptions, CaptureStats},
+    cycle_stream,
+    ethercrab_events::{self, EventSite},
+    frame_stream::{self, StreamedFrames},
+    kernel_probes::{self, KernelFrame},
+    live::{self, LiveEvent},
+    load::{self, NetworkLoad},
+    memory_lock::{prefault_stack, MemoryLock},
+    otel, perf,
+    phc::{self, PhcOffset},
+    sched_trace,
+    stats::{Aggregate, CycleSummary},
+};
+use ::smol::Async;
 use chrono::{DateTime, Utc};
+use composed::composed;
+pub use composed::{Composition, CompositionSpec};
+use compute::single_thread_compute;
+use dc::single_thread_dc;
+use dc_drift::single_thread_dc_drift;
 use ethercrab::{
     slave_group::{Op, PreOp},
-    Client, ClientConfig, PduStorage, RetryBehaviour, SlaveGroup, Timeouts,
+    Client, ClientConfig, PduLoop, PduRx, PduStorage, PduTx, RetryBehaviour, SlaveGroup, Timeouts,
 };
+use futures::future::Either;
+use hil::hil;
+pub use hil::{HilWiring, IoBit};
+use init::single_thread_init;
+use interleaved::single_thread_interleaved;
+use lrd_lwr::single_thread_lrd_lwr;
+use min_cycle::min_cycle_time;
+use mixed_prio::mixed_prio;
+use multi_pdu::single_thread_multi_pdu;
+use nanosleep::single_thread_nanosleep;
+use null::null;
+pub use null::NULL_SCENARIO;
+use numa::{two_threads_numa_local, two_threads_numa_remote};
+use pdi::single_thread_pdi;
+use pipeline::pipeline;
+use poll_mode::single_thread_poll;
+use raw_socket::RawSocket;
+use regex::Regex;
+use sdo::single_thread_sdo;
+use single_group::single_group;
 use single_thread::single_thread;
 use single_thread_10_tasks::single_thread_10_tasks;
 use single_thread_2_tasks::single_thread_2_tasks;
 use smol::smol_default;
+use soak::soak;
+pub use soak::SOAK_SCENARIO;
+use spin::single_thread_spin;
+use split_tx_rx::split_tx_rx;
 use std::{
-    fs,
+    collections::{BTreeMap, VecDeque},
+    fmt, fs,
     future::Future,
+    io,
     path::PathBuf,
-    process::Stdio,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
     time::{Duration, Instant},
 };
+use thread_per_core::thread_per_core;
+use thread_per_task::custom_threads;
 use thread_per_task::eleven_threads;
 use thread_per_task::three_threads;
 use thread_per_task::two_threads;
-use thread_priority::{ThreadBuilder, ThreadPriority, ThreadSchedulePolicy};
+use thread_priority::{DeadlineFlags, ThreadBuilder, ThreadPriority, ThreadSchedulePolicy};
 use tokio::tokio_default;
 use two_threads_10_tasks::two_threads_10_tasks;
+use unplug::single_thread_unplug;
+use uring::{two_threads_uring, URING_SCENARIO};
+use xdp::two_threads_xdp;
+pub use xdp::XDP_SCENARIO;
 
 /// Maximum number of slaves that can be stored. This must be a power of 2 greater than 1.
 const MAX_SLAVES: usize = 16;
 /// Maximum PDU data payload size - set this to the max PDI size or higher.
 const MAX_PDU_DATA: usize = 1100;
-/// Maximum number of EtherCAT frames that can be in flight at any one time.
-const MAX_FRAMES: usize = 64;
+/// Maximum number of EtherCAT frames that can be in flight at any one time, with the largest
+/// [`StorageSize`].
+const MAX_FRAMES: usize = 128;
+/// Number of groups devices are split between.
+const GROUPS: usize = 10;
+
+/// `SCHED_DEADLINE` threads get this fraction of each cycle as their runtime budget. Kept small so
+/// every thread in the biggest scenarios passes the kernel's admission control.
+const DEADLINE_RUNTIME_DIVISOR: u32 = 4;
 
 pub const DUMPS_PATH: &str = "./dumps";
 
@@ -45,6 +137,28 @@ pub struct TestSettings {
     /// Ethernet NIC, e.g. `enp2s0`.
     pub nic: String,
 
+    /// Interface frames were captured on, if they were. The same as `nic` unless traffic is
+    /// mirrored to a separate NIC.
+    pub capture_interface: Option<String>,
+
+    /// Whether NIC hardware receive timestamps are recorded in captures, where supported.
+    pub hardware_timestamps: bool,
+
+    /// How many times to re-run a scenario whose capture failed.
+    pub capture_retries: u32,
+
+    /// Whether frames are recorded by the TX/RX task instead of captured from the NIC.
+    pub in_band_capture: bool,
+
+    /// tcpdump filter expression captures are limited to. EtherCAT frames only if not set.
+    pub capture_filter: Option<String>,
+
+    /// Most bytes of each frame captured.
+    pub capture_snaplen: usize,
+
+    /// Whether frames are paired while each run is captured instead of at ingest.
+    pub stream_frames: bool,
+
     /// Machine hostname.
     pub hostname: String,
 
@@ -60,18 +174,451 @@ pub struct TestSettings {
     /// If RT is enabled, this is the priority to set for thread(s) that handle PDI tasks.
     pub task_prio: u8,
 
+    /// Scheduling policy for scenario threads, if RT is enabled.
+    pub policy: SchedPolicy,
+
+    /// EtherCrab PDU retry policy.
+    pub retries: Retries,
+
+    /// EtherCrab PDU storage size.
+    pub storage: StorageSize,
+
+    /// How devices are split between groups.
+    pub group_strategy: GroupStrategy,
+
+    /// Whether EtherCrab's trace logging is on, to measure its overhead.
+    pub trace_logging: bool,
+
+    /// Whether process memory is locked and thread stacks prefaulted, to measure the cost of page
+    /// faults.
+    pub lock_memory: bool,
+
+    /// If RT is enabled, the priority to set for the RX thread in scenarios that send and receive
+    /// on separate threads. Uses `net_prio` if not set.
+    pub rx_prio: Option<u8>,
+
     /// Cycle time in microseconds.
     pub cycle_time_us: u32,
 
     /// Optional list of tags the user wants to attach to this set of scenarios.
     pub tags: Vec<String>,
+
+    /// Cycles to run each scenario for instead of its own default, if set. Scenarios that run for a
+    /// fixed time or search for a cycle time ignore it.
+    pub iterations: Option<usize>,
+
+    /// Idle time before each run, in seconds.
+    pub cooldown_secs: u64,
+
+    /// Whether repeats of this run were interleaved with other runs or back to back.
+    pub repeat_mode: RepeatMode,
+
+    /// Seed runs were shuffled with, if they were.
+    pub shuffle_seed: Option<u64>,
+
+    /// Number of cycles at the start of each scenario, or each of its tasks, that aren't recorded.
+    pub warmup_cycles: usize,
+
+    /// Stop a run early once more than this fraction of the last `abort_window` cycles missed their
+    /// deadline.
+    pub abort_miss_rate: Option<f64>,
+
+    /// Number of recent cycles `abort_miss_rate` is measured over.
+    pub abort_window: usize,
+
+    /// Only keep streaming statistics for each run instead of every individual cycle.
+    pub summary_only: bool,
+
+    /// Number of static drift compensation iterations run during init.
+    ///
+    /// Init, including this, runs again for every repeat so lowering it makes short scenarios
+    /// much quicker to repeat.
+    pub dc_static_sync_iterations: u32,
+
+    /// Store one in every this many cycles, plus aggregates of the skipped ones. `1` stores every
+    /// cycle.
+    pub cycle_sample: usize,
+
+    /// EtherCrab git revision under test.
+    pub ethercrab_rev: String,
+
+    /// Interval to sample the NIC's PHC offset at, if enabled.
+    pub phc_offset_ms: Option<u64>,
+
+    /// Count scheduler and cache events during each run.
+    pub perf_counters: bool,
+
+    /// Time frames through the kernel network stack during each run.
+    pub kernel_probes: bool,
+
+    /// Trace scheduler events, keeping this many microseconds either side of each deadline miss.
+    pub sched_trace_window_us: Option<u64>,
+
+    /// Loopback wiring for the HIL scenario. It's skipped if this isn't set.
+    pub hil: Option<HilWiring>,
+
+    /// CPUs to pin scenario threads to. Threads can run anywhere if this isn't set.
+    pub affinity: Option<CoreAffinity>,
+
+    /// Thread and task counts for the `custom_threads` scenario. It's skipped if this isn't set.
+    pub thread_layout: Option<ThreadLayout>,
+
+    /// Scenarios described in the run matrix file.
+    #[serde(skip)]
+    pub compositions: Vec<Composition>,
+
+    /// Which of `compositions` this run is, if it's one of them.
+    pub composition: Option<Composition>,
+
+    /// Deadline miss rate the minimum cycle time search allows, as a fraction of cycles. The search
+    /// is skipped if this isn't set.
+    pub max_miss_rate: Option<f64>,
+
+    /// How long the soak scenario runs for, in seconds. It's skipped if this isn't set.
+    pub soak_secs: Option<u64>,
+
+    /// Busy work done each cycle by the compute scenario. It's skipped if this isn't set.
+    pub compute_us: Option<u32>,
+
+    /// Run the io_uring TX/RX scenario. It's skipped if this isn't set.
+    pub io_uring: bool,
+
+    /// Run the AF_XDP TX/RX scenario. It's skipped if this isn't set.
+    pub xdp: bool,
+
+    /// Busy poll for packets for up to this many microseconds instead of waiting for interrupts, if
+    /// set.
+    pub busy_poll_us: Option<u32>,
+
+    /// Background traffic to send during each run, if any.
+    pub network_load: Option<NetworkLoad>,
+}
+
+/// Task thread and task counts for the `custom_threads` scenario.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ThreadLayout {
+    pub task_threads: usize,
+
+    /// Spread evenly over the task threads. At most one per group.
+    pub tasks: usize,
+
+    /// Scenario name, in the same form as the presets so matching layouts share a name in the
+    /// database.
+    #[serde(skip)]
+    name: &'static str,
+}
+
+impl ThreadLayout {
+    /// `threads` includes the TX/RX thread, like the scenario names. Returns `None` if any task
+    /// thread would have no tasks or there are more tasks than groups.
+    pub fn new(threads: usize, tasks: usize) -> Option<Self> {
+        let task_threads = threads.checked_sub(1)?;
+
+        if task_threads == 0 || tasks < task_threads || tasks > GROUPS {
+            return None;
+        }
+
+        // Only made once per suite, from the command line
+        let name = Box::leak(format!("{}thr-{}task", threads, tasks).into_boxed_str());
+
+        Some(Self {
+            task_threads,
+            tasks,
+            name,
+        })
+    }
+}
+
+/// Scheduling policy for scenario threads on RT kernels.
+#[derive(clap::ValueEnum, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+    /// `SCHED_FIFO` at the net and task priorities.
+    #[default]
+    Fifo,
+    /// `SCHED_RR` at the net and task priorities. Threads at the same priority take turns instead
+    /// of running until they block.
+    #[value(name = "rr")]
+    #[serde(rename = "rr")]
+    RoundRobin,
+    /// `SCHED_DEADLINE` with a period and deadline of one cycle. Priorities are ignored.
+    Deadline,
+}
+
+impl SchedPolicy {
+    /// Appended to run slugs. Empty for FIFO so slugs from before policies were added still match.
+    fn slug(&self) -> &'static str {
+        match self {
+            SchedPolicy::Fifo => "",
+            SchedPolicy::RoundRobin => "-rr",
+            SchedPolicy::Deadline => "-dl",
+        }
+    }
+}
+
+/// Order repeats of each run are made in.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatMode {
+    /// Run the whole matrix, then run it again, so repeats are far apart.
+    #[default]
+    Interleave,
+    /// Repeat each run back to back, so repeats share warm caches.
+    Batch,
+}
+
+/// EtherCrab's PDU retry policy.
+#[derive(clap::ValueEnum, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Retries {
+    /// Fail on the first PDU timeout.
+    None,
+    /// Resend a timed out PDU up to twice.
+    #[default]
+    #[value(name = "count2")]
+    #[serde(rename = "count2")]
+    Count2,
+    /// Resend timed out PDUs until they get a response.
+    Forever,
+}
+
+impl Retries {
+    /// Appended to run slugs. Empty for two retries so slugs from before retries were configurable
+    /// still match.
+    fn slug(&self) -> &'static str {
+        match self {
+            Retries::None => "-rtnone",
+            Retries::Count2 => "",
+            Retries::Forever => "-rtforever",
+        }
+    }
+
+    fn behaviour(&self) -> RetryBehaviour {
+        match self {
+            Retries::None => RetryBehaviour::None,
+            Retries::Count2 => RetryBehaviour::Count(2),
+            Retries::Forever => RetryBehaviour::Forever,
+        }
+    }
+}
+
+/// How discovered devices are split between the [`GROUPS`] groups.
+///
+/// Each group is one LRW per cycle, so this decides how many frames are sent.
+#[derive(serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(into = "String")]
+pub enum GroupStrategy {
+    /// One device per group, wrapping round if there are more devices than groups.
+    #[default]
+    RoundRobin,
+    /// Every device in the first group.
+    AllInOne,
+    /// One group per vendor ID, in the order vendors are found.
+    ByVendor,
+    /// Consecutive devices in groups of this many.
+    Chunked(usize),
+}
+
+impl GroupStrategy {
+    /// Appended to run slugs. Empty for round robin so slugs from before this was configurable
+    /// still match.
+    fn slug(&self) -> String {
+        match self {
+            GroupStrategy::RoundRobin => String::new(),
+            GroupStrategy::AllInOne => String::from("-grpone"),
+            GroupStrategy::ByVendor => String::from("-grpvendor"),
+            GroupStrategy::Chunked(n) => format!("-grpchunk{}", n),
+        }
+    }
+}
+
+impl fmt::Display for GroupStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupStrategy::RoundRobin => f.write_str("round-robin"),
+            GroupStrategy::AllInOne => f.write_str("all-in-one"),
+            GroupStrategy::ByVendor => f.write_str("by-vendor"),
+            GroupStrategy::Chunked(n) => write!(f, "chunked-{}", n),
+        }
+    }
+}
+
+impl FromStr for GroupStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(GroupStrategy::RoundRobin),
+            "all-in-one" => Ok(GroupStrategy::AllInOne),
+            "by-vendor" => Ok(GroupStrategy::ByVendor),
+            _ => s
+                .strip_prefix("chunked-")
+                .and_then(|n| n.parse().ok())
+                .filter(|n| *n > 0)
+                .map(GroupStrategy::Chunked)
+                .ok_or_else(|| {
+                    format!(
+                        "expected round-robin, all-in-one, by-vendor or chunked-<n>, got {:?}",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl From<GroupStrategy> for String {
+    fn from(strategy: GroupStrategy) -> Self {
+        strategy.to_string()
+    }
+}
+
+/// How many frames EtherCrab's PDU storage can have in flight, and how big each PDU can be.
+#[derive(clap::ValueEnum, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(into = "StorageSizes")]
+pub enum StorageSize {
+    /// 16 frames of 1100 bytes.
+    Small,
+    /// 64 frames of 1100 bytes.
+    #[default]
+    Medium,
+    /// 128 frames, each as big as a standard Ethernet frame allows.
+    Large,
+}
+
+/// [`StorageSize`] as stored in run settings.
+#[derive(serde::Serialize)]
+struct StorageSizes {
+    preset: &'static str,
+    max_frames: usize,
+    max_pdu_data: usize,
+}
+
+impl From<StorageSize> for StorageSizes {
+    fn from(size: StorageSize) -> Self {
+        let (max_frames, max_pdu_data) = size.sizes();
+
+        Self {
+            preset: size.name(),
+            max_frames,
+            max_pdu_data,
+        }
+    }
+}
+
+impl StorageSize {
+    const SMALL: (usize, usize) = (16, MAX_PDU_DATA);
+    const MEDIUM: (usize, usize) = (64, MAX_PDU_DATA);
+    /// 1500 byte MTU minus the EtherCAT, PDU and working counter headers.
+    const LARGE: (usize, usize) = (MAX_FRAMES, 1486);
+
+    fn name(&self) -> &'static str {
+        match self {
+            StorageSize::Small => "small",
+            StorageSize::Medium => "medium",
+            StorageSize::Large => "large",
+        }
+    }
+
+    /// Max frames in flight and max PDU data length.
+    fn sizes(&self) -> (usize, usize) {
+        match self {
+            StorageSize::Small => Self::SMALL,
+            StorageSize::Medium => Self::MEDIUM,
+            StorageSize::Large => Self::LARGE,
+        }
+    }
+
+    /// Appended to run slugs. Empty for medium storage so slugs from before storage was
+    /// configurable still match.
+    fn slug(&self) -> &'static str {
+        match self {
+            StorageSize::Small => "-stsmall",
+            StorageSize::Medium => "",
+            StorageSize::Large => "-stlarge",
+        }
+    }
+}
+
+/// PDU storage of the size given by [`TestSettings::storage`].
+///
+/// EtherCrab's storage sizes are const generics, but a split storage's halves aren't generic, so
+/// scenarios can pick a size at runtime through this.
+#[allow(clippy::large_enum_variant)]
+enum Storage {
+    Small(PduStorage<{ StorageSize::SMALL.0 }, { StorageSize::SMALL.1 }>),
+    Medium(PduStorage<{ StorageSize::MEDIUM.0 }, { StorageSize::MEDIUM.1 }>),
+    Large(PduStorage<{ StorageSize::LARGE.0 }, { StorageSize::LARGE.1 }>),
+}
+
+impl Storage {
+    fn new(settings: &TestSettings) -> Self {
+        match settings.storage {
+            StorageSize::Small => Storage::Small(PduStorage::new()),
+            StorageSize::Medium => Storage::Medium(PduStorage::new()),
+            StorageSize::Large => Storage::Large(PduStorage::new()),
+        }
+    }
+
+    fn try_split(&self) -> Result<(PduTx<'_>, PduRx<'_>, PduLoop<'_>), ()> {
+        match self {
+            Storage::Small(storage) => storage.try_split(),
+            Storage::Medium(storage) => storage.try_split(),
+            Storage::Large(storage) => storage.try_split(),
+        }
+    }
+}
+
+/// Which CPUs the net and task threads are allowed to run on.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct CoreAffinity {
+    pub net: usize,
+    /// Task threads share all of these CPUs.
+    pub task: Vec<usize>,
+}
+
+impl CoreAffinity {
+    /// Put the net thread on the first isolated CPU and task threads on the rest.
+    ///
+    /// Returns `None` if there are fewer than 2 isolated CPUs.
+    pub fn from_isolated(cpus: &[usize]) -> Option<Self> {
+        match cpus {
+            [net, task @ ..] if !task.is_empty() => Some(Self {
+                net: *net,
+                task: task.to_vec(),
+            }),
+            _ => None,
+        }
+    }
 }
 
 impl TestSettings {
+    /// Number of cycles to run, `default` unless overridden.
+    pub fn iterations(&self, default: usize) -> usize {
+        self.iterations.unwrap_or(default)
+    }
+
+    pub fn capture_options(&self) -> CaptureOptions<'_> {
+        CaptureOptions {
+            hardware_timestamps: self.hardware_timestamps,
+            filter: self.capture_filter.as_deref(),
+            snaplen: self.capture_snaplen,
+        }
+    }
+
     /// Get a hyphenated slug to insert into a filename, test name, etc.
     pub fn slug(&self) -> String {
         format!(
-            "{}-{}-tadm-{}-etht-{}-{}-n{}-t{}-{}us",
+            "{}-{}-tadm-{}-etht-{}-{}-n{}-t{}{}{}-{}us{}{}{}{}{}{}{}{}",
             self.nic,
             if self.is_rt { "rt" } else { "nort" },
             self.tuned_adm_profile,
@@ -79,19 +626,63 @@ impl TestSettings {
             self.ethtool_settings.1,
             self.net_prio,
             self.task_prio,
-            self.cycle_time_us
+            self.rx_prio
+                .map(|prio| format!("-rx{}", prio))
+                .unwrap_or_default(),
+            self.policy.slug(),
+            self.cycle_time_us,
+            if self.affinity.is_some() { "-iso" } else { "" },
+            self.busy_poll_us
+                .map(|us| format!("-bp{}", us))
+                .unwrap_or_default(),
+            self.network_load
+                .as_ref()
+                .map(|load| format!("-load{}", load.mbit_per_sec))
+                .unwrap_or_default(),
+            self.retries.slug(),
+            self.storage.slug(),
+            self.group_strategy.slug(),
+            if self.trace_logging { "-trace" } else { "" },
+            if self.lock_memory { "-mlock" } else { "" }
         )
     }
 }
 
 /// Create an EtherCrab client and TX/RX task ready to be used and spawned respectively.
 fn create_client<'sto>(
-    ethercat_nic: &str,
-    storage: &'sto PduStorage<MAX_FRAMES, MAX_PDU_DATA>,
+    settings: &TestSettings,
+    storage: &'sto Storage,
 ) -> (
     Client<'sto>,
     impl Future<Output = Result<(), ethercrab::error::Error>> + 'sto,
 ) {
+    let (client, tx, rx) = create_client_raw(settings, storage);
+
+    // Taken once here so the task doesn't look for the capture on every frame
+    let recorder = settings
+        .in_band_capture
+        .then(capture::in_band_recorder)
+        .flatten();
+
+    let tx_rx_task = if let Some(recorder) = recorder {
+        let socket = RawSocket::open(&settings.nic, libc::SOCK_NONBLOCK)
+            .and_then(Async::new)
+            .expect("In-band TX/RX socket");
+
+        Either::Left(in_band::recorded_tx_rx(socket, tx, rx, recorder))
+    } else {
+        Either::Right(ethercrab::std::tx_rx_task(&settings.nic, tx, rx).expect("Spawn"))
+    };
+
+    (client, tx_rx_task)
+}
+
+/// Like [`create_client`] but returns the TX and RX halves of the PDU loop for scenarios that
+/// drive the network interface themselves.
+fn create_client_raw<'sto>(
+    settings: &TestSettings,
+    storage: &'sto Storage,
+) -> (Client<'sto>, PduTx<'sto>, PduRx<'sto>) {
     let (tx, rx, pdu_loop) = storage.try_split().expect("Split");
 
     let client = Client::new(
@@ -105,39 +696,65 @@ fn create_client<'sto>(
             ..Timeouts::default()
         },
         ClientConfig {
-            dc_static_sync_iterations: 1000,
-            retry_behaviour: RetryBehaviour::Count(2),
-            ..ClientConfig::default()
+            dc_static_sync_iterations: settings.dc_static_sync_iterations,
+            retry_behaviour: settings.retries.behaviour(),
         },
     );
 
-    let tx_rx_task = ethercrab::std::tx_rx_task(ethercat_nic, tx, rx).expect("Spawn");
-
-    (client, tx_rx_task)
+    (client, tx, rx)
 }
 
-type Group<S = PreOp> = SlaveGroup<1, 16, S>;
-type Groups = [Group; 10];
+/// Maximum PDI size of each of the [`GROUPS`] groups, big enough for every device to be in one.
+const GROUP_PDI: usize = 256;
 
-/// Create a list of groups from discovered devices.
-///
-/// Each group may only have one device, with a PDI of up to 16 bytes.
-async fn create_groups(client: &Client<'_>) -> Result<Groups, ethercrab::error::Error> {
+type Group<S = PreOp> = SlaveGroup<MAX_SLAVES, GROUP_PDI, S>;
+type Groups = [Group; GROUPS];
+
+/// Maximum PDI size of a group holding every device.
+const SINGLE_GROUP_PDI: usize = 1024;
+
+type SingleGroup<S = PreOp> = SlaveGroup<MAX_SLAVES, SINGLE_GROUP_PDI, S>;
+
+/// Create a list of groups from discovered devices, assigned with [`TestSettings::group_strategy`].
+async fn create_groups(
+    settings: &TestSettings,
+    client: &Client<'_>,
+) -> Result<Groups, ethercrab::error::Error> {
     let mut index = 0;
+    let mut vendors = Vec::new();
 
     client
-        .init::<MAX_SLAVES, _>(|groups: &Groups, _slave| {
-            let g = &groups[index % groups.len()];
+        .init::<MAX_SLAVES, _>(|groups: &Groups, slave| {
+            let group = match settings.group_strategy {
+                GroupStrategy::RoundRobin => index,
+                GroupStrategy::AllInOne => 0,
+                GroupStrategy::ByVendor => {
+                    let vendor = slave.identity().vendor_id;
+
+                    vendors
+                        .iter()
+                        .position(|v| *v == vendor)
+                        .unwrap_or_else(|| {
+                            vendors.push(vendor);
+
+                            vendors.len() - 1
+                        })
+                }
+                GroupStrategy::Chunked(n) => index / n,
+            };
 
             index += 1;
 
-            Ok(g)
+            Ok(&groups[group % groups.len()])
         })
         .await
 }
 
 /// A single tick for a single group.
-async fn loop_tick(group: &mut Group<Op>, client: &Client<'_>) {
+async fn loop_tick<const N: usize, const PDI: usize>(
+    group: &mut SlaveGroup<N, PDI, Op>,
+    client: &Client<'_>,
+) {
     group.tx_rx(client).await.expect("TX/RX");
 
     // Increment every output byte for every slave device by one
@@ -165,12 +782,386 @@ pub struct CycleMetadata {
 
     /// Cycle number, starting from zero.
     pub cycle: usize,
+
+    /// Time from sending an output change to seeing it on a wired input, for the cycle the change
+    /// was seen in. Only recorded by the HIL scenario.
+    pub reaction_latency_ns: Option<u32>,
+
+    /// Time since the last SYNC0 edge when the cycle woke, according to the DC reference clock.
+    /// Only recorded by the DC scenario.
+    pub dc_offset_ns: Option<u32>,
+
+    /// DC system time difference furthest from zero across every device synchronised to the
+    /// reference clock. Negative when a device's clock is behind. Only recorded by the DC drift
+    /// scenario.
+    pub dc_drift_ns: Option<i32>,
+
+    /// Which of the scenario's groups this cycle exchanged, for scenarios that run groups at
+    /// different rates or priorities.
+    pub group_index: Option<u8>,
+}
+
+/// A deadline miss, kept with enough context to investigate it.
+#[derive(Debug, Clone)]
+pub struct Spike {
+    pub cycle: usize,
+
+    pub cycle_time_delta_ns: u32,
+
+    /// `CLOCK_MONOTONIC` time the miss was detected at.
+    pub time_ns: u64,
+
+    /// Thread that most often took the CPU from a scenario thread around the miss, if traced.
+    pub preempted_by: Option<String>,
+
+    /// Scheduler events around the miss, if traced.
+    pub sched_trace: Option<String>,
+}
+
+/// Time taken to move devices from one state to another.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// Group that was transitioned, or `None` for the whole network.
+    pub group_index: Option<u8>,
+
+    pub from: &'static str,
+
+    pub to: &'static str,
+
+    pub duration_ns: u64,
+}
+
+/// Shortest cycle time found to keep deadline misses under a threshold.
+#[derive(Debug, Clone)]
+pub struct MinCycleTime {
+    pub cycle_time_us: u32,
+
+    /// Largest fraction of cycles allowed to miss their deadline.
+    pub max_miss_rate: f64,
+
+    /// Number of cycle times tried to find it.
+    pub probes: u32,
+}
+
+/// Most spikes to keep for a single run, so a badly misconfigured machine doesn't record one for
+/// every cycle.
+const MAX_SPIKES: usize = 10_000;
+
+/// Set once any task of the current run goes over its abort threshold, so every task stops.
+static ABORTED: AtomicBool = AtomicBool::new(false);
+
+impl CycleMetadata {
+    /// Timings of a scenario loop iteration from when it started, when process data had been
+    /// exchanged, when the tick fired, and when the previous tick fired. Values only some scenarios
+    /// record are `None`.
+    pub fn timed(
+        cycle: usize,
+        loop_start: Instant,
+        processed: Instant,
+        tick_end: Instant,
+        prev: Instant,
+    ) -> Self {
+        Self {
+            cycle,
+            processing_time_ns: (processed - loop_start).as_nanos() as u32,
+            tick_wait_ns: (tick_end - processed).as_nanos() as u32,
+            cycle_time_delta_ns: (tick_end - prev).as_nanos() as u32,
+            reaction_latency_ns: None,
+            dc_offset_ns: None,
+            dc_drift_ns: None,
+            group_index: None,
+        }
+    }
+
+    /// Whether this cycle took more than twice as long as it was meant to.
+    pub fn is_deadline_miss(&self, cycle_time_ns: u32) -> bool {
+        self.cycle_time_delta_ns > cycle_time_ns.saturating_mul(2)
+    }
+}
+
+/// Exact aggregates for every cycle in a sampling bucket, including those that weren't stored.
+#[derive(Debug, Clone, Default)]
+pub struct CycleBucket {
+    /// Number of the first cycle in this bucket.
+    pub first_cycle: usize,
+
+    /// Group every cycle in this bucket exchanged, for scenarios that record it.
+    pub group_index: Option<u8>,
+
+    pub processing_time: Aggregate,
+    pub tick_wait: Aggregate,
+    pub cycle_time_delta: Aggregate,
+
+    /// Empty unless the scenario records it.
+    pub reaction_latency: Aggregate,
+    /// Empty unless the scenario records it.
+    pub dc_offset: Aggregate,
+    /// Magnitude of [`CycleMetadata::dc_drift_ns`]. Empty unless the scenario records it.
+    pub dc_drift: Aggregate,
+}
+
+impl CycleBucket {
+    fn record(&mut self, cycle: &CycleMetadata) {
+        self.processing_time.record(cycle.processing_time_ns);
+        self.tick_wait.record(cycle.tick_wait_ns);
+        self.cycle_time_delta.record(cycle.cycle_time_delta_ns);
+
+        if let Some(ns) = cycle.reaction_latency_ns {
+            self.reaction_latency.record(ns);
+        }
+
+        if let Some(ns) = cycle.dc_offset_ns {
+            self.dc_offset.record(ns);
+        }
+
+        if let Some(ns) = cycle.dc_drift_ns {
+            self.dc_drift.record(ns.unsigned_abs());
+        }
+    }
+}
+
+/// Cycle data collected by one or more scenario tasks.
+#[derive(Debug, Clone)]
+pub struct Cycles {
+    /// Every recorded cycle. Left empty in summary-only mode.
+    ///
+    /// When sampling, this only holds every Nth cycle plus any deadline misses.
+    pub raw: Vec<CycleMetadata>,
+
+    /// Aggregates for each group of sampled cycles. Empty unless sampling.
+    pub buckets: Vec<CycleBucket>,
+
+    /// Statistics computed on the fly over every recorded cycle.
+    pub summary: CycleSummary,
+
+    /// Deadline misses, kept even in summary-only mode.
+    pub spikes: Vec<Spike>,
+
+    /// State transition times, for scenarios that measure them.
+    pub transitions: Vec<Transition>,
+
+    /// Result of the minimum cycle time search, for the scenario that does it.
+    pub min_cycle_time: Option<MinCycleTime>,
+
+    keep_raw: bool,
+
+    sample: usize,
+
+    /// Cycles with a lower index than this are dropped.
+    warmup: usize,
+
+    cycle_time_ns: u32,
+
+    /// Abort threshold as `(miss_rate, window)`, if set.
+    abort: Option<(f64, usize)>,
+
+    /// Whether each of the last `window` recorded cycles missed its deadline.
+    recent_misses: VecDeque<bool>,
+
+    /// Number of `true`s in `recent_misses`.
+    recent_miss_count: usize,
+
+    /// Whether the run stopped early because of too many deadline misses.
+    pub aborted: bool,
+
+    /// Where cycles are streamed to instead of being kept, for soak runs.
+    stream: Option<cycle_stream::Sink>,
+}
+
+impl Default for Cycles {
+    fn default() -> Self {
+        Self {
+            raw: Vec::new(),
+            buckets: Vec::new(),
+            summary: CycleSummary::default(),
+            spikes: Vec::new(),
+            transitions: Vec::new(),
+            min_cycle_time: None,
+            keep_raw: true,
+            sample: 1,
+            warmup: 0,
+            cycle_time_ns: 0,
+            abort: None,
+            recent_misses: VecDeque::new(),
+            recent_miss_count: 0,
+            aborted: false,
+            stream: None,
+        }
+    }
+}
+
+impl Cycles {
+    /// Create an empty set of cycles, preallocating raw storage if it will be used.
+    fn new(settings: &TestSettings, iterations: usize) -> Self {
+        let keep_raw = !settings.summary_only;
+        let sample = settings.cycle_sample.max(1);
+
+        let (raw, buckets) = match (keep_raw, sample) {
+            (false, _) => (Vec::new(), Vec::new()),
+            (true, 1) => (Vec::with_capacity(iterations), Vec::new()),
+            (true, sample) => (
+                Vec::with_capacity(iterations / sample + 1),
+                Vec::with_capacity(iterations / sample + 1),
+            ),
+        };
+
+        Self {
+            raw,
+            buckets,
+            summary: CycleSummary::default(),
+            spikes: Vec::new(),
+            transitions: Vec::new(),
+            min_cycle_time: None,
+            keep_raw,
+            sample,
+            warmup: settings.warmup_cycles,
+            cycle_time_ns: settings.cycle_time_us.saturating_mul(1000),
+            abort: settings
+                .abort_miss_rate
+                .map(|rate| (rate, settings.abort_window.max(1))),
+            recent_misses: VecDeque::new(),
+            recent_miss_count: 0,
+            aborted: false,
+            // Only started by `run` for soak runs
+            stream: cycle_stream::sink(),
+        }
+    }
+
+    /// Whether the scenario should stop, because this or another task of the run went over the
+    /// abort threshold.
+    pub fn is_aborted(&self) -> bool {
+        ABORTED.load(Ordering::Relaxed)
+    }
+
+    fn check_abort(&mut self, cycle: usize, is_deadline_miss: bool) {
+        let Some((miss_rate, window)) = self.abort else {
+            return;
+        };
+
+        self.recent_misses.push_back(is_deadline_miss);
+        self.recent_miss_count += usize::from(is_deadline_miss);
+
+        if self.recent_misses.len() > window && self.recent_misses.pop_front() == Some(true) {
+            self.recent_miss_count -= 1;
+        }
+
+        // Wait for a full window so a few misses at the start can't abort the run
+        if self.recent_misses.len() < window
+            || self.recent_miss_count as f64 / window as f64 <= miss_rate
+        {
+            return;
+        }
+
+        self.aborted = true;
+
+        if !ABORTED.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Aborting run at cycle {}: {} of the last {} cycles missed their deadline",
+                cycle,
+                self.recent_miss_count,
+                window
+            );
+        }
+    }
+
+    /// Record a cycle. Call this only once all of the cycle's timestamps have been taken, so this
+    /// bookkeeping isn't measured as processing or tick wait time.
+    fn push(&mut self, cycle: CycleMetadata) {
+        // Caches are cold and DC is still settling, so these would only skew the statistics
+        if cycle.cycle < self.warmup {
+            return;
+        }
+
+        self.summary.record(&cycle);
+
+        live::publish_cycle(&cycle);
+
+        if let Some(stream) = &self.stream {
+            stream.push(&cycle);
+        }
+
+        let is_deadline_miss = cycle.is_deadline_miss(self.cycle_time_ns);
+
+        self.check_abort(cycle.cycle, is_deadline_miss);
+
+        if is_deadline_miss {
+            self.summary.deadline_misses += 1;
+
+            if self.spikes.len() < MAX_SPIKES {
+                self.spikes.push(Spike {
+                    cycle: cycle.cycle,
+                    cycle_time_delta_ns: cycle.cycle_time_delta_ns,
+                    time_ns: sched_trace::monotonic_ns(),
+                    preempted_by: None,
+                    sched_trace: None,
+                });
+            }
+        }
+
+        if !self.keep_raw {
+            return;
+        }
+
+        if self.sample == 1 {
+            self.raw.push(cycle);
+
+            return;
+        }
+
+        let is_sample = cycle.cycle.is_multiple_of(self.sample);
+
+        // Don't mix groups running at different rates into one bucket
+        let group_changed = self
+            .buckets
+            .last()
+            .is_some_and(|bucket| bucket.group_index != cycle.group_index);
+
+        if is_sample || group_changed || self.buckets.is_empty() {
+            self.buckets.push(CycleBucket {
+                first_cycle: cycle.cycle,
+                group_index: cycle.group_index,
+                ..CycleBucket::default()
+            });
+        }
+
+        if let Some(bucket) = self.buckets.last_mut() {
+            bucket.record(&cycle);
+        }
+
+        // Always keep outliers so tails aren't lost to sampling
+        if is_sample || is_deadline_miss {
+            self.raw.push(cycle);
+        }
+    }
+
+    fn append(&mut self, other: Cycles) {
+        self.raw.extend(other.raw);
+        self.buckets.extend(other.buckets);
+        self.summary.merge(&other.summary);
+        self.spikes.extend(other.spikes);
+        self.transitions.extend(other.transitions);
+        self.min_cycle_time = self.min_cycle_time.take().or(other.min_cycle_time);
+        self.aborted |= other.aborted;
+    }
+}
+
+impl FromIterator<Cycles> for Cycles {
+    fn from_iter<T: IntoIterator<Item = Cycles>>(iter: T) -> Self {
+        iter.into_iter().fold(Cycles::default(), |mut acc, cycles| {
+            acc.append(cycles);
+
+            acc
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RunMetadata {
     pub date: DateTime<Utc>,
 
+    /// When the scenario finished running.
+    pub finished: DateTime<Utc>,
+
     /// Scenario name, e.g. `single-thread`.
     pub scenario: String,
 
@@ -180,30 +1171,104 @@ pub struct RunMetadata {
     /// Run category (`name` field without timestamp).
     pub slug: String,
 
+    /// Tags of the scenario that was run, e.g. `single-thread`.
+    pub scenario_tags: Vec<String>,
+
+    /// Whether the run was stopped early by `--abort-miss-rate`.
+    pub aborted: bool,
+
+    /// Frames dropped while capturing this run, if it was captured.
+    pub capture_stats: Option<CaptureStats>,
+
+    /// Frames paired while capturing, if they were streamed.
+    pub streamed_frames: Option<StreamedFrames>,
+
     /// Metadata: computer hostname to use as an identifier.
     pub hostname: String,
 
     /// Data recorded for each process cycle in the scenario.
     ///
-    /// Does not include anything before process cycle starts.
+    /// Does not include anything before process cycle starts. Empty in summary-only mode.
     pub cycle_metadata: Vec<CycleMetadata>,
 
+    /// Statistics over every process cycle in the scenario, including in summary-only mode.
+    pub cycle_summary: CycleSummary,
+
+    /// Aggregates of sampled cycles when `--cycle-sample` is used.
+    pub cycle_buckets: Vec<CycleBucket>,
+
     /// Time for a packet to reach the end of the network and come back, according to EtherCAT's DC
     /// system.
     pub network_propagation_time_ns: u32,
 
     /// Settings used for this run.
     pub settings: TestSettings,
+
+    /// EtherCrab log events recorded during the run, if enabled.
+    pub ethercrab_events: Vec<EventSite>,
+
+    /// PHC to system clock offsets sampled during the run, if enabled.
+    pub phc_offsets: Vec<PhcOffset>,
+
+    /// `perf_event` totals over every scenario thread, if enabled.
+    pub perf_counters: BTreeMap<&'static str, u64>,
+
+    /// Kernel network stack times of each LRW frame, if enabled.
+    pub kernel_frames: Vec<KernelFrame>,
+
+    /// Deadline misses, with scheduler events around them if traced.
+    pub spikes: Vec<Spike>,
+
+    /// State transition times, for scenarios that measure them.
+    pub transitions: Vec<Transition>,
+
+    /// Result of the minimum cycle time search, for the scenario that does it.
+    pub min_cycle_time: Option<MinCycleTime>,
+}
+
+/// Why a run failed.
+#[derive(Debug)]
+pub enum RunError {
+    EtherCrab(ethercrab::error::Error),
+    /// The scenario needs kernel features or privileges this machine doesn't have.
+    Unsupported(io::Error),
+    /// The capture failed to start, stopped early or left an empty or truncated dump.
+    Capture(io::Error),
+}
+
+impl From<ethercrab::error::Error> for RunError {
+    fn from(e: ethercrab::error::Error) -> Self {
+        Self::EtherCrab(e)
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EtherCrab(e) => write!(f, "{}", e),
+            Self::Unsupported(e) => write!(f, "unsupported: {}", e),
+            Self::Capture(e) => write!(f, "capture failed: {}", e),
+        }
+    }
 }
 
 fn run(
     settings: &TestSettings,
-    scenario: impl Fn(&TestSettings) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error>,
+    scenario: impl Fn(&TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error>,
     scenario_name: &str,
-    no_capture: bool,
-) -> Result<RunMetadata, ethercrab::error::Error> {
+    scenario_tags: &[&str],
+    capture: CaptureMode,
+) -> Result<RunMetadata, RunError> {
     let scenario_name = scenario_name.replace('_', "-");
 
+    // Checked before anything is started so an unsupported scenario leaves nothing behind
+    match scenario_name.as_str() {
+        URING_SCENARIO => uring::probe(&settings.nic),
+        XDP_SCENARIO => xdp::probe(&settings.nic),
+        _ => Ok(()),
+    }
+    .map_err(RunError::Unsupported)?;
+
     let now = Utc::now();
 
     let date_slug = now.timestamp();
@@ -219,66 +1284,278 @@ fn run(
 
     let dump_filename = dump_path(&name);
 
+    let span = otel::Span::new("scenario")
+        .attr("scenario", &scenario_name)
+        .attr("run", &name);
+
+    live::publish(LiveEvent::ScenarioStarted {
+        scenario: scenario_name.clone(),
+        run: name.clone(),
+        hostname: settings.hostname.clone(),
+        cycle_time_us: settings.cycle_time_us,
+    });
+
     let start = Instant::now();
 
-    let tshark = if !no_capture {
-        let mut cmd = std::process::Command::new("tshark");
+    let packet_capture = match capture {
+        CaptureMode::PerRun => {
+            let interface = settings
+                .capture_interface
+                .as_deref()
+                .unwrap_or(&settings.nic);
+
+            let packet_capture =
+                capture::start(interface, &dump_filename, settings.capture_options())
+                    .and_then(|packet_capture| {
+                        capture::sync(&packet_capture, &settings.nic)?;
+
+                        Ok(packet_capture)
+                    })
+                    .map_err(|e| {
+                        fs::remove_file(&dump_filename).ok();
+
+                        RunError::Capture(e)
+                    })?;
+
+            log::info!(
+                "Running scenario {}, saving to {}",
+                scenario_name,
+                dump_filename.display()
+            );
+
+            Some(packet_capture)
+        }
+        CaptureMode::Session => {
+            log::info!(
+                "Running scenario {}, will split to {}",
+                scenario_name,
+                dump_filename.display()
+            );
+
+            None
+        }
+        CaptureMode::InBand => {
+            let packet_capture = capture::start_in_band(&dump_filename, settings.capture_snaplen)
+                .map_err(RunError::Capture)?;
 
-        cmd.stdout(Stdio::null()).stderr(Stdio::null()).args(&[
-            "-w",
-            dump_filename.display().to_string().as_str(),
-            "--interface",
-            &settings.nic,
-            "-f",
-            "ether proto 0x88a4",
-        ]);
+            log::info!(
+                "Running scenario {}, recording frames in-band to {}",
+                scenario_name,
+                dump_filename.display()
+            );
 
-        log::debug!("Running capture command {:?}", cmd);
+            Some(packet_capture)
+        }
+        CaptureMode::None => {
+            log::info!("Running scenario {}, not capturing packets", scenario_name);
 
-        let res = Some(cmd.spawn().expect("Could not spawn tshark command"));
+            None
+        }
+    };
 
-        // Let tshark settle in. It might miss packets if this delay is not here.
-        std::thread::sleep(Duration::from_millis(300));
+    // The null scenario sends nothing, so there's nothing to pair
+    let frame_stream =
+        (settings.stream_frames && packet_capture.is_some() && scenario_name != NULL_SCENARIO)
+            .then(|| frame_stream::start(&name, &dump_filename, &frames_path(&name)));
 
-        log::info!(
-            "Running scenario {}, saving to {}",
-            scenario_name,
-            dump_filename.display()
-        );
+    let phc = settings
+        .phc_offset_ms
+        .map(|ms| phc::Sampler::start(&settings.nic, Duration::from_millis(ms)));
 
-        res
+    let probes = if settings.kernel_probes {
+        kernel_probes::Probes::start(&settings.nic)
+            .map_err(|e| log::warn!("Kernel probes disabled for this run: {}", e))
+            .ok()
     } else {
-        log::info!("Running scenario {}, not capturing packets", scenario_name);
-
         None
     };
 
-    let (cycle_metadata, network_propagation_time_ns) = scenario(settings)?;
+    let tracer = settings.sched_trace_window_us.and_then(|window_us| {
+        sched_trace::Tracer::start(window_us)
+            .map_err(|e| log::warn!("Scheduler tracing disabled for this run: {}", e))
+            .ok()
+    });
+
+    let load = settings.network_load.as_ref().and_then(|load| {
+        load::Generator::start(load)
+            .map_err(|e| log::warn!("Network load disabled for this run: {}", e))
+            .ok()
+    });
+
+    // Restored when dropped at the end of the run
+    let _busy_poll = settings.busy_poll_us.map(|us| {
+        BusyPoll::enable(us).unwrap_or_else(|e| panic!("Failed to enable busy polling: {}", e))
+    });
+
+    // Soak runs are too long to keep every cycle in memory
+    let stream = (scenario_name == SOAK_SCENARIO).then(|| {
+        cycle_stream::start(&cycles_path(&name))
+            .unwrap_or_else(|e| panic!("Failed to start cycle stream: {}", e))
+    });
+
+    // Restored when dropped at the end of the run
+    let _trace_logging = settings
+        .trace_logging
+        .then(ethercrab_events::TraceLogging::enable);
+
+    // Unlocked when dropped at the end of the run
+    let _memory_lock = settings
+        .lock_memory
+        .then(|| MemoryLock::enable().unwrap_or_else(|e| panic!("Failed to lock memory: {}", e)));
+
+    let counters = settings.perf_counters.then(perf::Counters::start);
+
+    ethercrab_events::start();
 
-    // Stop tshark
-    if let Some(mut tshark) = tshark {
-        tshark.kill().expect("Failed to kill tshark");
+    ABORTED.store(false, Ordering::Relaxed);
+
+    let mut scenario_result = scenario(settings);
+
+    let ethercrab_events = ethercrab_events::stop();
+
+    let perf_counters = counters.map(perf::Counters::stop).unwrap_or_default();
+
+    let kernel_frames = probes.map(kernel_probes::Probes::stop).unwrap_or_default();
+
+    let phc_offsets = phc.map(phc::Sampler::stop).unwrap_or_default();
+
+    if let Some(load) = load {
+        load.stop();
     }
 
-    std::thread::sleep(Duration::from_millis(500));
+    if let Some(stream) = stream {
+        // The writer only finishes once the scenario's sink is gone too
+        if let Ok((cycles, _)) = &mut scenario_result {
+            cycles.stream = None;
+        }
+
+        if let Err(e) = stream.stop() {
+            log::warn!("Failed to finish cycle stream: {}", e);
+        }
+    }
+
+    // Stop tracing even if the scenario failed so it isn't left running
+    if let Some(tracer) = tracer {
+        let spikes = scenario_result
+            .as_mut()
+            .map_or(&mut [] as &mut [Spike], |(cycles, _)| &mut cycles.spikes);
+
+        if let Err(e) = tracer.stop(spikes) {
+            log::warn!("Failed to collect scheduler trace: {}", e);
+        }
+    }
+
+    let (cycles, network_propagation_time_ns) = scenario_result?;
+
+    let finished = Utc::now();
+
+    let capture_stats = packet_capture
+        .map(capture::stop)
+        .transpose()
+        .and_then(|stats| {
+            // The null scenario sends nothing, so its dump is always empty
+            if stats.is_some() && scenario_name != NULL_SCENARIO {
+                capture::verify(&dump_filename)?;
+            }
+
+            Ok(stats)
+        })
+        .map_err(|e| {
+            // Don't leave a broken dump lying around for a later import to trip over
+            fs::remove_file(&dump_filename).ok();
+
+            RunError::Capture(e)
+        })?;
+
+    let streamed_frames = frame_stream
+        .map(frame_stream::Stream::stop)
+        .transpose()
+        .map_err(|e| {
+            fs::remove_file(&dump_filename).ok();
+            fs::remove_file(frames_path(&name)).ok();
+
+            RunError::Capture(e)
+        })?;
 
     log::info!(
         "--> Collected {} process cycles in {} ms, network propagation time {} ns",
-        cycle_metadata.len(),
+        cycles.summary.count(),
         start.elapsed().as_millis(),
         network_propagation_time_ns
     );
 
-    Ok(RunMetadata {
+    let result = RunMetadata {
         date: now,
+        finished,
         hostname: settings.hostname.clone(),
         name,
         slug,
-        cycle_metadata,
+        scenario_tags: scenario_tags.iter().map(|tag| tag.to_string()).collect(),
+        aborted: cycles.aborted,
+        capture_stats,
+        streamed_frames,
+        cycle_metadata: cycles.raw,
+        cycle_summary: cycles.summary,
+        cycle_buckets: cycles.buckets,
         network_propagation_time_ns,
         scenario: scenario_name,
         settings: settings.clone(),
-    })
+        ethercrab_events,
+        phc_offsets,
+        perf_counters,
+        kernel_frames,
+        spikes: cycles.spikes,
+        transitions: cycles.transitions,
+        min_cycle_time: cycles.min_cycle_time,
+    };
+
+    drop(span);
+
+    let cycle_time_delta = result.cycle_summary.cycle_time_delta.summary();
+
+    live::publish(LiveEvent::ScenarioFinished {
+        scenario: result.scenario.clone(),
+        run: result.name.clone(),
+        cycles: result.cycle_summary.count(),
+        deadline_misses: result.cycle_summary.deadline_misses,
+        cycle_time_delta_p50_ns: cycle_time_delta.p50_ns,
+        cycle_time_delta_p99_ns: cycle_time_delta.p99_ns,
+        cycle_time_delta_max_ns: cycle_time_delta.max_ns,
+    });
+
+    otel::record_run(&result);
+
+    if let Err(e) = otel::flush() {
+        log::warn!("Failed to export telemetry: {}", e);
+    }
+
+    Ok(result)
+}
+
+/// Merge the cycle statistics of every repeat of each scenario configuration, keyed by slug.
+pub fn summaries_by_slug<'a>(
+    results: &'a [(&str, RunMetadata)],
+) -> BTreeMap<&'a str, CycleSummary> {
+    let mut by_slug = BTreeMap::<&str, CycleSummary>::new();
+
+    for (_scenario, result) in results {
+        by_slug
+            .entry(&result.slug)
+            .or_default()
+            .merge(&result.cycle_summary);
+    }
+
+    by_slug
+}
+
+/// Path cycles are streamed to for runs that don't keep them in memory, next to the run's dump.
+pub fn cycles_path(name: &str) -> PathBuf {
+    dump_path(name).with_extension("cycles.csv")
+}
+
+/// Path frames paired during a run are written to, next to the run's dump.
+pub fn frames_path(name: &str) -> PathBuf {
+    dump_path(name).with_extension("frames.bin")
 }
 
 /// Create a full canonicalised file path from a run name.
@@ -296,92 +1573,481 @@ pub fn dump_path(name: &str) -> PathBuf {
     path
 }
 
-/// Run all scenarios sequentially while capturing network traffic in the background with `tshark`
-/// for each one.
+/// Run all scenarios sequentially while capturing network traffic in the background for each one.
 ///
 /// Network captures are saved to disk inside the `dumps/` folder.
-pub fn run_all(
+type ScenarioFn = dyn Fn(&TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error>;
+
+/// A scenario queued to run with one combination of settings.
+#[derive(Clone)]
+pub struct ScenarioRun {
+    settings: TestSettings,
+    scenario_fn: &'static ScenarioFn,
+    name: &'static str,
+    tags: &'static [&'static str],
+}
+
+impl ScenarioRun {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn tags(&self) -> &'static [&'static str] {
+        self.tags
+    }
+
+    pub fn settings_mut(&mut self) -> &mut TestSettings {
+        &mut self.settings
+    }
+
+    /// Run the scenario, re-running it up to `capture_retries` times if its capture fails.
+    pub fn run(&self, capture: CaptureMode) -> Result<(&'static str, RunMetadata), RunError> {
+        let mut attempt = 0;
+
+        loop {
+            match run(
+                &self.settings,
+                self.scenario_fn,
+                self.name,
+                self.tags,
+                capture,
+            ) {
+                Err(RunError::Capture(e)) if attempt < self.settings.capture_retries => {
+                    attempt += 1;
+
+                    log::warn!(
+                        "Capture of {} failed, retrying ({} of {}): {}",
+                        self.name,
+                        attempt,
+                        self.settings.capture_retries,
+                        e
+                    );
+                }
+                result => return result.map(|result| (self.name, result)),
+            }
+        }
+    }
+}
+
+/// Repeat every run `repeats(run)` times, in the order given by `mode`.
+pub fn repeat_runs(
+    runs: Vec<ScenarioRun>,
+    mode: RepeatMode,
+    repeats: impl Fn(&ScenarioRun) -> u32,
+) -> Vec<ScenarioRun> {
+    let counts = runs.iter().map(&repeats).collect::<Vec<_>>();
+
+    match mode {
+        RepeatMode::Interleave => {
+            let rounds = counts.iter().copied().max().unwrap_or(0);
+
+            // Runs with fewer repeats drop out of later rounds
+            (0..rounds)
+                .flat_map(|round| {
+                    runs.iter()
+                        .zip(counts.iter())
+                        .filter(move |(_, count)| round < **count)
+                        .map(|(run, _)| run.clone())
+                })
+                .collect()
+        }
+        RepeatMode::Batch => runs
+            .iter()
+            .zip(counts)
+            .flat_map(|(run, count)| std::iter::repeat_n(run, count as usize).cloned())
+            .collect(),
+    }
+}
+
+/// Scenario tags to select and skip. See the scenario list in [`scenario_runs`] for every tag.
+#[derive(Debug, Default)]
+pub struct TagFilter {
+    /// Only run scenarios with at least one of these tags. Everything runs if this is empty.
+    pub select: Vec<String>,
+
+    /// Don't run scenarios with any of these tags.
+    pub skip: Vec<String>,
+}
+
+/// Every scenario that applies to `settings`, narrowed down by `filter` or `name_filter` and
+/// without any matching `exclude` or skipped tag, in the order they'd normally run.
+pub fn scenario_runs(
     settings: &TestSettings,
-    filter: &Option<String>,
+    filter: Option<&Regex>,
+    exclude: Option<&Regex>,
+    tag_filter: &TagFilter,
     name_filter: &[String],
-    no_capture: bool,
-) -> Result<Vec<(&'static str, RunMetadata)>, ethercrab::error::Error> {
-    let scenarios: Vec<(
-        &dyn Fn(&TestSettings) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error>,
-        &'static str,
-    )> = vec![
-        (&tokio_default, "tokio-default"),
-        (&smol_default, "smol-default"),
-        (&single_thread, "1thr-1task"),
-        (&single_thread_2_tasks, "1thr-2task"),
-        (&single_thread_10_tasks, "1thr-10task"),
-        (&two_threads, "2thr-1task"),
-        (&three_threads, "3thr-2task"),
-        (&eleven_threads, "11thr-10task"),
-        (&two_threads_10_tasks, "2thr-10task"),
+) -> Vec<ScenarioRun> {
+    // Scenario, name and tags. Tags are thread count, executor, then anything else worth selecting
+    // or skipping by
+    let mut scenarios: Vec<(&'static ScenarioFn, &'static str, &'static [&'static str])> = vec![
+        (&tokio_default, "tokio-default", &["multi-thread", "tokio"]),
+        (&smol_default, "smol-default", &["multi-thread", "smol"]),
+        (
+            &null,
+            NULL_SCENARIO,
+            &["single-thread", "smol", "no-ethercat"],
+        ),
+        (&single_thread, "1thr-1task", &["single-thread", "smol"]),
+        (
+            &single_thread_spin,
+            "1thr-1task-spin",
+            &["single-thread", "smol", "timer"],
+        ),
+        (
+            &single_thread_nanosleep,
+            "1thr-1task-nanosleep",
+            &["single-thread", "smol", "timer"],
+        ),
+        (
+            &single_thread_poll,
+            "1thr-1task-poll",
+            &["single-thread", "no-executor", "custom-io", "timer"],
+        ),
+        (
+            &single_thread_interleaved,
+            "1thr-1task-interleaved",
+            &["single-thread", "no-executor", "timer"],
+        ),
+        (
+            &single_thread_dc,
+            "1thr-1task-dc",
+            &["single-thread", "smol", "dc", "timer"],
+        ),
+        (
+            &single_thread_dc_drift,
+            "1thr-1task-dc-drift",
+            &["single-thread", "smol", "dc"],
+        ),
+        (
+            &single_thread_pdi::<16>,
+            "1thr-1task-pdi16",
+            &["single-thread", "smol", "pdi"],
+        ),
+        (
+            &single_thread_pdi::<128>,
+            "1thr-1task-pdi128",
+            &["single-thread", "smol", "pdi"],
+        ),
+        (
+            &single_thread_pdi::<512>,
+            "1thr-1task-pdi512",
+            &["single-thread", "smol", "pdi"],
+        ),
+        (
+            &single_thread_pdi::<1024>,
+            "1thr-1task-pdi1024",
+            &["single-thread", "smol", "pdi"],
+        ),
+        (&single_group, "1thr-1group", &["single-thread", "smol"]),
+        (
+            &single_thread_lrd_lwr,
+            "1thr-1task-lrd-lwr",
+            &["single-thread", "smol", "pdi"],
+        ),
+        (
+            &single_thread_init,
+            "1thr-1task-init",
+            &["single-thread", "smol", "state"],
+        ),
+        (
+            &single_thread_unplug,
+            "1thr-1task-unplug",
+            &["single-thread", "smol", "state"],
+        ),
+        (
+            &single_thread_2_tasks,
+            "1thr-2task",
+            &["single-thread", "smol"],
+        ),
+        (
+            &single_thread_sdo,
+            "1thr-1task-sdo",
+            &["single-thread", "smol", "mailbox"],
+        ),
+        (
+            &single_thread_10_tasks,
+            "1thr-10task",
+            &["single-thread", "smol"],
+        ),
+        (
+            &single_thread_multi_pdu,
+            "1thr-10group-multipdu",
+            &["single-thread", "smol", "custom-io"],
+        ),
+        (&two_threads, "2thr-1task", &["multi-thread", "smol"]),
+        (&pipeline, "2thr-1task-pipeline", &["multi-thread", "smol"]),
+        (
+            &split_tx_rx,
+            "3thr-1task-split-txrx",
+            &["multi-thread", "smol", "custom-io"],
+        ),
+        (&three_threads, "3thr-2task", &["multi-thread", "smol"]),
+        (
+            &mixed_prio,
+            "3thr-2task-mixed-prio",
+            &["multi-thread", "smol", "rt-only"],
+        ),
+        (&eleven_threads, "11thr-10task", &["multi-thread", "smol"]),
+        (
+            &two_threads_10_tasks,
+            "2thr-10task",
+            &["multi-thread", "smol"],
+        ),
+        (
+            &thread_per_core,
+            "thread-per-core",
+            &["multi-thread", "smol", "pinned"],
+        ),
+        (
+            &async_std_single_thread,
+            "async-std-1thr-1task",
+            &["single-thread", "async-std"],
+        ),
+        (
+            &async_std_two_threads,
+            "async-std-2thr-1task",
+            &["multi-thread", "async-std"],
+        ),
     ];
 
+    // Needs an output wired back to an input, so only run on rigs that have one
+    if settings.hil.is_some() {
+        scenarios.push((&hil, "hil", &["single-thread", "smol", "optional"]));
+    }
+
+    // Needs a miss rate to search for
+    if settings.max_miss_rate.is_some() {
+        scenarios.push((
+            &min_cycle_time,
+            "1thr-1task-min-cycle",
+            &["single-thread", "smol", "optional"],
+        ));
+    }
+
+    // Runs for as long as it's told to, so only when asked for
+    if settings.soak_secs.is_some() {
+        scenarios.push((&soak, SOAK_SCENARIO, &["single-thread", "smol", "optional"]));
+    }
+
+    // Only meaningful with a compute time to compare against
+    if settings.compute_us.is_some() {
+        scenarios.push((
+            &single_thread_compute,
+            "1thr-1task-compute",
+            &["single-thread", "smol", "optional"],
+        ));
+    }
+
+    // Not every kernel allows io_uring, so only when asked for
+    if settings.io_uring {
+        scenarios.push((
+            &two_threads_uring,
+            URING_SCENARIO,
+            &["multi-thread", "smol", "custom-io", "optional"],
+        ));
+    }
+
+    // Needs XDP support in the NIC driver, so only when asked for
+    if settings.xdp {
+        scenarios.push((
+            &two_threads_xdp,
+            XDP_SCENARIO,
+            &["multi-thread", "smol", "custom-io", "optional"],
+        ));
+    }
+
+    // Skip layouts that are the same as a preset, which already has the same name
+    if let Some(layout) = settings.thread_layout.as_ref() {
+        if !scenarios.iter().any(|(_, name, _)| *name == layout.name) {
+            scenarios.push((
+                &custom_threads,
+                layout.name,
+                &["multi-thread", "smol", "optional"],
+            ));
+        }
+    }
+
+    // Names must be unique to tell results apart
+    for composition in &settings.compositions {
+        if scenarios
+            .iter()
+            .any(|(_, name, _)| *name == composition.name())
+        {
+            log::warn!(
+                "Skipping composed scenario {}, a scenario already has that name",
+                composition.name()
+            );
+        } else {
+            scenarios.push((&composed, composition.name(), composition.tags));
+        }
+    }
+
+    // Needs the NIC on one of several NUMA nodes
+    if numa::has_remote_node(&settings.nic) {
+        scenarios.push((
+            &two_threads_numa_local,
+            "2thr-1task-numa-local",
+            &["multi-thread", "smol", "pinned"],
+        ));
+        scenarios.push((
+            &two_threads_numa_remote,
+            "2thr-1task-numa-remote",
+            &["multi-thread", "smol", "pinned"],
+        ));
+    }
+
     scenarios
         .into_iter()
-        .filter_map(|(scenario_fn, scenario_name)| {
-            // Filter by substring
+        .filter(|(_, scenario_name, tags)| {
+            !exclude.is_some_and(|exclude| exclude.is_match(scenario_name))
+                && !tags
+                    .iter()
+                    .any(|tag| tag_filter.skip.iter().any(|skip| skip == tag))
+                && (tag_filter.select.is_empty()
+                    || tags
+                        .iter()
+                        .any(|tag| tag_filter.select.iter().any(|select| select == tag)))
+        })
+        .filter(|(_, scenario_name, _)| {
+            // Filter by pattern
             if let Some(filter) = filter {
-                if scenario_name.contains(filter) {
-                    Some(
-                        run(settings, scenario_fn, &scenario_name, no_capture)
-                            .map(|result| (scenario_name, result)),
-                    )
-                } else {
-                    None
-                }
+                filter.is_match(scenario_name)
             }
             // Filter by explicit scenario name
             else if !name_filter.is_empty() {
-                if name_filter.contains(&scenario_name.to_string()) {
-                    Some(
-                        run(settings, scenario_fn, &scenario_name, no_capture)
-                            .map(|result| (scenario_name, result)),
-                    )
-                } else {
-                    None
-                }
+                name_filter.contains(&scenario_name.to_string())
             }
             // No filtering - run everything
             else {
-                Some(
-                    run(settings, scenario_fn, &scenario_name, no_capture)
-                        .map(|result| (scenario_name, result)),
-                )
+                true
             }
         })
-        .collect::<Result<Vec<_>, _>>()
+        .map(|(scenario_fn, name, tags)| {
+            let mut settings = settings.clone();
+
+            settings.composition = settings
+                .compositions
+                .iter()
+                .find(|composition| composition.name() == name)
+                .cloned();
+
+            ScenarioRun {
+                settings,
+                scenario_fn,
+                name,
+                tags,
+            }
+        })
+        .collect()
 }
 
 /// Create a thread builder using the `net` priority from [`TestSettings`].
 fn make_net_thread(settings: &TestSettings) -> ThreadBuilder {
-    make_thread(settings.is_rt, settings.net_prio, "ethercrab-net")
+    make_thread(settings, settings.net_prio, "ethercrab-net")
+}
+
+/// Create a thread builder using the `rx` priority from [`TestSettings`], falling back to the
+/// `net` priority.
+fn make_rx_thread(settings: &TestSettings) -> ThreadBuilder {
+    make_thread(
+        settings,
+        settings.rx_prio.unwrap_or(settings.net_prio),
+        "ethercrab-rx",
+    )
 }
 
 /// Create a thread builder using the `task` priority from [`TestSettings`].
 fn make_task_thread(settings: &TestSettings) -> ThreadBuilder {
-    make_thread(settings.is_rt, settings.task_prio, "ethercrab-task")
+    make_thread(settings, settings.task_prio, "ethercrab-task")
+}
+
+/// Pin the current thread to the net CPU from [`TestSettings::affinity`], if set, and prefault its
+/// stack if [`TestSettings::lock_memory`] is set.
+///
+/// Call this first thing in the net thread.
+fn pin_net_thread(settings: &TestSettings) {
+    prefault_thread(settings);
+
+    if let Some(affinity) = settings.affinity.as_ref() {
+        pin_current_thread(&[affinity.net]);
+    }
 }
 
-fn make_thread(is_rt: bool, prio: u8, name: &str) -> ThreadBuilder {
+/// Pin the current thread to the task CPUs from [`TestSettings::affinity`], if set, and prefault
+/// its stack if [`TestSettings::lock_memory`] is set.
+///
+/// Call this first thing in each task thread.
+fn pin_task_thread(settings: &TestSettings) {
+    prefault_thread(settings);
+
+    if let Some(affinity) = settings.affinity.as_ref() {
+        pin_current_thread(&affinity.task);
+    }
+}
+
+/// Fault in the current thread's stack if [`TestSettings::lock_memory`] is set.
+fn prefault_thread(settings: &TestSettings) {
+    if settings.lock_memory {
+        prefault_stack();
+    }
+}
+
+fn pin_current_thread(cpus: &[usize]) {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+
+    for cpu in cpus {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+    }
+
+    let res = unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) };
+
+    if res != 0 {
+        panic!(
+            "Failed to pin thread to CPUs {:?}: {}",
+            cpus,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+fn make_thread(settings: &TestSettings, prio: u8, name: &str) -> ThreadBuilder {
     let builder = ThreadBuilder::default().name(name);
 
-    // Magic value of 0 denotes no scheduling set
-    let builder = if is_rt && prio > 0 {
-        builder
+    if !settings.is_rt {
+        return builder;
+    }
+
+    match settings.policy {
+        SchedPolicy::Deadline => {
+            let period = Duration::from_micros(settings.cycle_time_us.into());
+
+            builder
+                .policy(ThreadSchedulePolicy::Realtime(
+                    thread_priority::RealtimeThreadSchedulePolicy::Deadline,
+                ))
+                .priority(ThreadPriority::Deadline {
+                    runtime: period / DEADLINE_RUNTIME_DIVISOR,
+                    deadline: period,
+                    period,
+                    flags: DeadlineFlags::default(),
+                })
+        }
+        // Magic value of 0 denotes no scheduling set
+        _ if prio == 0 => builder,
+        SchedPolicy::Fifo => builder
             .policy(ThreadSchedulePolicy::Realtime(
                 thread_priority::RealtimeThreadSchedulePolicy::Fifo,
             ))
             .priority(ThreadPriority::Crossplatform(
                 prio.try_into().expect("Bad net thread prio"),
+            )),
+        SchedPolicy::RoundRobin => builder
+            .policy(ThreadSchedulePolicy::Realtime(
+                thread_priority::RealtimeThreadSchedulePolicy::RoundRobin,
             ))
-    } else {
-        builder
-    };
-
-    builder
+            .priority(ThreadPriority::Crossplatform(
+                prio.try_into().expect("Bad net thread prio"),
+            )),
+    }
 }
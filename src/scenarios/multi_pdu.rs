@@ -0,0 +1,289 @@
+use super::{
+    create_client_raw, create_groups, loop_tick, make_task_thread, pin_task_thread,
+    raw_socket::{RawSocket, FRAME_BUF_LEN},
+    CycleMetadata, Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, PduRx, PduTx};
+use futures_lite::StreamExt;
+use smol::Async;
+use std::{
+    io,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// Ethernet II header plus the EtherCAT frame header.
+const HEADER_LEN: usize = 16;
+/// Offset of the EtherCAT frame header.
+const ETHERCAT_HEADER: usize = 14;
+/// PDU command, index, address, length/flags and IRQ.
+const PDU_HEADER_LEN: usize = 10;
+/// Offset of the length/flags field in a PDU header.
+const PDU_FLAGS: usize = 6;
+/// Working counter after each PDU's data.
+const WKC_LEN: usize = 2;
+/// Length bits of both the EtherCAT frame header and PDU length field.
+const LEN_MASK: u16 = 0x07ff;
+/// Set in a PDU's length field when another PDU follows it in the same frame.
+const MORE_FOLLOWS: u16 = 1 << 15;
+/// EtherCAT frame header protocol type for PDUs.
+const PROTOCOL_PDU: u16 = 0x1 << 12;
+
+/// Single thread with one task exchanging all 10 groups every cycle, with every group's LRW packed
+/// into one Ethernet frame.
+///
+/// EtherCrab sends each PDU in its own frame, so TX/RX is done here instead of with `tx_rx_task`.
+/// Every frame that's ready to send is merged into one, and response frames are split back up into
+/// one per PDU before being handed back to EtherCrab.
+pub fn single_thread_multi_pdu(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx, rx) = create_client_raw(settings, &storage);
+
+                let socket = RawSocket::open(&settings.nic, 0)
+                    .and_then(Async::new)
+                    .expect("Raw socket");
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex
+                    .spawn(async move {
+                        if let Err(e) = packed_tx_rx(socket, tx, rx).await {
+                            log::error!("Packed TX/RX failed: {}", e);
+                        }
+                    })
+                    .detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let mut groups = futures::future::join_all(
+                        groups.into_iter().map(|group| group.into_op(&client)),
+                    )
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()
+                    .expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        // Every group's frame is queued before the TX/RX task next runs, so they
+                        // all go out together
+                        futures::future::join_all(
+                            groups.iter_mut().map(|group| loop_tick(group, &client)),
+                        )
+                        .await;
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Send every queued frame packed into as few Ethernet frames as possible, and split received
+/// frames back into one per PDU.
+async fn packed_tx_rx(
+    socket: Async<RawSocket>,
+    mut tx: PduTx<'_>,
+    mut rx: PduRx<'_>,
+) -> io::Result<()> {
+    let mut packer = Packer::new();
+    let mut frame_buf = [0u8; FRAME_BUF_LEN];
+
+    let send = futures_lite::future::poll_fn(|ctx| {
+        tx.replace_waker(ctx.waker());
+
+        while let Some(frame) = tx.next_sendable_frame() {
+            if !packer.fits(frame.len()) {
+                if let Err(e) = packer.send(socket.get_ref()) {
+                    return Poll::Ready(Err(e));
+                }
+            }
+
+            let res = frame.send_blocking(&mut frame_buf, |bytes| {
+                packer.push(bytes);
+
+                Ok(bytes.len())
+            });
+
+            if let Err(e) = res {
+                return Poll::Ready(Err(io::Error::other(format!("Pack frame: {}", e))));
+            }
+        }
+
+        match packer.send(socket.get_ref()) {
+            Ok(()) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    });
+
+    let receive = async {
+        let mut recv_buf = [0u8; FRAME_BUF_LEN];
+        let mut split_buf = [0u8; FRAME_BUF_LEN];
+
+        loop {
+            let len = socket
+                .read_with(|socket| socket.recv(&mut recv_buf))
+                .await?;
+
+            receive_packed(&mut rx, &recv_buf[0..len], &mut split_buf)
+                .map_err(|e| io::Error::other(format!("Receive frame: {}", e)))?;
+        }
+    };
+
+    futures_lite::future::race(send, receive).await
+}
+
+/// Builds one Ethernet frame out of the PDUs from several.
+struct Packer {
+    buf: [u8; FRAME_BUF_LEN],
+    len: usize,
+    /// Start of the last PDU pushed, so its flags can be updated when another follows it.
+    last_pdu: Option<usize>,
+}
+
+impl Packer {
+    fn new() -> Self {
+        Self {
+            buf: [0u8; FRAME_BUF_LEN],
+            len: 0,
+            last_pdu: None,
+        }
+    }
+
+    /// Whether the PDU from an Ethernet frame of `frame_len` bytes fits in what's left.
+    fn fits(&self, frame_len: usize) -> bool {
+        self.len.max(HEADER_LEN) + frame_len - HEADER_LEN <= self.buf.len()
+    }
+
+    /// Append the PDU from a single PDU Ethernet frame.
+    fn push(&mut self, frame: &[u8]) {
+        // Headers are the same for every frame EtherCrab sends, apart from the length
+        if self.len == 0 {
+            self.buf[0..HEADER_LEN].copy_from_slice(&frame[0..HEADER_LEN]);
+            self.len = HEADER_LEN;
+        }
+
+        if let Some(prev) = self.last_pdu {
+            let flags = &mut self.buf[prev + PDU_FLAGS..prev + PDU_FLAGS + 2];
+
+            let more = u16::from_le_bytes([flags[0], flags[1]]) | MORE_FOLLOWS;
+
+            flags.copy_from_slice(&more.to_le_bytes());
+        }
+
+        let pdu = &frame[HEADER_LEN..];
+
+        self.last_pdu = Some(self.len);
+        self.buf[self.len..self.len + pdu.len()].copy_from_slice(pdu);
+        self.len += pdu.len();
+    }
+
+    /// Send the packed frame, if there's anything in it.
+    fn send(&mut self, socket: &RawSocket) -> io::Result<()> {
+        if self.len == 0 {
+            return Ok(());
+        }
+
+        let header = (self.len - HEADER_LEN) as u16 | PROTOCOL_PDU;
+
+        self.buf[ETHERCAT_HEADER..HEADER_LEN].copy_from_slice(&header.to_le_bytes());
+
+        let frame = &self.buf[0..self.len];
+
+        self.len = 0;
+        self.last_pdu = None;
+
+        socket.send(frame)
+    }
+}
+
+/// Pass each PDU in `frame` to EtherCrab in its own Ethernet frame.
+fn receive_packed(
+    rx: &mut PduRx<'_>,
+    frame: &[u8],
+    split_buf: &mut [u8; FRAME_BUF_LEN],
+) -> Result<(), ethercrab::error::Error> {
+    let Some(header) = frame.get(ETHERCAT_HEADER..HEADER_LEN) else {
+        // Let EtherCrab decide what's wrong with it
+        return rx.receive_frame(frame);
+    };
+
+    let payload_len = usize::from(u16::from_le_bytes([header[0], header[1]]) & LEN_MASK);
+
+    let Some(mut payload) = frame.get(HEADER_LEN..HEADER_LEN + payload_len) else {
+        return rx.receive_frame(frame);
+    };
+
+    while let Some(flags) = payload.get(PDU_FLAGS..PDU_FLAGS + 2) {
+        let data_len = usize::from(u16::from_le_bytes([flags[0], flags[1]]) & LEN_MASK);
+
+        let Some(pdu) = payload.get(0..PDU_HEADER_LEN + data_len + WKC_LEN) else {
+            log::warn!("Truncated PDU in {} byte frame", frame.len());
+
+            break;
+        };
+
+        let split_len = HEADER_LEN + pdu.len();
+        let header = pdu.len() as u16 | PROTOCOL_PDU;
+
+        split_buf[0..ETHERCAT_HEADER].copy_from_slice(&frame[0..ETHERCAT_HEADER]);
+        split_buf[ETHERCAT_HEADER..HEADER_LEN].copy_from_slice(&header.to_le_bytes());
+        split_buf[HEADER_LEN..split_len].copy_from_slice(pdu);
+
+        // This is now the only PDU in the frame
+        let flags = HEADER_LEN + PDU_FLAGS;
+        split_buf[flags + 1] &= !((MORE_FOLLOWS >> 8) as u8);
+
+        rx.receive_frame(&split_buf[0..split_len])?;
+
+        payload = &payload[pdu.len()..];
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self};
+use std::time::{Duration, Instant};
+
+/// The same as [`single_thread`](super::single_thread::single_thread), but waits for the next cycle
+/// with `clock_nanosleep(CLOCK_MONOTONIC, TIMER_ABSTIME)` instead of with `smol::Timer`.
+///
+/// This is how most hand-written RT loops tick. The sleep blocks the whole thread, which is fine
+/// here as no frames are in flight between the end of one cycle and the start of the next. If
+/// `tick_wait_ns` jitter is still there with this scenario, it's coming from the scheduler rather
+/// than the async timer.
+pub fn single_thread_nanosleep(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let cycle_time = Duration::from_micros(settings.cycle_time_us.into());
+
+                    let mut next_tick = monotonic_now();
+                    advance(&mut next_tick, cycle_time);
+
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        sleep_until(&next_tick);
+
+                        advance(&mut next_tick, cycle_time);
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+pub(super) fn monotonic_now() -> libc::timespec {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+
+    now
+}
+
+pub(super) fn advance(time: &mut libc::timespec, by: Duration) {
+    time.tv_sec += by.as_secs() as libc::time_t;
+    time.tv_nsec += by.subsec_nanos() as libc::c_long;
+
+    if time.tv_nsec >= 1_000_000_000 {
+        time.tv_sec += 1;
+        time.tv_nsec -= 1_000_000_000;
+    }
+}
+
+/// Block the thread until `deadline`. Returns immediately if it's already passed.
+pub(super) fn sleep_until(deadline: &libc::timespec) {
+    // Returns the error directly instead of through errno
+    while unsafe {
+        libc::clock_nanosleep(
+            libc::CLOCK_MONOTONIC,
+            libc::TIMER_ABSTIME,
+            deadline,
+            std::ptr::null_mut(),
+        )
+    } == libc::EINTR
+    {}
+}
@@ -0,0 +1,59 @@
+use super::{make_task_thread, pin_task_thread, CycleMetadata, Cycles, TestSettings};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Name of the scenario that sends no EtherCAT traffic. Its runs have no frames to ingest.
+pub const NULL_SCENARIO: &str = "1thr-null";
+
+/// The same tick loop as [`single_thread`](super::single_thread::single_thread), but without
+/// EtherCrab or any network traffic.
+///
+/// `processing_time_ns` is just the cost of taking timestamps. Whatever jitter is left in
+/// `tick_wait_ns` and `cycle_time_delta_ns` comes from the OS and timer, not from EtherCrab or the
+/// network. No network propagation time is recorded.
+pub fn null(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, 0))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
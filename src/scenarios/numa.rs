@@ -0,0 +1,59 @@
+use super::{thread_per_task::two_threads, CoreAffinity, Cycles, TestSettings};
+use crate::system::{nic_numa_node, numa_node_cpus};
+
+/// The same as `2thr-1task`, with the net thread and task thread both pinned to CPUs on the NIC's
+/// NUMA node.
+pub fn two_threads_numa_local(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    two_threads(&placed(settings, false))
+}
+
+/// The same as `2thr-1task`, with the net thread pinned to a CPU on the NIC's NUMA node and the task
+/// thread pinned to CPUs on another node, so every PDI access from the task crosses the
+/// interconnect.
+pub fn two_threads_numa_remote(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    two_threads(&placed(settings, true))
+}
+
+/// Whether the NIC reports a NUMA node and there's another node to put the task thread on.
+pub fn has_remote_node(nic: &str) -> bool {
+    placement(nic, true).is_some()
+}
+
+fn placed(settings: &TestSettings, remote: bool) -> TestSettings {
+    let affinity = placement(&settings.nic, remote).expect("No NUMA placement for NIC");
+
+    log::info!(
+        "Net thread on CPU {}, task thread on CPUs {:?}",
+        affinity.net,
+        affinity.task
+    );
+
+    TestSettings {
+        affinity: Some(affinity),
+        ..settings.clone()
+    }
+}
+
+/// Net thread on the first CPU of the NIC's node, and task threads on either the rest of that node
+/// or the first other node.
+fn placement(nic: &str, remote: bool) -> Option<CoreAffinity> {
+    let node = nic_numa_node(nic)?;
+
+    let mut nodes = numa_node_cpus();
+
+    let local = nodes.remove(&node)?;
+
+    let (&net, rest) = local.split_first()?;
+
+    let task = if remote {
+        nodes.into_values().next()?
+    } else {
+        rest.to_vec()
+    };
+
+    (!task.is_empty()).then_some(CoreAffinity { net, task })
+}
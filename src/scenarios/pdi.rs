@@ -0,0 +1,95 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, Command};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Logical address of the padding LRW. Far past the end of every group's PDI so no device maps any
+/// of it.
+const PADDING_ADDRESS: u32 = 0x8000_0000;
+
+/// Single thread with TX/RX and one PDI loop, plus an extra `LEN` byte LRW every cycle.
+///
+/// The test devices only have a few bytes of process data between them, so the extra LRW stands in
+/// for a group with a `LEN` byte PDI. It's addressed past every mapped PDI so it passes through every
+/// device untouched, but still has to be shifted through each one. Its round trip time is in
+/// `frames` alongside the real PDI's, told apart by `data_len`.
+pub fn single_thread_pdi<const LEN: usize>(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let padding = [0u8; LEN];
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        Command::lrw(PADDING_ADDRESS)
+                            .send_slice(&client, &padding)
+                            .await
+                            .expect("Padding LRW");
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
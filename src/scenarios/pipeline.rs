@@ -0,0 +1,161 @@
+use super::{
+    compute::busy_work, create_client, create_groups, make_task_thread, pin_task_thread,
+    CycleMetadata, Cycles, Group, Storage, TestSettings,
+};
+use ethercrab::{self, slave_group::Op, Client};
+use futures_lite::StreamExt;
+use std::{
+    sync::mpsc::{self, Receiver, SyncSender, TryRecvError},
+    time::{Duration, Instant},
+};
+
+/// Process data computed from one cycle's inputs.
+struct Outputs(Vec<u8>);
+
+/// 1 cyclic thread doing TX/RX and one PDI loop, and 1 worker thread computing outputs, connected by
+/// bounded channels.
+///
+/// Each cycle hands its inputs to the worker and applies the outputs the worker computed from the
+/// previous cycle's inputs, so processing for cycle N+1 happens while cycle N is on the wire. The
+/// worker does `--compute-us` of busy work per cycle if set. If its outputs aren't ready in time,
+/// the previous outputs are sent again and the cycle is counted as late.
+pub fn pipeline(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    // One cycle in flight each way is all double buffering needs
+    let (inputs_tx, inputs_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+    let (outputs_tx, outputs_rx) = mpsc::sync_channel::<Outputs>(1);
+
+    std::thread::scope(|s| {
+        make_task_thread(settings)
+            .name("ethercrab-worker")
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                worker(settings, inputs_rx, outputs_tx);
+            })
+            .expect("Worker thread");
+
+        make_task_thread(settings)
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let mut late = 0usize;
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        group.tx_rx(&client).await.expect("TX/RX");
+
+                        match outputs_rx.try_recv() {
+                            Ok(outputs) => apply_outputs(&mut group, &client, &outputs),
+                            // Nothing computed yet on the first cycle
+                            Err(TryRecvError::Empty) if cycle > 0 => late += 1,
+                            Err(TryRecvError::Empty) => (),
+                            Err(TryRecvError::Disconnected) => panic!("Worker stopped"),
+                        }
+
+                        // Worker still busy with the previous cycle, which also counts as late
+                        inputs_tx.try_send(inputs(&mut group, &client)).ok();
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    // Stops the worker
+                    drop(inputs_tx);
+
+                    log::info!("--> {} of {} cycles' outputs were late", late, iterations);
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Compute outputs for every set of inputs until the cyclic thread hangs up.
+fn worker(settings: &TestSettings, inputs: Receiver<Vec<u8>>, outputs: SyncSender<Outputs>) {
+    let compute = Duration::from_micros(settings.compute_us.unwrap_or(0).into());
+
+    let mut state = Vec::new();
+
+    for inputs in inputs {
+        // Increment every output byte by one, like `loop_tick`
+        state.resize(inputs.len(), 0u8);
+
+        for byte in state.iter_mut() {
+            *byte = byte.wrapping_add(1);
+        }
+
+        busy_work(compute);
+
+        if outputs.send(Outputs(state.clone())).is_err() {
+            break;
+        }
+    }
+}
+
+/// Every device's inputs, one after the other.
+fn inputs(group: &mut Group<Op>, client: &Client<'_>) -> Vec<u8> {
+    group
+        .iter(client)
+        .flat_map(|device| device.io_raw().0.to_vec())
+        .collect()
+}
+
+/// Copy outputs into every device in turn. Devices with no computed outputs are left alone.
+fn apply_outputs(group: &mut Group<Op>, client: &Client<'_>, outputs: &Outputs) {
+    let mut computed = outputs.0.iter();
+
+    for mut device in group.iter(client) {
+        let (_i, o) = device.io_raw_mut();
+
+        for (byte, computed) in o.iter_mut().zip(computed.by_ref()) {
+            *byte = *computed;
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use super::{
+    create_client_raw, create_groups, loop_tick, make_task_thread,
+    nanosleep::{advance, monotonic_now, sleep_until},
+    pin_task_thread,
+    raw_socket::{RawSocket, FRAME_BUF_LEN},
+    CycleMetadata, Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, PduRx, PduTx};
+use std::{
+    future::Future,
+    io,
+    os::fd::AsRawFd,
+    pin::pin,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+/// Longest time to block waiting for a frame before polling again, so EtherCrab's timeouts and
+/// delays still make progress.
+const POLL_TIMEOUT_MS: libc::c_int = 1;
+
+/// Single thread with no async executor at all.
+///
+/// Futures are polled by hand with a no-op waker. Between polls, queued frames are sent and the
+/// thread blocks in `poll(2)` until a response arrives, then the future is polled again. Cycles
+/// are ticked with `clock_nanosleep` like `1thr-1task-nanosleep`, so comparing the two shows what
+/// the executor and EtherCrab's `tx_rx_task` cost.
+pub fn single_thread_poll(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx, rx) = create_client_raw(settings, &storage);
+
+                let mut net = PollLoop::new(&settings.nic, tx, rx).expect("Poll loop");
+
+                let mut groups = net.block_on(create_groups(settings, &client))?;
+
+                // The time it takes to traverse to the end of the EtherCAT network and back again.
+                let network_propagation_time_ns = groups
+                    .iter_mut()
+                    .flat_map(|group| group.iter(&client))
+                    .map(|device| device.propagation_delay())
+                    .max()
+                    .expect("Unable to compute prop time");
+
+                let [group, ..] = groups;
+
+                let mut group = net.block_on(group.into_op(&client)).expect("PRE-OP -> OP");
+
+                let cycle_time = Duration::from_micros(settings.cycle_time_us.into());
+
+                let mut next_tick = monotonic_now();
+                advance(&mut next_tick, cycle_time);
+
+                let mut prev = Instant::now();
+
+                let iterations = settings.iterations(5000);
+                let mut cycles = Cycles::new(settings, iterations);
+
+                for cycle in 0..iterations {
+                    let loop_start = Instant::now();
+
+                    net.block_on(loop_tick(&mut group, &client));
+
+                    let processed = Instant::now();
+
+                    sleep_until(&next_tick);
+
+                    advance(&mut next_tick, cycle_time);
+
+                    let tick_end = Instant::now();
+
+                    cycles.push(CycleMetadata::timed(
+                        cycle, loop_start, processed, tick_end, prev,
+                    ));
+
+                    prev = tick_end;
+
+                    if cycles.is_aborted() {
+                        break;
+                    }
+                }
+
+                Ok((cycles, network_propagation_time_ns))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Drives the PDU loop and a single future on the current thread.
+struct PollLoop<'sto> {
+    socket: RawSocket,
+    tx: PduTx<'sto>,
+    rx: PduRx<'sto>,
+    send_buf: [u8; FRAME_BUF_LEN],
+    recv_buf: [u8; FRAME_BUF_LEN],
+}
+
+impl<'sto> PollLoop<'sto> {
+    fn new(interface: &str, tx: PduTx<'sto>, rx: PduRx<'sto>) -> io::Result<Self> {
+        let socket = RawSocket::open(interface, libc::SOCK_NONBLOCK)?;
+
+        Ok(Self {
+            socket,
+            tx,
+            rx,
+            send_buf: [0u8; FRAME_BUF_LEN],
+            recv_buf: [0u8; FRAME_BUF_LEN],
+        })
+    }
+
+    /// Poll `future` until it completes, doing network IO in between.
+    fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = pin!(future);
+
+        // Nothing needs waking: the future is polled again after every trip round the loop
+        let mut ctx = Context::from_waker(Waker::noop());
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+                return output;
+            }
+
+            self.send().expect("TX");
+            self.receive().expect("RX");
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.tx.next_sendable_frame() {
+            frame
+                .send_blocking(&mut self.send_buf, |data| {
+                    self.socket
+                        .send(data)
+                        .map_err(|_| ethercrab::error::Error::SendFrame)?;
+
+                    Ok(data.len())
+                })
+                .map_err(|e| io::Error::other(format!("Send frame: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait up to [`POLL_TIMEOUT_MS`] for a frame, then receive everything that's arrived.
+    fn receive(&mut self) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        match unsafe { libc::poll(&mut pollfd, 1, POLL_TIMEOUT_MS) } {
+            -1 => {
+                let e = io::Error::last_os_error();
+
+                return if e.kind() == io::ErrorKind::Interrupted {
+                    Ok(())
+                } else {
+                    Err(e)
+                };
+            }
+            // Timed out
+            0 => return Ok(()),
+            _ => (),
+        }
+
+        loop {
+            let len = match self.socket.recv(&mut self.recv_buf) {
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            self.rx
+                .receive_frame(&self.recv_buf[0..len])
+                .map_err(|e| io::Error::other(format!("Receive frame: {}", e)))?;
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! A raw `AF_PACKET` socket for scenarios that drive the network themselves instead of using
+//! EtherCrab's `tx_rx_task`.
+
+use crate::capture::ETHERCAT_ETHERTYPE;
+use std::{
+    ffi::CString,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    time::Duration,
+};
+
+/// Large enough for any Ethernet II frame without jumbo frames or VLAN tags.
+pub const FRAME_BUF_LEN: usize = 1514;
+
+/// A raw socket bound to one interface that only receives EtherCAT frames.
+pub struct RawSocket {
+    fd: OwnedFd,
+}
+
+impl RawSocket {
+    /// Open a socket on `interface`. `flags` are passed through to `socket(2)`, e.g.
+    /// `libc::SOCK_NONBLOCK`.
+    pub fn open(interface: &str, flags: libc::c_int) -> io::Result<Self> {
+        let ifindex = interface_index(interface)?;
+
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | flags,
+                i32::from(ETHERCAT_ETHERTYPE.to_be()),
+            )
+        };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let sockaddr = libc::sockaddr_ll {
+            sll_family: libc::AF_PACKET as u16,
+            sll_protocol: ETHERCAT_ETHERTYPE.to_be(),
+            sll_ifindex: ifindex as i32,
+            sll_hatype: 1,
+            sll_pkttype: 0,
+            sll_halen: 6,
+            sll_addr: [0; 8],
+        };
+
+        let res = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &sockaddr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+
+        if res == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Send a whole frame.
+    pub fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let sent = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                frame.as_ptr() as *const libc::c_void,
+                frame.len(),
+                0,
+            )
+        };
+
+        match sent {
+            -1 => Err(io::Error::last_os_error()),
+            sent if sent as usize != frame.len() => Err(io::Error::other(format!(
+                "Sent {} of {} bytes",
+                sent,
+                frame.len()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Receive a frame into `buf`, returning its length.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+
+        if len == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+
+    /// Make blocking receives give up with [`io::ErrorKind::WouldBlock`] after `timeout`.
+    pub fn set_recv_timeout(&self, timeout: Duration) -> io::Result<()> {
+        let timeval = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+
+        let res = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeval as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+
+        if res == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+/// Look up the kernel's index for a network interface.
+pub fn interface_index(interface: &str) -> io::Result<u32> {
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Bad interface name"))?;
+
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::last_os_error()),
+        ifindex => Ok(ifindex),
+    }
+}
+
+impl AsRawFd for RawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -0,0 +1,164 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Group, Storage, TestSettings,
+};
+use ethercrab::{self, Client};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Time between SDO transfers.
+const SDO_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Device type. Every CoE device has it, so it's used to find one.
+const DEVICE_TYPE: u16 = 0x1000;
+
+/// Sync error counter limit. Written back with the value read from it, so it's a harmless write
+/// when a device has it.
+const SYNC_ERROR_LIMIT: (u16, u8) = (0x10f1, 2);
+
+/// Single thread with TX/RX, one PDI loop and a task doing SDO reads and writes to another device
+/// every [`SDO_INTERVAL`].
+///
+/// Mailbox frames don't use LRW so they're never paired with the cyclic PDUs in the captured
+/// dump, but they still share the TX/RX task, the network and the devices with them.
+pub fn single_thread_sdo(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                let mut groups =
+                    futures_lite::future::block_on(local_ex.run(create_groups(settings, &client)))?;
+
+                // The time it takes to traverse to the end of the EtherCAT network and back again.
+                let network_propagation_time_ns = groups
+                    .iter_mut()
+                    .flat_map(|group| group.iter(&client))
+                    .map(|device| device.propagation_delay())
+                    .max()
+                    .expect("Unable to compute prop time");
+
+                let mut groups = groups.into_iter().collect::<Vec<_>>();
+
+                let sdo_position = futures_lite::future::block_on(
+                    local_ex.run(find_coe_group(&mut groups, &client)),
+                )
+                .expect("No devices support CoE");
+
+                let mut sdo_group = groups.remove(sdo_position);
+                let group = groups.remove(0);
+
+                let sdo_client = &client;
+
+                // Left to run until the executor is dropped at the end of the cyclic task
+                local_ex
+                    .spawn(async move { sdo_traffic(&mut sdo_group, sdo_client).await })
+                    .detach();
+
+                let cycles =
+                    futures_lite::future::block_on(local_ex.run(task(group, &client, settings)));
+
+                Ok((cycles, network_propagation_time_ns))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Find the first group with a device that answers SDO reads.
+async fn find_coe_group(groups: &mut [Group], client: &Client<'_>) -> Option<usize> {
+    for (position, group) in groups.iter_mut().enumerate() {
+        let Some(device) = group.iter(client).next() else {
+            continue;
+        };
+
+        if device.sdo_read::<u32>(DEVICE_TYPE, 0).await.is_ok() {
+            log::info!("Sending SDOs to {}", device.name());
+
+            return Some(position);
+        }
+    }
+
+    None
+}
+
+/// Read the device type and, if the device has one, read and write back the sync error counter
+/// limit, forever.
+///
+/// The group stays in PRE-OP, where the mailbox is still available.
+async fn sdo_traffic(group: &mut Group, client: &Client<'_>) {
+    let device = group.iter(client).next().expect("Empty SDO group");
+
+    let (index, sub_index) = SYNC_ERROR_LIMIT;
+
+    let writes = device.sdo_read::<u16>(index, sub_index).await.is_ok();
+
+    let mut tick = smol::Timer::interval(SDO_INTERVAL);
+
+    loop {
+        if let Err(e) = device.sdo_read::<u32>(DEVICE_TYPE, 0).await {
+            log::warn!("SDO read failed: {}", e);
+        }
+
+        if writes {
+            let res = async {
+                let limit = device.sdo_read::<u16>(index, sub_index).await?;
+
+                device.sdo_write(index, sub_index, limit).await
+            };
+
+            if let Err(e) = res.await {
+                log::warn!("SDO write failed: {}", e);
+            }
+        }
+
+        tick.next().await;
+    }
+}
+
+async fn task(group: Group, client: &Client<'_>, settings: &TestSettings) -> Cycles {
+    let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
+    let mut tick = smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+    let mut prev = Instant::now();
+
+    let iterations = settings.iterations(5000);
+
+    let mut cycles = Cycles::new(settings, iterations);
+
+    for cycle in 0..iterations {
+        let loop_start = Instant::now();
+
+        loop_tick(&mut group, client).await;
+
+        let processed = Instant::now();
+
+        tick.next().await;
+
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
+
+        prev = tick_end;
+
+        if cycles.is_aborted() {
+            break;
+        }
+    }
+
+    cycles
+}
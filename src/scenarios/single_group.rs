@@ -0,0 +1,79 @@
+use super::{
+    create_client, loop_tick, make_task_thread, pin_task_thread, CycleMetadata, Cycles,
+    SingleGroup, Storage, TestSettings, MAX_SLAVES, SINGLE_GROUP_PDI,
+};
+use ethercrab::{self};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Single thread with TX/RX and one PDI loop for a group containing every device.
+///
+/// The whole PDI goes out in one LRW each cycle, which is how most applications are set up, unlike
+/// the other scenarios that split devices across 10 groups.
+pub fn single_group(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut group: SingleGroup = client
+                        .init_single_group::<MAX_SLAVES, SINGLE_GROUP_PDI>()
+                        .await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = group
+                        .iter(&client)
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
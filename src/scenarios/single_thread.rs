@@ -1,7 +1,8 @@
 use super::{
-    create_client, create_groups, loop_tick, make_task_thread, CycleMetadata, TestSettings,
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
 };
-use ethercrab::{self, PduStorage};
+use ethercrab::{self};
 use futures_lite::StreamExt;
 use std::time::{Duration, Instant};
 
@@ -10,24 +11,24 @@ use std::time::{Duration, Instant};
 /// This function forces `smol` to not start an IO thread in the background, giving a more
 /// representative worst case. In real code, one would either use a `static` `PduStorage`, or spawn
 /// scoped threads so it's easier to use `smol::spawn`, `smol::block_on`, etc.
-pub fn single_thread(
-    settings: &TestSettings,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
+pub fn single_thread(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
     std::thread::scope(|s| {
         let builder = make_task_thread(settings);
 
         builder
             .spawn_scoped(s, |_| {
-                let storage = PduStorage::new();
+                pin_task_thread(settings);
 
-                let (client, tx_rx) = create_client(&settings.nic, &storage);
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
 
                 let local_ex = smol::LocalExecutor::new();
 
                 local_ex.spawn(tx_rx).detach();
 
                 futures_lite::future::block_on(local_ex.run(async move {
-                    let mut groups = create_groups(&client).await?;
+                    let mut groups = create_groups(settings, &client).await?;
 
                     // The time it takes to traverse to the end of the EtherCAT network and back again.
                     let network_propagation_time_ns = groups
@@ -46,30 +47,29 @@ pub fn single_thread(
 
                     let mut prev = Instant::now();
 
-                    let iterations = 5000usize;
-                    let mut cycles = Vec::with_capacity(iterations);
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
 
                     for cycle in 0..iterations {
                         let loop_start = Instant::now();
 
                         loop_tick(&mut group, &client).await;
 
-                        let processing_time_ns = loop_start.elapsed().as_nanos();
+                        let processed = Instant::now();
 
                         tick.next().await;
 
-                        let tick_wait_ns = loop_start.elapsed().as_nanos() - processing_time_ns;
+                        let tick_end = Instant::now();
 
-                        let cycle_time_delta_ns = prev.elapsed().as_nanos();
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
 
-                        cycles.push(CycleMetadata {
-                            cycle,
-                            processing_time_ns: processing_time_ns as u32,
-                            tick_wait_ns: tick_wait_ns as u32,
-                            cycle_time_delta_ns: cycle_time_delta_ns as u32,
-                        });
+                        prev = tick_end;
 
-                        prev = Instant::now();
+                        if cycles.is_aborted() {
+                            break;
+                        }
                     }
 
                     Ok((cycles, network_propagation_time_ns))
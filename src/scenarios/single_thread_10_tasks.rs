@@ -1,7 +1,8 @@
 use super::{
-    create_client, create_groups, loop_tick, make_task_thread, CycleMetadata, TestSettings,
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Group, Storage, TestSettings,
 };
-use ethercrab::{self, PduStorage};
+use ethercrab::{self};
 use futures_lite::StreamExt;
 use std::time::{Duration, Instant};
 
@@ -12,22 +13,24 @@ use std::time::{Duration, Instant};
 /// scoped threads so it's easier to use `smol::spawn`, `smol::block_on`, etc.
 pub fn single_thread_10_tasks(
     settings: &TestSettings,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
     std::thread::scope(|s| {
         let builder = make_task_thread(settings);
 
         builder
             .spawn_scoped(s, |_| {
-                let storage = PduStorage::new();
+                pin_task_thread(settings);
 
-                let (client, tx_rx) = create_client(&settings.nic, &storage);
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
 
                 let local_ex = smol::LocalExecutor::new();
 
                 local_ex.spawn(tx_rx).detach();
 
                 let mut groups =
-                    futures_lite::future::block_on(local_ex.run(create_groups(&client)))?;
+                    futures_lite::future::block_on(local_ex.run(create_groups(settings, &client)))?;
 
                 // The time it takes to traverse to the end of the EtherCAT network and back again.
                 let network_propagation_time_ns = groups
@@ -45,7 +48,7 @@ pub fn single_thread_10_tasks(
                     )),
                 );
 
-                let groups = groups.into_iter().flatten().collect::<Vec<_>>();
+                let groups = groups.into_iter().collect::<Cycles>();
 
                 Ok((groups, network_propagation_time_ns))
             })
@@ -55,39 +58,35 @@ pub fn single_thread_10_tasks(
     })
 }
 
-async fn task(
-    group: ethercrab::SlaveGroup<1, 16>,
-    client: &ethercrab::Client<'_>,
-    settings: &TestSettings,
-) -> Vec<CycleMetadata> {
+async fn task(group: Group, client: &ethercrab::Client<'_>, settings: &TestSettings) -> Cycles {
     let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
     let mut tick = smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
     let mut prev = Instant::now();
 
-    let iterations = 2000usize;
+    let iterations = settings.iterations(2000);
 
-    let mut cycles = Vec::with_capacity(iterations);
+    let mut cycles = Cycles::new(settings, iterations);
 
     for cycle in 0..iterations {
         let loop_start = Instant::now();
 
         loop_tick(&mut group, client).await;
 
-        let processing_time_ns = loop_start.elapsed().as_nanos();
+        let processed = Instant::now();
 
         tick.next().await;
 
-        let tick_wait_ns = loop_start.elapsed().as_nanos() - processing_time_ns;
-        let cycle_time_delta_ns = prev.elapsed().as_nanos();
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
 
-        cycles.push(CycleMetadata {
-            cycle,
-            processing_time_ns: processing_time_ns as u32,
-            tick_wait_ns: tick_wait_ns as u32,
-            cycle_time_delta_ns: cycle_time_delta_ns as u32,
-        });
+        prev = tick_end;
 
-        prev = Instant::now();
+        if cycles.is_aborted() {
+            break;
+        }
     }
 
     cycles
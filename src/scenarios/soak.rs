@@ -0,0 +1,110 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Name of the soak scenario. Its cycles are streamed to disk instead of kept in memory.
+pub const SOAK_SCENARIO: &str = "1thr-1task-soak";
+
+/// How often to log progress through a soak run.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The same as [`single_thread`](super::single_thread::single_thread), but runs for `--soak-secs`
+/// instead of a fixed number of cycles.
+///
+/// Only streaming statistics are kept in memory. Every cycle is streamed to a file next to the
+/// run's dump, which is read back in chunks during ingest, so memory use stays flat however long
+/// the run is.
+pub fn soak(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let duration = Duration::from_secs(settings.soak_secs.expect("No soak duration configured"));
+
+    // Cycles are streamed, so don't keep them around as well
+    let summary_settings = TestSettings {
+        summary_only: true,
+        ..settings.clone()
+    };
+
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let cycle_time = Duration::from_micros(settings.cycle_time_us.into());
+                    let mut tick = smol::Timer::interval(cycle_time);
+                    let mut prev = Instant::now();
+
+                    let iterations = (duration.as_micros() / cycle_time.as_micros()) as usize;
+                    let mut cycles = Cycles::new(&summary_settings, iterations);
+
+                    let start = Instant::now();
+                    let mut next_progress = start + PROGRESS_INTERVAL;
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+
+                        if tick_end >= next_progress {
+                            next_progress += PROGRESS_INTERVAL;
+
+                            log::info!(
+                                "--> Soak {} of {} s, {} deadline misses",
+                                start.elapsed().as_secs(),
+                                duration.as_secs(),
+                                cycles.summary.deadline_misses
+                            );
+                        }
+                    }
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
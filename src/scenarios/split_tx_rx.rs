@@ -0,0 +1,133 @@
+use super::{
+    create_client_raw, create_groups,
+    doorbell::Doorbell,
+    make_net_thread, make_rx_thread, make_task_thread, pin_net_thread, pin_task_thread,
+    raw_socket::{RawSocket, FRAME_BUF_LEN},
+    thread_per_task::task,
+    Cycles, Storage, TestSettings,
+};
+use ethercrab::{self, PduRx, PduTx};
+use std::{io, sync::Arc, task::Waker, time::Duration};
+
+/// How often the RX thread checks whether it should stop when nothing is being received.
+const RX_STOP_POLL: Duration = Duration::from_millis(100);
+
+/// 1 TX thread, 1 RX thread and 1 task thread.
+///
+/// The same as `2thr-1task`, but sending and receiving are done by separate threads with blocking
+/// syscalls. The TX thread runs at the net priority and the RX thread at `--rx-prio`, if given.
+pub fn split_tx_rx(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let storage = Storage::new(settings);
+
+    let (client, tx, rx) = create_client_raw(settings, &storage);
+
+    // Blocking, shared by both threads
+    let socket = RawSocket::open(&settings.nic, 0).expect("Raw socket");
+
+    socket
+        .set_recv_timeout(RX_STOP_POLL)
+        .expect("Socket receive timeout");
+
+    let doorbell = Doorbell::new().expect("Doorbell");
+
+    std::thread::scope(|s| {
+        make_net_thread(settings)
+            .spawn_scoped(s, |_| {
+                pin_net_thread(settings);
+
+                if let Err(e) = send_loop(&socket, tx, &doorbell) {
+                    log::error!("TX failed: {}", e);
+                }
+            })
+            .expect("TX thread");
+
+        make_rx_thread(settings)
+            .spawn_scoped(s, |_| {
+                pin_net_thread(settings);
+
+                if let Err(e) = receive_loop(&socket, rx, &doorbell) {
+                    log::error!("RX failed: {}", e);
+                }
+            })
+            .expect("RX thread");
+
+        let result = (|| {
+            let mut groups = smol::block_on(create_groups(settings, &client))?;
+
+            // The time it takes to traverse to the end of the EtherCAT network and back again.
+            let network_propagation_time_ns = groups
+                .iter_mut()
+                .flat_map(|group| group.iter(&client))
+                .map(|device| device.propagation_delay())
+                .max()
+                .expect("Unable to compute prop time");
+
+            let [group, ..] = groups;
+
+            let cycles = make_task_thread(settings)
+                .spawn_scoped_careless(s, || {
+                    pin_task_thread(settings);
+
+                    let local_ex = smol::LocalExecutor::new();
+
+                    futures_lite::future::block_on(local_ex.run(task(group, &client, settings)))
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            Ok((cycles, network_propagation_time_ns))
+        })();
+
+        // Stop both net threads, otherwise the scope waits on them forever.
+        doorbell.stop();
+
+        result
+    })
+}
+
+/// Send frames whenever the PDU loop rings the doorbell, until it's stopped.
+fn send_loop(socket: &RawSocket, mut tx: PduTx<'_>, doorbell: &Arc<Doorbell>) -> io::Result<()> {
+    tx.replace_waker(&Waker::from(Arc::clone(doorbell)));
+
+    let mut send_buf = [0u8; FRAME_BUF_LEN];
+
+    while !doorbell.is_stopped() {
+        while let Some(frame) = tx.next_sendable_frame() {
+            frame
+                .send_blocking(&mut send_buf, |data| {
+                    socket
+                        .send(data)
+                        .map_err(|_| ethercrab::error::Error::SendFrame)?;
+
+                    Ok(data.len())
+                })
+                .map_err(|e| io::Error::other(format!("Send frame: {}", e)))?;
+        }
+
+        // Blocks until the next frame is queued or the loop is stopped
+        doorbell.clear();
+    }
+
+    Ok(())
+}
+
+/// Receive frames until the doorbell is stopped.
+fn receive_loop(socket: &RawSocket, mut rx: PduRx<'_>, doorbell: &Doorbell) -> io::Result<()> {
+    let mut recv_buf = [0u8; FRAME_BUF_LEN];
+
+    while !doorbell.is_stopped() {
+        let len = match socket.recv(&mut recv_buf) {
+            Ok(len) => len,
+            // Timed out, check whether to stop
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        rx.receive_frame(&recv_buf[0..len])
+            .map_err(|e| io::Error::other(format!("Receive frame: {}", e)))?;
+    }
+
+    Ok(())
+}
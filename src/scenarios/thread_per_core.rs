@@ -0,0 +1,123 @@
+use super::{
+    create_client, create_groups, make_net_thread, make_task_thread, pin_current_thread,
+    pin_net_thread, prefault_thread, thread_per_task::task, Cycles, Storage, TestSettings, GROUPS,
+};
+use crate::system::physical_cores;
+use ethercrab::{self};
+use futures_lite::future;
+use std::{sync::Arc, thread::ScopedJoinHandle};
+
+/// 1 tx/rx thread and one task thread per physical core, each with its own `LocalExecutor` running
+/// a single group task.
+///
+/// Every task thread is pinned to its own core so nothing moves between cores, like the thread per
+/// core layout many RT applications use. The tx/rx thread takes the first core, or the net CPU from
+/// `--isolated-cores` in which case task threads only use the isolated task CPUs. There are as many
+/// task threads as there are cores left, up to one per group.
+pub fn thread_per_core(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let (net_cpu, task_cpus) = cores(settings);
+
+    log::info!(
+        "Net thread on CPU {:?}, task threads on CPUs {:?}",
+        net_cpu,
+        task_cpus
+    );
+
+    let storage = Storage::new(settings);
+
+    let (client, tx_rx) = create_client(settings, &storage);
+
+    std::thread::scope(|s| {
+        let client = Arc::new(client);
+
+        let (net_tx, net_rx) = smol::channel::bounded(1);
+
+        make_net_thread(settings)
+            .spawn_scoped(s, move |_| {
+                match net_cpu {
+                    Some(cpu) => {
+                        prefault_thread(settings);
+                        pin_current_thread(&[cpu]);
+                    }
+                    None => pin_net_thread(settings),
+                }
+
+                let local_ex = smol::LocalExecutor::new();
+
+                futures_lite::future::block_on(local_ex.run(future::or(tx_rx, async {
+                    net_rx.recv().await.ok();
+
+                    Ok(())
+                })))
+            })
+            .expect("TX/RX thread");
+
+        let mut groups = smol::block_on(create_groups(settings, &client))?;
+
+        // The time it takes to traverse to the end of the EtherCAT network and back again.
+        let network_propagation_time_ns = groups
+            .iter_mut()
+            .flat_map(|group| group.iter(&client))
+            .map(|device| device.propagation_delay())
+            .max()
+            .expect("Unable to compute prop time");
+
+        let handles = groups
+            .into_iter()
+            .zip(task_cpus)
+            .map(|(group, cpu)| {
+                let client = client.clone();
+
+                make_task_thread(settings)
+                    .spawn_scoped_careless(s, move || {
+                        prefault_thread(settings);
+                        pin_current_thread(&[cpu]);
+
+                        let local_ex = smol::LocalExecutor::new();
+
+                        futures_lite::future::block_on(local_ex.run(task(group, &client, settings)))
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<ScopedJoinHandle<'_, Cycles>>>();
+
+        let results = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Cycles>();
+
+        // Stop net thread. Scoped thread hangs waiting on net task to join otherwise.
+        net_tx.send_blocking(()).ok();
+
+        Ok((results, network_propagation_time_ns))
+    })
+}
+
+/// CPU to pin the net thread to, if it isn't already pinned by `--isolated-cores`, and one CPU per
+/// task thread.
+fn cores(settings: &TestSettings) -> (Option<usize>, Vec<usize>) {
+    let cores = physical_cores();
+
+    let (net_cpu, mut task_cpus) = match settings.affinity.as_ref() {
+        Some(affinity) => (
+            None,
+            cores
+                .into_iter()
+                .filter(|cpu| affinity.task.contains(cpu))
+                .collect::<Vec<_>>(),
+        ),
+        None => match cores.split_first() {
+            Some((&net, rest)) => (Some(net), rest.to_vec()),
+            None => (None, Vec::new()),
+        },
+    };
+
+    assert!(
+        !task_cpus.is_empty(),
+        "No physical cores left for task threads"
+    );
+
+    task_cpus.truncate(GROUPS);
+
+    (net_cpu, task_cpus)
+}
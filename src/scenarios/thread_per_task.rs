@@ -1,8 +1,8 @@
 use super::{
-    create_client, create_groups, loop_tick, make_net_thread, make_task_thread, CycleMetadata,
-    TestSettings,
+    create_client, create_groups, loop_tick, make_net_thread, make_task_thread, pin_net_thread,
+    pin_task_thread, CycleMetadata, Cycles, Group, Storage, TestSettings,
 };
-use ethercrab::{self, PduStorage};
+use ethercrab::{self};
 use futures_lite::{future, StreamExt};
 use std::{
     sync::Arc,
@@ -11,33 +11,39 @@ use std::{
 };
 
 // Start 1 tx/rx thread and 1 task thread.
-pub fn two_threads(
-    settings: &TestSettings,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
-    inner(settings, 1)
+pub fn two_threads(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    inner(settings, 1, 1)
 }
 
 // Start 1 tx/rx thread and 2 task threads.
-pub fn three_threads(
-    settings: &TestSettings,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
-    inner(settings, 2)
+pub fn three_threads(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    inner(settings, 2, 2)
 }
 
 // Start 1 tx/rx thread and 10 task threads.
-pub fn eleven_threads(
-    settings: &TestSettings,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
-    inner(settings, 10)
+pub fn eleven_threads(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    inner(settings, 10, 10)
+}
+
+// Start 1 tx/rx thread and the task threads and tasks from `--threads` and `--tasks`.
+pub fn custom_threads(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let layout = settings
+        .thread_layout
+        .as_ref()
+        .expect("No thread layout configured");
+
+    inner(settings, layout.task_threads, layout.tasks)
 }
 
+/// Run `num_tasks` tasks spread evenly over `num_threads` task threads, plus a TX/RX thread.
 fn inner(
     settings: &TestSettings,
+    num_threads: usize,
     num_tasks: usize,
-) -> Result<(Vec<CycleMetadata>, u32), ethercrab::error::Error> {
-    let storage = PduStorage::new();
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let storage = Storage::new(settings);
 
-    let (client, tx_rx) = create_client(&settings.nic, &storage);
+    let (client, tx_rx) = create_client(settings, &storage);
 
     std::thread::scope(|s| {
         let client = Arc::new(client);
@@ -46,6 +52,8 @@ fn inner(
 
         make_net_thread(settings)
             .spawn_scoped(s, move |_| {
+                pin_net_thread(settings);
+
                 let local_ex = smol::LocalExecutor::new();
 
                 futures_lite::future::block_on(local_ex.run(future::or(tx_rx, async {
@@ -57,7 +65,7 @@ fn inner(
             })
             .expect("TX/RX thread");
 
-        let mut groups = smol::block_on(create_groups(&client))?;
+        let mut groups = smol::block_on(create_groups(settings, &client))?;
 
         // The time it takes to traverse to the end of the EtherCAT network and back again.
         let network_propagation_time_ns = groups
@@ -67,29 +75,42 @@ fn inner(
             .max()
             .expect("Unable to compute prop time");
 
-        let groups = groups.into_iter().take(num_tasks).collect::<Vec<_>>();
+        // Deal groups out to threads round robin
+        let mut thread_groups = (0..num_threads).map(|_| Vec::new()).collect::<Vec<_>>();
+
+        for (i, group) in groups.into_iter().take(num_tasks).enumerate() {
+            thread_groups[i % num_threads].push(group);
+        }
 
-        let handles = groups
+        let handles = thread_groups
             .into_iter()
-            .map(|group| {
+            .map(|groups| {
                 let client = client.clone();
 
                 make_task_thread(settings)
                     .spawn_scoped_careless(s, move || {
+                        pin_task_thread(settings);
+
                         let local_ex = smol::LocalExecutor::new();
 
                         futures_lite::future::block_on(
-                            local_ex.run(task(group, &client, &settings)),
+                            local_ex.run(futures::future::join_all(
+                                groups
+                                    .into_iter()
+                                    .map(|group| task(group, &client, settings)),
+                            )),
                         )
+                        .into_iter()
+                        .collect::<Cycles>()
                     })
                     .unwrap()
             })
-            .collect::<Vec<ScopedJoinHandle<'_, Vec<CycleMetadata>>>>();
+            .collect::<Vec<ScopedJoinHandle<'_, Cycles>>>();
 
         let results = handles
             .into_iter()
-            .flat_map(|handle| handle.join().unwrap().into_iter())
-            .collect::<Vec<CycleMetadata>>();
+            .map(|handle| handle.join().unwrap())
+            .collect::<Cycles>();
 
         // Stop net thread. Scoped thread hangs waiting on net task to join otherwise.
         net_tx.send_blocking(()).ok();
@@ -98,39 +119,39 @@ fn inner(
     })
 }
 
-async fn task(
-    group: ethercrab::SlaveGroup<1, 16>,
+pub(super) async fn task(
+    group: Group,
     client: &ethercrab::Client<'_>,
     settings: &TestSettings,
-) -> Vec<CycleMetadata> {
+) -> Cycles {
     let mut group = group.into_op(client).await.expect("PRE-OP -> OP");
     let mut tick = smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
     let mut prev = Instant::now();
 
-    let iterations = 5000usize;
+    let iterations = settings.iterations(5000);
 
-    let mut cycles = Vec::with_capacity(iterations);
+    let mut cycles = Cycles::new(settings, iterations);
 
     for cycle in 0..iterations {
         let loop_start = Instant::now();
 
         loop_tick(&mut group, client).await;
 
-        let processing_time_ns = loop_start.elapsed().as_nanos();
+        let processed = Instant::now();
 
         tick.next().await;
 
-        let tick_wait_ns = loop_start.elapsed().as_nanos() - processing_time_ns;
-        let cycle_time_delta_ns = prev.elapsed().as_nanos();
+        let tick_end = Instant::now();
+
+        cycles.push(CycleMetadata::timed(
+            cycle, loop_start, processed, tick_end, prev,
+        ));
 
-        cycles.push(CycleMetadata {
-            cycle,
-            processing_time_ns: processing_time_ns as u32,
-            tick_wait_ns: tick_wait_ns as u32,
-            cycle_time_delta_ns: cycle_time_delta_ns as u32,
-        });
+        prev = tick_end;
 
-        prev = Instant::now();
+        if cycles.is_aborted() {
+            break;
+        }
     }
 
     cycles
@@ -0,0 +1,223 @@
+use super::{
+    create_client, create_groups, loop_tick, make_task_thread, pin_task_thread, CycleMetadata,
+    Cycles, Storage, TestSettings, Transition,
+};
+use ethercrab::{self, Client, Command, RegisterAddress};
+use futures_lite::StreamExt;
+use std::time::{Duration, Instant};
+
+/// Cycle to knock the device out of OP at, leaving plenty of cycles either side.
+const FAULT_CYCLE: usize = 1000;
+
+/// AL control and status codes for each state the device is brought back through, in order.
+const STEPS: [(u16, &str); 4] = [
+    (0x01, "INIT"),
+    (0x02, "PRE-OP"),
+    (0x04, "SAFE-OP"),
+    (0x08, "OP"),
+];
+
+/// Set in the AL status register if the device refused a state change.
+const AL_ERROR: u16 = 0x10;
+
+/// A device being brought back to OP.
+struct Recovery {
+    /// Index into [`STEPS`] of the state last requested.
+    step: usize,
+    fault: Instant,
+    step_start: Instant,
+}
+
+/// Single thread with TX/RX and one PDI loop, with the last device in the network dropping out of
+/// OP partway through.
+///
+/// At cycle [`FAULT_CYCLE`] the device is requested into INIT, like a power glitch or cable pull
+/// would leave it. It's then brought back through PRE-OP and SAFE-OP to OP, one step at a time,
+/// with cycling carrying on throughout. Each step, plus `FAULT -> OP` for the whole recovery, is
+/// stored in `transitions`. Checking the device's state costs an extra PDU each cycle during
+/// recovery, which shows up in `cycles` as the latency spike a real master would see too.
+///
+/// Devices that forget their sync manager configuration in INIT refuse to leave it, in which case
+/// the refusal is logged and no recovery is recorded.
+pub fn single_thread_unplug(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    std::thread::scope(|s| {
+        let builder = make_task_thread(settings);
+
+        builder
+            .spawn_scoped(s, |_| {
+                pin_task_thread(settings);
+
+                let storage = Storage::new(settings);
+
+                let (client, tx_rx) = create_client(settings, &storage);
+
+                let local_ex = smol::LocalExecutor::new();
+
+                local_ex.spawn(tx_rx).detach();
+
+                futures_lite::future::block_on(local_ex.run(async move {
+                    let mut groups = create_groups(settings, &client).await?;
+
+                    // The time it takes to traverse to the end of the EtherCAT network and back again.
+                    let network_propagation_time_ns = groups
+                        .iter_mut()
+                        .flat_map(|group| group.iter(&client))
+                        .map(|device| device.propagation_delay())
+                        .max()
+                        .expect("Unable to compute prop time");
+
+                    let [group, ..] = groups;
+
+                    let mut group = group.into_op(&client).await.expect("PRE-OP -> OP");
+
+                    let device = group
+                        .iter(&client)
+                        .map(|device| device.configured_address())
+                        .max()
+                        .expect("No devices in group");
+
+                    let mut tick =
+                        smol::Timer::interval(Duration::from_micros(settings.cycle_time_us.into()));
+                    let mut prev = Instant::now();
+
+                    let mut recovery: Option<Recovery> = None;
+
+                    // Slowest cycle before the fault and while recovering from it
+                    let mut before_max_ns = 0u32;
+                    let mut recovery_max_ns = 0u32;
+
+                    let iterations = settings.iterations(5000);
+                    let mut cycles = Cycles::new(settings, iterations);
+
+                    for cycle in 0..iterations {
+                        let loop_start = Instant::now();
+
+                        loop_tick(&mut group, &client).await;
+
+                        if cycle == FAULT_CYCLE {
+                            request(&client, device, STEPS[0].0).await?;
+
+                            recovery = Some(Recovery {
+                                step: 0,
+                                fault: loop_start,
+                                step_start: loop_start,
+                            });
+                        } else if let Some(r) = recovery.as_mut() {
+                            let (status, _wkc) =
+                                Command::fprd(device, RegisterAddress::AlStatus.into())
+                                    .receive::<u16>(&client)
+                                    .await?;
+
+                            let (code, to) = STEPS[r.step];
+
+                            if status & AL_ERROR != 0 {
+                                let (status_code, _wkc) =
+                                    Command::fprd(device, RegisterAddress::AlStatusCode.into())
+                                        .receive::<u16>(&client)
+                                        .await?;
+
+                                log::warn!(
+                                    "Device {:#06x} refused {}, AL status code {:#06x}. Giving up",
+                                    device,
+                                    to,
+                                    status_code
+                                );
+
+                                recovery = None;
+                            } else if status & 0x0f == code {
+                                let now = Instant::now();
+
+                                cycles.transitions.push(Transition {
+                                    group_index: Some(0),
+                                    from: r.step.checked_sub(1).map_or("OP", |prev| STEPS[prev].1),
+                                    to,
+                                    duration_ns: (now - r.step_start).as_nanos() as u64,
+                                });
+
+                                if let Some((code, _)) = STEPS.get(r.step + 1) {
+                                    request(&client, device, *code).await?;
+
+                                    r.step += 1;
+                                    r.step_start = now;
+                                } else {
+                                    cycles.transitions.push(Transition {
+                                        group_index: Some(0),
+                                        from: "FAULT",
+                                        to: "OP",
+                                        duration_ns: (now - r.fault).as_nanos() as u64,
+                                    });
+
+                                    log::info!(
+                                        "--> Device {:#06x} back in OP after {} cycles, {} us",
+                                        device,
+                                        cycle - FAULT_CYCLE,
+                                        (now - r.fault).as_micros()
+                                    );
+
+                                    recovery = None;
+                                }
+                            }
+                        }
+
+                        let processed = Instant::now();
+
+                        tick.next().await;
+
+                        let tick_end = Instant::now();
+
+                        let processing_time_ns = (processed - loop_start).as_nanos() as u32;
+
+                        if cycle < FAULT_CYCLE {
+                            before_max_ns = before_max_ns.max(processing_time_ns);
+                        } else if recovery.is_some() {
+                            recovery_max_ns = recovery_max_ns.max(processing_time_ns);
+                        }
+
+                        cycles.push(CycleMetadata::timed(
+                            cycle, loop_start, processed, tick_end, prev,
+                        ));
+
+                        prev = tick_end;
+
+                        if cycles.is_aborted() {
+                            break;
+                        }
+                    }
+
+                    if let Some(r) = recovery {
+                        log::warn!(
+                            "Device {:#06x} still waiting for {} at end of run",
+                            device,
+                            STEPS[r.step].1
+                        );
+                    }
+
+                    log::info!(
+                        "--> Slowest cycle processing {} us before fault, {} us while recovering",
+                        before_max_ns / 1000,
+                        recovery_max_ns / 1000
+                    );
+
+                    Ok((cycles, network_propagation_time_ns))
+                }))
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Request a device go into the state with the given AL control code.
+async fn request(
+    client: &Client<'_>,
+    device: u16,
+    code: u16,
+) -> Result<(), ethercrab::error::Error> {
+    Command::fpwr(device, RegisterAddress::AlControl.into())
+        .send(client, code)
+        .await?;
+
+    Ok(())
+}
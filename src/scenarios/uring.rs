@@ -0,0 +1,234 @@
+use super::{
+    create_client_raw, create_groups,
+    doorbell::Doorbell,
+    make_net_thread, make_task_thread, pin_net_thread, pin_task_thread,
+    raw_socket::{RawSocket, FRAME_BUF_LEN},
+    thread_per_task::task,
+    Cycles, Storage, TestSettings, MAX_FRAMES,
+};
+use ethercrab::{self, PduRx, PduTx};
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use std::{io, os::fd::AsRawFd, sync::Arc, task::Waker};
+
+/// Name of the io_uring scenario. Only run with `--io-uring`, as not every kernel allows io_uring.
+pub const URING_SCENARIO: &str = "2thr-1task-uring";
+
+/// Room for a send per frame plus the receive and eventfd read.
+const RING_ENTRIES: u32 = (MAX_FRAMES * 2) as u32;
+
+/// Completion tag for the eventfd read. Send completions are tagged with their buffer slot.
+const WAKE: u64 = u64::MAX;
+/// Completion tag for the socket receive.
+const RECV: u64 = u64::MAX - 1;
+
+/// 1 TX/RX thread driving the raw socket with io_uring and 1 task thread.
+///
+/// The same as `2thr-1task`, but TX/RX uses submission/completion queues instead of the epoll
+/// based `tx_rx_task` from EtherCrab.
+pub fn two_threads_uring(
+    settings: &TestSettings,
+) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let storage = Storage::new(settings);
+
+    let (client, tx, rx) = create_client_raw(settings, &storage);
+
+    let net = UringTxRx::new(&settings.nic, tx, rx).map_err(|e| {
+        log::error!("Failed to set up io_uring TX/RX: {}", e);
+
+        ethercrab::error::Error::SendFrame
+    })?;
+    let doorbell = net.doorbell();
+
+    std::thread::scope(|s| {
+        make_net_thread(settings)
+            .spawn_scoped(s, move |_| {
+                pin_net_thread(settings);
+
+                if let Err(e) = net.run() {
+                    log::error!("io_uring TX/RX failed: {}", e);
+                }
+            })
+            .expect("TX/RX thread");
+
+        let result = (|| {
+            let mut groups = smol::block_on(create_groups(settings, &client))?;
+
+            // The time it takes to traverse to the end of the EtherCAT network and back again.
+            let network_propagation_time_ns = groups
+                .iter_mut()
+                .flat_map(|group| group.iter(&client))
+                .map(|device| device.propagation_delay())
+                .max()
+                .expect("Unable to compute prop time");
+
+            let [group, ..] = groups;
+
+            let cycles = make_task_thread(settings)
+                .spawn_scoped_careless(s, || {
+                    pin_task_thread(settings);
+
+                    let local_ex = smol::LocalExecutor::new();
+
+                    futures_lite::future::block_on(local_ex.run(task(group, &client, settings)))
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            Ok((cycles, network_propagation_time_ns))
+        })();
+
+        // Stop net thread, otherwise the scope waits on it forever.
+        doorbell.stop();
+
+        result
+    })
+}
+
+/// Check a ring and the raw socket it drives can be set up, so the scenario is skipped on machines
+/// without io_uring instead of failing part way through.
+pub fn probe(interface: &str) -> io::Result<()> {
+    RawSocket::open(interface, 0)?;
+    IoUring::new(RING_ENTRIES)?;
+
+    Ok(())
+}
+
+struct UringTxRx<'sto> {
+    socket: RawSocket,
+    doorbell: Arc<Doorbell>,
+    tx: PduTx<'sto>,
+    rx: PduRx<'sto>,
+}
+
+impl<'sto> UringTxRx<'sto> {
+    fn new(interface: &str, tx: PduTx<'sto>, rx: PduRx<'sto>) -> io::Result<Self> {
+        // Blocking socket: io_uring handles readiness itself.
+        let socket = RawSocket::open(interface, 0)?;
+
+        let doorbell = Doorbell::new()?;
+
+        Ok(Self {
+            socket,
+            doorbell,
+            tx,
+            rx,
+        })
+    }
+
+    fn doorbell(&self) -> Arc<Doorbell> {
+        self.doorbell.clone()
+    }
+
+    /// Send queued frames and receive responses until [`Doorbell::stop`] is called.
+    ///
+    /// One receive and one eventfd read are always in flight. The PDU loop rings the eventfd when
+    /// a new frame is ready to send, so a single `io_uring_enter` waits for both directions.
+    fn run(mut self) -> io::Result<()> {
+        let socket = types::Fd(self.socket.as_raw_fd());
+        let eventfd = types::Fd(self.doorbell.as_raw_fd());
+
+        self.tx
+            .replace_waker(&Waker::from(Arc::clone(&self.doorbell)));
+
+        // Declared before the ring so they're dropped after it, as the receive and eventfd read
+        // are still in flight when the loop stops.
+        let mut wake_buf = 0u64;
+        let mut recv_buf = vec![0u8; FRAME_BUF_LEN];
+        // A send buffer can't be reused until its completion arrives
+        let mut send_bufs = vec![[0u8; FRAME_BUF_LEN]; MAX_FRAMES];
+        let mut free_slots = (0..MAX_FRAMES).collect::<Vec<_>>();
+
+        let mut ring = IoUring::new(RING_ENTRIES)?;
+
+        let read_wake = opcode::Read::new(
+            eventfd,
+            &mut wake_buf as *mut u64 as *mut u8,
+            std::mem::size_of::<u64>() as u32,
+        )
+        .build()
+        .user_data(WAKE);
+        let recv = opcode::Recv::new(socket, recv_buf.as_mut_ptr(), recv_buf.len() as u32)
+            .build()
+            .user_data(RECV);
+
+        let (submitter, mut sq, mut cq) = ring.split();
+
+        // SAFETY: Buffers outlive the ring and each is only in use by one queued operation at once.
+        unsafe {
+            push(&mut sq, &read_wake)?;
+            push(&mut sq, &recv)?;
+        }
+
+        let mut completions = Vec::with_capacity(MAX_FRAMES * 2);
+
+        while !self.doorbell.is_stopped() {
+            while let Some(&slot) = free_slots.last() {
+                let Some(frame) = self.tx.next_sendable_frame() else {
+                    break;
+                };
+
+                free_slots.pop();
+
+                frame
+                    .send_blocking(&mut send_bufs[slot], |data| {
+                        let send = opcode::Send::new(socket, data.as_ptr(), data.len() as u32)
+                            .build()
+                            .user_data(slot as u64);
+
+                        unsafe { push(&mut sq, &send) }
+                            .map_err(|_| ethercrab::error::Error::SendFrame)?;
+
+                        Ok(data.len())
+                    })
+                    .map_err(|e| io::Error::other(format!("Send frame: {}", e)))?;
+            }
+
+            sq.sync();
+
+            match submitter.submit_and_wait(1) {
+                Ok(_) => (),
+                Err(e) if e.raw_os_error() == Some(libc::EINTR) => continue,
+                Err(e) => return Err(e),
+            }
+
+            cq.sync();
+
+            completions.extend(
+                cq.by_ref()
+                    .map(|cqe: cqueue::Entry| (cqe.user_data(), cqe.result())),
+            );
+
+            for (user_data, result) in completions.drain(..) {
+                if result < 0 {
+                    return Err(io::Error::from_raw_os_error(-result));
+                }
+
+                match user_data {
+                    // Loop around to pick up new frames or the stop flag
+                    WAKE => unsafe { push(&mut sq, &read_wake)? },
+                    RECV => {
+                        self.rx
+                            .receive_frame(&recv_buf[0..result as usize])
+                            .map_err(|e| io::Error::other(format!("Receive frame: {}", e)))?;
+
+                        unsafe { push(&mut sq, &recv)? }
+                    }
+                    slot => free_slots.push(slot as usize),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Queue an operation.
+///
+/// # Safety
+///
+/// Any buffers referenced by `entry` must stay valid until its completion is reaped.
+unsafe fn push(sq: &mut squeue::SubmissionQueue<'_>, entry: &squeue::Entry) -> io::Result<()> {
+    sq.push(entry)
+        .map_err(|_| io::Error::other("io_uring submission queue full"))
+}
@@ -0,0 +1,712 @@
+//! TX/RX over an AF_XDP socket, bypassing the kernel network stack.
+//!
+//! A tiny XDP program is attached to the interface which redirects EtherCAT frames arriving on
+//! queue 0 into the socket and passes everything else up the stack as normal. Use `ethtool -L <nic>
+//! combined 1` on multi-queue NICs so EtherCAT responses aren't hashed to another queue.
+
+use super::{
+    create_client_raw, create_groups, doorbell::Doorbell, make_net_thread, make_task_thread,
+    pin_net_thread, pin_task_thread, raw_socket::interface_index, thread_per_task::task, Cycles,
+    Storage, TestSettings, MAX_FRAMES,
+};
+use ethercrab::{self, PduRx, PduTx};
+use std::{
+    ffi::CStr,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    ptr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    task::Waker,
+};
+
+/// Name of the AF_XDP scenario. Only run with `--xdp`, as it needs XDP support in the NIC driver.
+pub const XDP_SCENARIO: &str = "2thr-1task-xdp";
+
+/// Number of descriptors in each ring. Must be a power of 2.
+const RING_SIZE: u32 = MAX_FRAMES as u32;
+/// UMEM chunk size. Each chunk holds one frame.
+const FRAME_SIZE: usize = 2048;
+/// Half the UMEM chunks are for receiving, half for sending.
+const NUM_FRAMES: usize = RING_SIZE as usize * 2;
+const QUEUE_ID: u32 = 0;
+
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_PROG_LOAD: libc::c_int = 5;
+const BPF_LINK_CREATE: libc::c_int = 28;
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+const XDP_PASS: i32 = 2;
+
+/// 1 TX/RX thread using an AF_XDP socket and 1 task thread.
+///
+/// The same as `2thr-1task`, but frames skip the kernel network stack entirely.
+pub fn two_threads_xdp(settings: &TestSettings) -> Result<(Cycles, u32), ethercrab::error::Error> {
+    let storage = Storage::new(settings);
+
+    let (client, tx, rx) = create_client_raw(settings, &storage);
+
+    let net = XdpTxRx::new(&settings.nic, tx, rx).map_err(|e| {
+        log::error!("Failed to set up AF_XDP TX/RX: {}", e);
+
+        ethercrab::error::Error::SendFrame
+    })?;
+    let doorbell = net.doorbell.clone();
+
+    std::thread::scope(|s| {
+        make_net_thread(settings)
+            .spawn_scoped(s, move |_| {
+                pin_net_thread(settings);
+
+                if let Err(e) = net.run() {
+                    log::error!("AF_XDP TX/RX failed: {}", e);
+                }
+            })
+            .expect("TX/RX thread");
+
+        let result = (|| {
+            let mut groups = smol::block_on(create_groups(settings, &client))?;
+
+            // The time it takes to traverse to the end of the EtherCAT network and back again.
+            let network_propagation_time_ns = groups
+                .iter_mut()
+                .flat_map(|group| group.iter(&client))
+                .map(|device| device.propagation_delay())
+                .max()
+                .expect("Unable to compute prop time");
+
+            let [group, ..] = groups;
+
+            let cycles = make_task_thread(settings)
+                .spawn_scoped_careless(s, || {
+                    pin_task_thread(settings);
+
+                    let local_ex = smol::LocalExecutor::new();
+
+                    futures_lite::future::block_on(local_ex.run(task(group, &client, settings)))
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+
+            Ok((cycles, network_propagation_time_ns))
+        })();
+
+        // Stop net thread, otherwise the scope waits on it forever.
+        doorbell.stop();
+
+        result
+    })
+}
+
+/// Check a socket can be bound and the XDP program attached, so the scenario is skipped on machines
+/// without XDP support instead of failing part way through. The program is detached again.
+pub fn probe(interface: &str) -> io::Result<()> {
+    let ifindex = interface_index(interface)?;
+
+    let xsk = Xsk::new(ifindex)?;
+    XdpProgram::attach(ifindex, &xsk)?;
+
+    Ok(())
+}
+
+struct XdpTxRx<'sto> {
+    xsk: Xsk,
+    // Detaches the XDP program when dropped
+    _program: XdpProgram,
+    doorbell: Arc<Doorbell>,
+    tx: PduTx<'sto>,
+    rx: PduRx<'sto>,
+}
+
+impl<'sto> XdpTxRx<'sto> {
+    fn new(interface: &str, tx: PduTx<'sto>, rx: PduRx<'sto>) -> io::Result<Self> {
+        let ifindex = interface_index(interface)?;
+
+        let xsk = Xsk::new(ifindex)?;
+        let program = XdpProgram::attach(ifindex, &xsk)?;
+
+        Ok(Self {
+            xsk,
+            _program: program,
+            doorbell: Doorbell::new()?,
+            tx,
+            rx,
+        })
+    }
+
+    /// Send queued frames and receive responses until [`Doorbell::stop`] is called.
+    fn run(mut self) -> io::Result<()> {
+        self.tx
+            .replace_waker(&Waker::from(Arc::clone(&self.doorbell)));
+
+        // UMEM chunks not currently queued for sending
+        let mut tx_frames = (RING_SIZE as usize..NUM_FRAMES)
+            .map(|i| (i * FRAME_SIZE) as u64)
+            .collect::<Vec<_>>();
+
+        let mut fds = [
+            libc::pollfd {
+                fd: self.xsk.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.doorbell.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+
+        while !self.doorbell.is_stopped() {
+            while let Some(addr) = self.xsk.completion.pop() {
+                tx_frames.push(addr);
+            }
+
+            let mut queued = false;
+
+            while let Some(&addr) = tx_frames.last() {
+                let Some(frame) = self.tx.next_sendable_frame() else {
+                    break;
+                };
+
+                tx_frames.pop();
+
+                let xsk_tx = &mut self.xsk.tx;
+
+                frame
+                    .send_blocking(self.xsk.umem.frame_mut(addr), |data| {
+                        // There are only as many TX chunks as ring entries so this never fails
+                        xsk_tx
+                            .push(libc::xdp_desc {
+                                addr,
+                                len: data.len() as u32,
+                                options: 0,
+                            })
+                            .then_some(data.len())
+                            .ok_or(ethercrab::error::Error::SendFrame)
+                    })
+                    .map_err(|e| io::Error::other(format!("Send frame: {}", e)))?;
+
+                queued = true;
+            }
+
+            if queued {
+                self.xsk.kick()?;
+            }
+
+            while let Some(desc) = self.xsk.rx.pop() {
+                let res = self
+                    .rx
+                    .receive_frame(self.xsk.umem.frame(desc.addr, desc.len));
+
+                // Give the chunk back to the kernel for another receive
+                self.xsk.fill.push(desc.addr & !(FRAME_SIZE as u64 - 1));
+
+                res.map_err(|e| io::Error::other(format!("Receive frame: {}", e)))?;
+            }
+
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+                let e = io::Error::last_os_error();
+
+                if e.raw_os_error() != Some(libc::EINTR) {
+                    return Err(e);
+                }
+            }
+
+            if fds[1].revents & libc::POLLIN != 0 {
+                self.doorbell.clear();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Frame memory shared with the kernel.
+struct Umem {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Umem {
+    fn new() -> io::Result<Self> {
+        let len = NUM_FRAMES * FRAME_SIZE;
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_POPULATE,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn frame(&self, addr: u64, len: u32) -> &[u8] {
+        assert!(addr as usize + len as usize <= self.len);
+
+        unsafe { std::slice::from_raw_parts(self.ptr.add(addr as usize), len as usize) }
+    }
+
+    /// The whole chunk at `addr`. The chunk must not be in any ring.
+    #[allow(clippy::mut_from_ref)]
+    fn frame_mut(&self, addr: u64) -> &mut [u8] {
+        assert!(addr as usize + FRAME_SIZE <= self.len);
+
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.add(addr as usize), FRAME_SIZE) }
+    }
+}
+
+impl Drop for Umem {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// A single producer/single consumer ring shared with the kernel.
+struct Ring<T> {
+    producer: *const AtomicU32,
+    consumer: *const AtomicU32,
+    descs: *mut T,
+    map: *mut libc::c_void,
+    map_len: usize,
+}
+
+impl<T: Copy> Ring<T> {
+    fn map(fd: RawFd, offsets: &libc::xdp_ring_offset, pgoff: u64) -> io::Result<Self> {
+        let map_len = offsets.desc as usize + RING_SIZE as usize * mem::size_of::<T>();
+
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                pgoff as libc::off_t,
+            )
+        };
+
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let base = map as *mut u8;
+
+        unsafe {
+            Ok(Self {
+                producer: base.add(offsets.producer as usize) as *const AtomicU32,
+                consumer: base.add(offsets.consumer as usize) as *const AtomicU32,
+                descs: base.add(offsets.desc as usize) as *mut T,
+                map,
+                map_len,
+            })
+        }
+    }
+
+    /// Producer side, for the fill and TX rings. Returns `false` if the ring is full.
+    fn push(&mut self, item: T) -> bool {
+        unsafe {
+            let prod = (*self.producer).load(Ordering::Relaxed);
+            let cons = (*self.consumer).load(Ordering::Acquire);
+
+            if prod.wrapping_sub(cons) >= RING_SIZE {
+                return false;
+            }
+
+            self.descs
+                .add((prod & (RING_SIZE - 1)) as usize)
+                .write(item);
+
+            (*self.producer).store(prod.wrapping_add(1), Ordering::Release);
+        }
+
+        true
+    }
+
+    /// Consumer side, for the completion and RX rings.
+    fn pop(&mut self) -> Option<T> {
+        unsafe {
+            let cons = (*self.consumer).load(Ordering::Relaxed);
+            let prod = (*self.producer).load(Ordering::Acquire);
+
+            if cons == prod {
+                return None;
+            }
+
+            let item = self.descs.add((cons & (RING_SIZE - 1)) as usize).read();
+
+            (*self.consumer).store(cons.wrapping_add(1), Ordering::Release);
+
+            Some(item)
+        }
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map, self.map_len) };
+    }
+}
+
+// The rings are only touched from the thread that owns the socket.
+unsafe impl<T> Send for Ring<T> {}
+unsafe impl Send for Umem {}
+
+struct Xsk {
+    fill: Ring<u64>,
+    completion: Ring<u64>,
+    rx: Ring<libc::xdp_desc>,
+    tx: Ring<libc::xdp_desc>,
+    // Close the socket before unmapping the UMEM registered with it
+    fd: OwnedFd,
+    umem: Umem,
+}
+
+impl Xsk {
+    fn new(ifindex: u32) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(libc::AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let raw = fd.as_raw_fd();
+
+        let umem = Umem::new()?;
+
+        setsockopt(
+            raw,
+            libc::XDP_UMEM_REG,
+            &libc::xdp_umem_reg {
+                addr: umem.ptr as u64,
+                len: umem.len as u64,
+                chunk_size: FRAME_SIZE as u32,
+                headroom: 0,
+                flags: 0,
+                tx_metadata_len: 0,
+            },
+        )?;
+
+        for ring in [
+            libc::XDP_UMEM_FILL_RING,
+            libc::XDP_UMEM_COMPLETION_RING,
+            libc::XDP_RX_RING,
+            libc::XDP_TX_RING,
+        ] {
+            setsockopt(raw, ring, &RING_SIZE)?;
+        }
+
+        let mut offsets: libc::xdp_mmap_offsets = unsafe { mem::zeroed() };
+        let mut len = mem::size_of_val(&offsets) as libc::socklen_t;
+
+        if unsafe {
+            libc::getsockopt(
+                raw,
+                libc::SOL_XDP,
+                libc::XDP_MMAP_OFFSETS,
+                &mut offsets as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        } == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut xsk = Self {
+            fill: Ring::map(raw, &offsets.fr, libc::XDP_UMEM_PGOFF_FILL_RING)?,
+            completion: Ring::map(raw, &offsets.cr, libc::XDP_UMEM_PGOFF_COMPLETION_RING)?,
+            rx: Ring::map(raw, &offsets.rx, libc::XDP_PGOFF_RX_RING as u64)?,
+            tx: Ring::map(raw, &offsets.tx, libc::XDP_PGOFF_TX_RING as u64)?,
+            fd,
+            umem,
+        };
+
+        // Lower half of UMEM is for receiving
+        for i in 0..RING_SIZE as usize {
+            xsk.fill.push((i * FRAME_SIZE) as u64);
+        }
+
+        // Zero copy is used if the driver supports it, copy mode otherwise
+        let addr = libc::sockaddr_xdp {
+            sxdp_family: libc::AF_XDP as u16,
+            sxdp_flags: 0,
+            sxdp_ifindex: ifindex,
+            sxdp_queue_id: QUEUE_ID,
+            sxdp_shared_umem_fd: 0,
+        };
+
+        if unsafe {
+            libc::bind(
+                raw,
+                &addr as *const libc::sockaddr_xdp as *const libc::sockaddr,
+                mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        } == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(xsk)
+    }
+
+    /// Tell the kernel there are new descriptors in the TX ring.
+    fn kick(&self) -> io::Result<()> {
+        let res = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                ptr::null(),
+                0,
+                libc::MSG_DONTWAIT,
+                ptr::null(),
+                0,
+            )
+        };
+
+        if res == -1 {
+            let e = io::Error::last_os_error();
+
+            // The kernel is still busy with earlier frames and will pick these up too
+            match e.raw_os_error() {
+                Some(libc::EAGAIN | libc::EBUSY | libc::ENOBUFS) => (),
+                _ => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for Xsk {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+fn setsockopt<T>(fd: RawFd, name: libc::c_int, value: &T) -> io::Result<()> {
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_XDP,
+            name,
+            value as *const T as *const libc::c_void,
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[repr(C)]
+struct BpfInsn {
+    code: u8,
+    // dst in the low nibble, src in the high nibble
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+const fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> BpfInsn {
+    BpfInsn {
+        code,
+        regs: dst | (src << 4),
+        off,
+        imm,
+    }
+}
+
+#[repr(C)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+}
+
+#[repr(C)]
+struct MapUpdateAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+#[repr(C)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+fn bpf<T>(cmd: libc::c_int, attr: &mut T) -> io::Result<libc::c_long> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            attr as *mut T,
+            mem::size_of::<T>() as libc::c_uint,
+        )
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(res)
+}
+
+/// For commands that return a new file descriptor.
+fn bpf_fd<T>(cmd: libc::c_int, attr: &mut T) -> io::Result<OwnedFd> {
+    bpf(cmd, attr).map(|fd| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// An XDP program redirecting EtherCAT frames into an AF_XDP socket.
+struct XdpProgram {
+    // Field order matters: the link must be dropped first to detach the program
+    _link: OwnedFd,
+    _prog: OwnedFd,
+    _map: OwnedFd,
+}
+
+impl XdpProgram {
+    fn attach(ifindex: u32, xsk: &Xsk) -> io::Result<Self> {
+        let map = bpf_fd(
+            BPF_MAP_CREATE,
+            &mut MapCreateAttr {
+                map_type: BPF_MAP_TYPE_XSKMAP,
+                key_size: 4,
+                value_size: 4,
+                max_entries: 64,
+            },
+        )?;
+
+        let key = QUEUE_ID;
+        let value = xsk.as_raw_fd() as u32;
+
+        bpf(
+            BPF_MAP_UPDATE_ELEM,
+            &mut MapUpdateAttr {
+                map_fd: map.as_raw_fd() as u32,
+                _pad: 0,
+                key: &key as *const u32 as u64,
+                value: &value as *const u32 as u64,
+                flags: 0,
+            },
+        )?;
+
+        // Labels are instruction indices; jump offsets are relative to the next instruction.
+        const PASS: i16 = 13;
+
+        let insns = [
+            // r2 = ctx->data, r3 = ctx->data_end
+            insn(0x61, 2, 1, 0, 0),
+            insn(0x61, 3, 1, 4, 0),
+            // Pass anything too short to have an EtherType
+            insn(0xbf, 4, 2, 0, 0),
+            insn(0x07, 4, 0, 0, 14),
+            insn(0x2d, 4, 3, PASS - 5, 0),
+            // EtherType 0x88a4, loaded little endian
+            insn(0x69, 4, 2, 12, 0),
+            insn(0x55, 4, 0, PASS - 7, 0xa488),
+            // return bpf_redirect_map(&xsks, ctx->rx_queue_index, XDP_PASS)
+            insn(0x61, 2, 1, 16, 0),
+            insn(0x18, 1, BPF_PSEUDO_MAP_FD, 0, map.as_raw_fd()),
+            insn(0x00, 0, 0, 0, 0),
+            insn(0xb7, 3, 0, 0, XDP_PASS),
+            insn(0x85, 0, 0, 0, BPF_FUNC_REDIRECT_MAP),
+            insn(0x95, 0, 0, 0, 0),
+            // PASS: return XDP_PASS
+            insn(0xb7, 0, 0, 0, XDP_PASS),
+            insn(0x95, 0, 0, 0, 0),
+        ];
+
+        let license = c"GPL";
+        let mut log = vec![0u8; 64 * 1024];
+        let mut name = [0u8; 16];
+        name[..9].copy_from_slice(b"ethercrab");
+
+        let prog = bpf_fd(
+            BPF_PROG_LOAD,
+            &mut ProgLoadAttr {
+                prog_type: BPF_PROG_TYPE_XDP,
+                insn_cnt: insns.len() as u32,
+                insns: insns.as_ptr() as u64,
+                license: license.as_ptr() as u64,
+                log_level: 1,
+                log_size: log.len() as u32,
+                log_buf: log.as_mut_ptr() as u64,
+                kern_version: 0,
+                prog_flags: 0,
+                prog_name: name,
+                prog_ifindex: 0,
+                expected_attach_type: BPF_XDP,
+            },
+        )
+        .map_err(|e| {
+            let log = CStr::from_bytes_until_nul(&log)
+                .map(|log| log.to_string_lossy())
+                .unwrap_or_default();
+
+            io::Error::new(e.kind(), format!("Load XDP program: {}\n{}", e, log))
+        })?;
+
+        // Native mode if the driver supports it, generic otherwise
+        let link = bpf_fd(
+            BPF_LINK_CREATE,
+            &mut LinkCreateAttr {
+                prog_fd: prog.as_raw_fd() as u32,
+                target_ifindex: ifindex,
+                attach_type: BPF_XDP,
+                flags: 0,
+            },
+        )
+        .map_err(|e| io::Error::new(e.kind(), format!("Attach XDP program: {}", e)))?;
+
+        Ok(Self {
+            _link: link,
+            _prog: prog,
+            _map: map,
+        })
+    }
+}
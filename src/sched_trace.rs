@@ -0,0 +1,168 @@
+//! Record scheduler events with ftrace and keep the ones around deadline misses.
+//!
+//! `sched_switch` and `sched_wakeup` are traced through tracefs for the whole run using the `mono`
+//! trace clock, so event times line up with [`monotonic_ns`] timestamps taken when a deadline miss
+//! is detected. Only events within the configured window either side of a miss are kept, attached
+//! to the miss as a snippet of the raw trace along with the thread that most often took the CPU
+//! from one of the scenario's threads.
+
+use crate::scenarios::Spike;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Most trace lines to keep for a single spike.
+const MAX_SNIPPET_LINES: usize = 500;
+
+/// Current `CLOCK_MONOTONIC` time.
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: `ts` is a valid timespec to write into.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+pub struct Tracer {
+    root: PathBuf,
+    window_ns: u64,
+}
+
+impl Tracer {
+    /// Start tracing scheduler events, keeping `window_us` either side of each spike.
+    pub fn start(window_us: u64) -> anyhow::Result<Self> {
+        let root = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"]
+            .into_iter()
+            .map(PathBuf::from)
+            .find(|path| path.join("trace").exists())
+            .ok_or_else(|| anyhow::anyhow!("tracefs is not mounted"))?;
+
+        let tracer = Self {
+            root,
+            window_ns: window_us * 1000,
+        };
+
+        tracer.write("tracing_on", "0")?;
+        tracer.write("trace_clock", "mono")?;
+        tracer.write("buffer_size_kb", "32768")?;
+        tracer.write("set_event", "sched:sched_switch sched:sched_wakeup")?;
+        // Clear anything left from a previous run
+        tracer.write("trace", "")?;
+        tracer.write("tracing_on", "1")?;
+
+        Ok(tracer)
+    }
+
+    /// Stop tracing and attach the events around each spike to it.
+    pub fn stop(self, spikes: &mut [Spike]) -> anyhow::Result<()> {
+        self.write("tracing_on", "0")?;
+
+        let result = self.collect(spikes);
+
+        self.write("set_event", "")?;
+        self.write("trace_clock", "local")?;
+
+        result
+    }
+
+    fn collect(&self, spikes: &mut [Spike]) -> anyhow::Result<()> {
+        if spikes.is_empty() {
+            return Ok(());
+        }
+
+        let mut snippets = vec![Vec::<String>::new(); spikes.len()];
+
+        // Spikes from multiple task threads are interleaved, so search them in time order
+        let mut order = (0..spikes.len()).collect::<Vec<_>>();
+
+        order.sort_by_key(|i| spikes[*i].time_ns);
+
+        let reader = BufReader::new(File::open(self.root.join("trace"))?);
+
+        for line in reader.lines() {
+            let line = line?;
+
+            let Some(time) = event_time_ns(&line) else {
+                continue;
+            };
+
+            let first = order.partition_point(|i| spikes[*i].time_ns + self.window_ns < time);
+
+            for i in order[first..]
+                .iter()
+                .take_while(|i| spikes[**i].time_ns <= time + self.window_ns)
+            {
+                if snippets[*i].len() < MAX_SNIPPET_LINES {
+                    snippets[*i].push(line.clone());
+                }
+            }
+        }
+
+        for (spike, snippet) in spikes.iter_mut().zip(snippets) {
+            spike.preempted_by = preempted_by(&snippet);
+            spike.sched_trace = Some(snippet.join("\n"));
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, file: &str, value: &str) -> anyhow::Result<()> {
+        write(&self.root.join(file), value)
+    }
+}
+
+fn write(path: &Path, value: &str) -> anyhow::Result<()> {
+    fs::write(path, value).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+/// Parse the timestamp of a trace line like
+///
+/// ```text
+/// ethercrab-net-1234 [002] d..3. 5678.123456: sched_switch: prev_comm=...
+/// ```
+fn event_time_ns(line: &str) -> Option<u64> {
+    let (head, _event) = line.split_once(": sched_")?;
+
+    let (secs, micros) = head.split_whitespace().last()?.split_once('.')?;
+
+    Some(secs.parse::<u64>().ok()? * 1_000_000_000 + micros.parse::<u64>().ok()? * 1000)
+}
+
+/// Find the thread that most often replaced one of this process's threads on a CPU.
+fn preempted_by(snippet: &[String]) -> Option<String> {
+    let mut counts = HashMap::<&str, usize>::new();
+
+    for line in snippet {
+        let Some((_, fields)) = line.split_once("sched_switch: ") else {
+            continue;
+        };
+
+        let field = |name: &str| {
+            fields
+                .split_whitespace()
+                .find_map(|f| f.strip_prefix(name)?.strip_prefix('='))
+        };
+
+        let ours = field("prev_comm")
+            .is_some_and(|comm| comm.starts_with("ethercrab-") || comm == "latency-data");
+
+        if let (true, Some(comm), Some(pid)) = (ours, field("next_comm"), field("next_pid")) {
+            // Swapper means our thread went idle, not that something else took over
+            if pid != "0" {
+                *counts.entry(comm).or_default() += 1;
+            }
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_comm, count)| *count)
+        .map(|(comm, _count)| comm.to_string())
+}
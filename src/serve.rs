@@ -0,0 +1,328 @@
+//! Small read-only HTTP API over the results database.
+//!
+//! Every response is JSON built by Postgres itself, so this is mostly a thin router. Only `GET`
+//! requests are supported and each connection serves a single request.
+//!
+//! - `GET /runs?limit=&offset=` - list runs, newest first
+//! - `GET /runs/<name>` - a run's metadata and summary statistics
+//! - `GET /runs/<name>/cycles?limit=&offset=` - a page of process cycles
+//! - `GET /runs/<name>/frames?limit=&offset=` - a page of captured frames
+//! - `GET /compare/<a>/<b>` - summary statistics of two runs side by side, with `b - a` deltas
+
+use crate::db::connect;
+use sqlx::{query_scalar, PgPool};
+use std::collections::HashMap;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Default and maximum page size for paginated endpoints.
+const DEFAULT_LIMIT: i64 = 1000;
+const MAX_LIMIT: i64 = 100_000;
+
+/// Largest request head that will be read before giving up on a connection.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+pub async fn serve(db: &str, listen: &str) -> anyhow::Result<()> {
+    let db = connect(db).await?;
+
+    let listener = TcpListener::bind(listen).await?;
+
+    log::info!("Serving API on http://{}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+
+        let db = db.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle(&db, stream).await {
+                log::warn!("Request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(db: &PgPool, mut stream: TcpStream) -> anyhow::Result<()> {
+    let request = read_request(&mut stream).await?;
+
+    let (status, body) = match request.method.as_str() {
+        "GET" => {
+            log::debug!("GET {}", request.target);
+
+            match route(db, &request.target).await {
+                Ok(Some(body)) => ("200 OK", body),
+                Ok(None) => ("404 Not Found", error("Not found")),
+                Err(e) => {
+                    log::error!("GET {}: {}", request.target, e);
+
+                    ("500 Internal Server Error", error(&e.to_string()))
+                }
+            }
+        }
+        _ => ("405 Method Not Allowed", error("Only GET is supported")),
+    };
+
+    write_json(&mut stream, status, body).await
+}
+
+/// The parts of an HTTP request this crate uses. Bodies are read but discarded.
+pub struct Request {
+    pub method: String,
+    pub target: String,
+
+    /// Headers with lowercase names.
+    pub headers: HashMap<String, String>,
+}
+
+/// Read a single HTTP/1.1 request from a connection.
+pub async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Request> {
+    let mut buf = Vec::with_capacity(1024);
+
+    let head_len = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+
+        anyhow::ensure!(buf.len() < MAX_REQUEST_LEN, "Request too large");
+
+        let mut chunk = [0u8; 1024];
+
+        let n = stream.read(&mut chunk).await?;
+
+        anyhow::ensure!(n > 0, "Connection closed before end of request");
+
+        buf.extend_from_slice(&chunk[0..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[0..head_len]);
+    let mut lines = head.lines();
+
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default().to_string();
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect::<HashMap<_, _>>();
+
+    // Drain any body so closing the connection doesn't reset it before the response is read
+    let body_len = headers
+        .get("content-length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    anyhow::ensure!(body_len <= MAX_REQUEST_LEN, "Request too large");
+
+    let mut remaining = body_len.saturating_sub(buf.len() - head_len);
+
+    while remaining > 0 {
+        let mut chunk = [0u8; 1024];
+
+        let n = stream.read(&mut chunk[0..remaining.min(1024)]).await?;
+
+        anyhow::ensure!(n > 0, "Connection closed before end of request");
+
+        remaining -= n;
+    }
+
+    Ok(Request {
+        method,
+        target,
+        headers,
+    })
+}
+
+/// Write a JSON response and close the connection.
+pub async fn write_json(
+    stream: &mut TcpStream,
+    status: &str,
+    body: serde_json::Value,
+) -> anyhow::Result<()> {
+    let body = body.to_string();
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Run the query for a request target, returning `None` if nothing matches it.
+async fn route(db: &PgPool, target: &str) -> anyhow::Result<Option<serde_json::Value>> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let params = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect::<HashMap<_, _>>();
+
+    let page = |key: &str, default: i64| {
+        params
+            .get(key)
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(default)
+            .clamp(0, MAX_LIMIT)
+    };
+
+    let limit = page("limit", DEFAULT_LIMIT);
+    let offset = page("offset", 0);
+
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect::<Vec<_>>();
+
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let value = match segments.as_slice() {
+        ["runs"] => Some(
+            query_scalar(
+                r#"select coalesce(json_agg(r), '[]') from (
+                    select name, date, scenario, slug, hostname, master, propagation_time_ns
+                    from runs
+                    order by date desc
+                    limit $1 offset $2
+                ) r"#,
+            )
+            .bind(limit)
+            .bind(offset)
+            .fetch_one(db)
+            .await?,
+        ),
+        ["runs", name] => {
+            query_scalar(
+                r#"select json_build_object(
+                    'name', r.name,
+                    'date', r.date,
+                    'scenario', r.scenario,
+                    'slug', r.slug,
+                    'hostname', r.hostname,
+                    'master', r.master,
+                    'ethercrab_rev', r.ethercrab_rev,
+                    'propagation_time_ns', r.propagation_time_ns,
+                    'settings', r.settings,
+                    'artifacts', coalesce((
+                        select json_agg(json_build_object('kind', a.kind, 'url', a.url))
+                        from artifacts a where a.run = r.name
+                    ), '[]'),
+                    'summaries', coalesce((
+                        select json_agg(s order by s.metric) from (
+                            select metric, count, min_ns, max_ns, mean_ns, stddev_ns,
+                                p25_ns, p50_ns, p75_ns, p95_ns, p99_ns
+                            from summaries where run = r.name
+                        ) s
+                    ), '[]')
+                )
+                from runs r
+                where r.name = $1"#,
+            )
+            .bind(name)
+            .fetch_optional(db)
+            .await?
+        }
+        ["runs", name, "cycles"] => Some(
+            query_scalar(
+                r#"select coalesce(json_agg(c order by c.cycle), '[]') from (
+                    select cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns, reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index
+                    from (
+                        select run, cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns, reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index
+                        from cycles
+                        union all
+                        select run, cycle, processing_time_ns, tick_wait_ns, cycle_time_delta_ns, reaction_latency_ns, dc_offset_ns, dc_drift_ns, group_index
+                        from cycle_series_rows
+                    ) all_cycles
+                    where run = $1
+                    order by cycle
+                    limit $2 offset $3
+                ) c"#,
+            )
+            .bind(name)
+            .bind(limit)
+            .bind(offset)
+            .fetch_one(db)
+            .await?,
+        ),
+        ["runs", name, "frames"] => Some(
+            query_scalar(
+                r#"select coalesce(json_agg(f order by f.packet_number), '[]') from (
+                    select packet_number, index, command, tx_time_ns, rx_time_ns, delta_time_ns, data_len, pdu_position, hw_tx_time_ns, hw_rx_time_ns
+                    from frames
+                    where run = $1
+                    order by packet_number
+                    limit $2 offset $3
+                ) f"#,
+            )
+            .bind(name)
+            .bind(limit)
+            .bind(offset)
+            .fetch_one(db)
+            .await?,
+        ),
+        ["compare", a, b] => Some(
+            query_scalar(
+                r#"select coalesce(json_agg(c order by c.metric), '[]') from (
+                    select
+                        metric,
+                        json_build_object('mean_ns', a.mean_ns, 'p50_ns', a.p50_ns, 'p99_ns', a.p99_ns, 'max_ns', a.max_ns) as a,
+                        json_build_object('mean_ns', b.mean_ns, 'p50_ns', b.p50_ns, 'p99_ns', b.p99_ns, 'max_ns', b.max_ns) as b,
+                        json_build_object(
+                            'mean_ns', b.mean_ns - a.mean_ns,
+                            'p50_ns', b.p50_ns - a.p50_ns,
+                            'p99_ns', b.p99_ns - a.p99_ns,
+                            'max_ns', b.max_ns - a.max_ns
+                        ) as delta
+                    from summaries a
+                    join summaries b using (metric)
+                    where a.run = $1 and b.run = $2
+                ) c"#,
+            )
+            .bind(a)
+            .bind(b)
+            .fetch_one(db)
+            .await?,
+        ),
+        _ => None,
+    };
+
+    Ok(value)
+}
+
+pub fn error(message: &str) -> serde_json::Value {
+    serde_json::json!({ "error": message })
+}
+
+/// Decode `%XX` escapes in a path segment.
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        if let Some(byte) = escaped {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,359 @@
+//! Streaming statistics so long runs don't need to keep every cycle in memory.
+
+use crate::scenarios::CycleMetadata;
+
+/// Values below this are counted exactly, one bucket per nanosecond.
+const LINEAR_BUCKETS: u32 = 128;
+/// Number of buckets each power of two above [`LINEAR_BUCKETS`] is split into. 64 sub-buckets gives
+/// a worst case error of ~1.5% for any recorded value.
+const SUB_BUCKETS: u32 = 64;
+/// `LINEAR_BUCKETS = 2^7`, so there are `32 - 7` powers of two left to cover the rest of `u32`.
+const NUM_BUCKETS: usize = (LINEAR_BUCKETS + (32 - 7) * SUB_BUCKETS) as usize;
+
+/// Log-linear histogram of nanosecond values.
+///
+/// Memory use is fixed regardless of how many values are recorded, and two histograms can be
+/// merged, so each task can keep its own and they can be combined at the end of a run.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    min: u32,
+    max: u32,
+    sum: u128,
+    sum_sq: u128,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; NUM_BUCKETS],
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            sum: 0,
+            sum_sq: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u32) {
+        self.counts[bucket_index(value)] += 1;
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u128::from(value);
+        self.sum_sq += u128::from(value) * u128::from(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Approximate value at the given percentile, e.g. `99.0`.
+    pub fn percentile(&self, percentile: f64) -> u32 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+
+        let mut seen = 0;
+
+        for (idx, count) in self.counts.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                let (lower, width) = bucket_bounds(idx);
+
+                let mid = (lower + width / 2).min(u64::from(u32::MAX)) as u32;
+
+                return mid.clamp(self.min, self.max);
+            }
+        }
+
+        self.max
+    }
+
+    /// Non-empty buckets as `(lower bound ns, count)` pairs.
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_idx, count)| **count > 0)
+            .map(|(idx, count)| (bucket_bounds(idx).0, *count))
+            .collect()
+    }
+
+    pub fn summary(&self) -> Summary {
+        if self.count == 0 {
+            return Summary::default();
+        }
+
+        let n = self.count as f64;
+        let mean = self.sum as f64 / n;
+        let variance = (self.sum_sq as f64 / n - mean * mean).max(0.0);
+
+        Summary {
+            count: self.count,
+            min_ns: self.min,
+            max_ns: self.max,
+            mean_ns: mean,
+            stddev_ns: variance.sqrt(),
+            p25_ns: self.percentile(25.0),
+            p50_ns: self.percentile(50.0),
+            p75_ns: self.percentile(75.0),
+            p95_ns: self.percentile(95.0),
+            p99_ns: self.percentile(99.0),
+        }
+    }
+}
+
+fn bucket_index(value: u32) -> usize {
+    if value < LINEAR_BUCKETS {
+        return value as usize;
+    }
+
+    let exp = 31 - value.leading_zeros();
+    // Top 7 bits of the value, i.e. in the range `64..128`.
+    let mantissa = value >> (exp - 6);
+
+    (LINEAR_BUCKETS + (exp - 7) * SUB_BUCKETS + (mantissa - SUB_BUCKETS)) as usize
+}
+
+/// Lower bound and width of the given bucket.
+fn bucket_bounds(idx: usize) -> (u64, u64) {
+    let idx = idx as u32;
+
+    if idx < LINEAR_BUCKETS {
+        return (u64::from(idx), 1);
+    }
+
+    let idx = idx - LINEAR_BUCKETS;
+    let exp = idx / SUB_BUCKETS + 7;
+    let mantissa = idx % SUB_BUCKETS + SUB_BUCKETS;
+
+    (u64::from(mantissa) << (exp - 6), 1 << (exp - 6))
+}
+
+/// Exact minimum, maximum and mean of a group of values.
+#[derive(Debug, Clone, Copy)]
+pub struct Aggregate {
+    pub min: u32,
+    pub max: u32,
+    sum: u64,
+    count: u32,
+}
+
+impl Default for Aggregate {
+    fn default() -> Self {
+        Self {
+            min: u32::MAX,
+            max: 0,
+            sum: 0,
+            count: 0,
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn record(&mut self, value: u32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u64::from(value);
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        self.sum as f64 / f64::from(self.count)
+    }
+}
+
+/// Summary statistics for a single metric.
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct Summary {
+    pub count: u64,
+    pub min_ns: u32,
+    pub max_ns: u32,
+    pub mean_ns: f64,
+    pub stddev_ns: f64,
+    pub p25_ns: u32,
+    pub p50_ns: u32,
+    pub p75_ns: u32,
+    pub p95_ns: u32,
+    pub p99_ns: u32,
+}
+
+/// Streaming statistics for every field of [`CycleMetadata`].
+#[derive(Debug, Clone, Default)]
+pub struct CycleSummary {
+    pub processing_time: Histogram,
+    pub tick_wait: Histogram,
+    pub cycle_time_delta: Histogram,
+    pub reaction_latency: Histogram,
+    pub dc_offset: Histogram,
+    /// Magnitude of [`CycleMetadata::dc_drift_ns`].
+    pub dc_drift: Histogram,
+
+    /// Number of cycles that took more than twice the cycle time.
+    pub deadline_misses: u64,
+}
+
+impl CycleSummary {
+    pub fn record(&mut self, cycle: &CycleMetadata) {
+        self.processing_time.record(cycle.processing_time_ns);
+        self.tick_wait.record(cycle.tick_wait_ns);
+        self.cycle_time_delta.record(cycle.cycle_time_delta_ns);
+
+        if let Some(ns) = cycle.reaction_latency_ns {
+            self.reaction_latency.record(ns);
+        }
+
+        if let Some(ns) = cycle.dc_offset_ns {
+            self.dc_offset.record(ns);
+        }
+
+        if let Some(ns) = cycle.dc_drift_ns {
+            self.dc_drift.record(ns.unsigned_abs());
+        }
+    }
+
+    pub fn merge(&mut self, other: &CycleSummary) {
+        self.processing_time.merge(&other.processing_time);
+        self.tick_wait.merge(&other.tick_wait);
+        self.cycle_time_delta.merge(&other.cycle_time_delta);
+        self.reaction_latency.merge(&other.reaction_latency);
+        self.dc_offset.merge(&other.dc_offset);
+        self.dc_drift.merge(&other.dc_drift);
+        self.deadline_misses += other.deadline_misses;
+    }
+
+    /// Number of cycles recorded.
+    pub fn count(&self) -> u64 {
+        self.processing_time.count()
+    }
+
+    /// Each metric's histogram, along with the name it's stored under in the database.
+    ///
+    /// Reaction latency, DC offset and DC drift are only included if the scenario measured them.
+    pub fn metrics(&self) -> Vec<(&'static str, &Histogram)> {
+        let mut metrics = vec![
+            ("processing_time", &self.processing_time),
+            ("tick_wait", &self.tick_wait),
+            ("cycle_time_delta", &self.cycle_time_delta),
+        ];
+
+        if self.reaction_latency.count() > 0 {
+            metrics.push(("reaction_latency", &self.reaction_latency));
+        }
+
+        if self.dc_offset.count() > 0 {
+            metrics.push(("dc_offset", &self.dc_offset));
+        }
+
+        if self.dc_drift.count() > 0 {
+            metrics.push(("dc_drift", &self.dc_drift));
+        }
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values that sit on bucket boundaries, plus the extremes of `u32`.
+    fn edge_values() -> Vec<u32> {
+        let mut values = vec![0, 1, 127, 128, 129, u32::MAX - 1, u32::MAX];
+
+        for exp in 7..32 {
+            let pow = 1u32 << exp;
+
+            values.extend([pow - 1, pow, pow + 1]);
+        }
+
+        values
+    }
+
+    #[test]
+    fn bucket_contains_value() {
+        for value in edge_values() {
+            let idx = bucket_index(value);
+
+            assert!(idx < NUM_BUCKETS, "{} -> {} out of range", value, idx);
+
+            let (lower, width) = bucket_bounds(idx);
+
+            assert!(
+                (lower..lower + width).contains(&u64::from(value)),
+                "{} not in bucket {} ({}, {})",
+                value,
+                idx,
+                lower,
+                width
+            );
+        }
+    }
+
+    #[test]
+    fn buckets_cover_u32() {
+        assert_eq!(bucket_index(u32::MAX), NUM_BUCKETS - 1);
+
+        let mut next = 0;
+
+        for idx in 0..NUM_BUCKETS {
+            let (lower, width) = bucket_bounds(idx);
+
+            assert_eq!(lower, next, "gap before bucket {}", idx);
+            assert_eq!(bucket_index(lower as u32), idx);
+
+            next = lower + width;
+        }
+
+        assert_eq!(next, u64::from(u32::MAX) + 1);
+    }
+
+    #[test]
+    fn percentile_error_is_bounded() {
+        for value in edge_values().into_iter().chain([1000, 12_345, 999_999]) {
+            let mut histogram = Histogram::default();
+
+            // Surround the value so the percentile isn't clamped to the exact min or max
+            histogram.record(0);
+            histogram.record(value);
+            histogram.record(u32::MAX);
+
+            let estimate = histogram.percentile(50.0);
+            let error = (f64::from(estimate) - f64::from(value)).abs();
+
+            assert!(
+                error <= f64::from(value) * 0.015,
+                "p50 {} too far from {}",
+                estimate,
+                value
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::process::Command;
+use std::{collections::BTreeMap, process::Command};
 
 /// Determine whether the kernel has the RT patches enabled or not
 pub fn is_rt_kernel() -> bool {
@@ -88,3 +88,96 @@ pub fn hostname() -> String {
 
     String::from_utf8_lossy(&output.stdout).trim().to_string()
 }
+
+/// CPUs isolated from the scheduler with `isolcpus` or `nohz_full` on the kernel command line,
+/// ascending.
+pub fn isolated_cpus() -> Vec<usize> {
+    let cmdline = std::fs::read_to_string("/proc/cmdline").unwrap_or_default();
+
+    let mut cpus = cmdline
+        .split_whitespace()
+        .filter_map(|param| {
+            param
+                .strip_prefix("isolcpus=")
+                .or_else(|| param.strip_prefix("nohz_full="))
+        })
+        .flat_map(parse_cpu_list)
+        .collect::<Vec<_>>();
+
+    cpus.sort_unstable();
+    cpus.dedup();
+
+    cpus
+}
+
+/// NUMA node the NIC is attached to, if the platform reports one.
+pub fn nic_numa_node(interface: &str) -> Option<usize> {
+    std::fs::read_to_string(format!("/sys/class/net/{}/device/numa_node", interface))
+        .ok()?
+        .trim()
+        // -1 when the platform doesn't know
+        .parse::<isize>()
+        .ok()
+        .and_then(|node| usize::try_from(node).ok())
+}
+
+/// CPUs on each NUMA node, keyed by node number.
+pub fn numa_node_cpus() -> BTreeMap<usize, Vec<usize>> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/node") else {
+        return BTreeMap::new();
+    };
+
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+
+            let node = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("node")?
+                .parse::<usize>()
+                .ok()?;
+
+            let cpus = std::fs::read_to_string(path.join("cpulist")).ok()?;
+
+            Some((node, parse_cpu_list(cpus.trim())))
+        })
+        .filter(|(_node, cpus)| !cpus.is_empty())
+        .collect()
+}
+
+/// One CPU from each online physical core, leaving out hyperthread siblings.
+pub fn physical_cores() -> Vec<usize> {
+    let online = std::fs::read_to_string("/sys/devices/system/cpu/online").unwrap_or_default();
+
+    parse_cpu_list(online.trim())
+        .into_iter()
+        .filter(|cpu| {
+            let siblings = std::fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                cpu
+            ))
+            .unwrap_or_default();
+
+            // Keep the lowest numbered CPU of each core, or the CPU itself if topology is unknown
+            parse_cpu_list(siblings.trim())
+                .into_iter()
+                .min()
+                .is_none_or(|first| first == *cpu)
+        })
+        .collect()
+}
+
+/// Parse a kernel CPU list like `2,4-7`. Non-numeric entries such as the `domain` and
+/// `managed_irq` flags `isolcpus` accepts are skipped.
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    list.split(',')
+        .flat_map(|item| match item.split_once('-') {
+            Some((start, end)) => match (start.parse::<usize>(), end.parse::<usize>()) {
+                (Ok(start), Ok(end)) => (start..=end).collect(),
+                _ => Vec::new(),
+            },
+            None => item.parse().map(|cpu| vec![cpu]).unwrap_or_default(),
+        })
+        .collect()
+}
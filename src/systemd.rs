@@ -0,0 +1,97 @@
+//! Generate systemd units to run the suite unattended on a schedule.
+//!
+//! The service is a oneshot that deletes old dumps, then runs this binary with the given suite
+//! arguments. Ingest, the `--baseline` regression gate and `--notify` all happen inside the suite
+//! run, so a regression fails the unit and shows up in `systemctl --failed`.
+
+use crate::scenarios::DUMPS_PATH;
+use std::{fs, path::Path};
+
+pub struct Schedule<'a> {
+    /// Unit name, without `.service`/`.timer`.
+    pub name: &'a str,
+
+    /// systemd calendar expression, e.g. `*-*-* 02:00:00`.
+    pub on_calendar: &'a str,
+
+    /// Delete dumps older than this many days before each run.
+    pub keep_days: u32,
+
+    /// Arguments to run the suite with.
+    pub suite: &'a [String],
+}
+
+/// Write `<name>.service` and `<name>.timer` into `out`.
+pub fn write_units(out: &Path, schedule: &Schedule) -> anyhow::Result<()> {
+    let exe = std::env::current_exe()?;
+    let dir = std::env::current_dir()?;
+
+    let exec_start = std::iter::once(exe.display().to_string())
+        .chain(schedule.suite.iter().cloned())
+        .map(|arg| quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let service = format!(
+        r#"[Unit]
+Description=EtherCrab latency suite
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+WorkingDirectory={dir}
+ExecStartPre=-/usr/bin/find {dumps} -name *.pcapng -mtime +{keep_days} -delete
+ExecStart={exec_start}
+LimitRTPRIO=infinity
+LimitMEMLOCK=infinity
+"#,
+        dir = dir.display().to_string().replace('%', "%%"),
+        dumps = DUMPS_PATH,
+        keep_days = schedule.keep_days,
+        exec_start = exec_start,
+    );
+
+    let timer = format!(
+        r#"[Unit]
+Description=Run the EtherCrab latency suite on a schedule
+
+[Timer]
+OnCalendar={on_calendar}
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+"#,
+        on_calendar = schedule.on_calendar,
+    );
+
+    fs::create_dir_all(out)?;
+
+    for (ext, contents) in [("service", service), ("timer", timer)] {
+        let path = out.join(format!("{}.{}", schedule.name, ext));
+
+        fs::write(&path, contents)?;
+
+        log::info!("Wrote {}", path.display());
+    }
+
+    log::info!(
+        "Install with: sudo cp {0}.service {0}.timer /etc/systemd/system/ && sudo systemctl daemon-reload && sudo systemctl enable --now {1}.timer",
+        out.join(schedule.name).display(),
+        schedule.name
+    );
+
+    Ok(())
+}
+
+/// Quote a word for a unit file command line, escaping systemd specifiers and variables.
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+
+    format!("\"{}\"", escaped)
+}
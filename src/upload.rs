@@ -0,0 +1,72 @@
+//! Upload dumps and exports to S3-compatible object storage with the `aws` CLI.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A file produced by a suite that should be uploaded after ingest.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// Run the file belongs to, or `None` for suite-wide files.
+    pub run: Option<String>,
+
+    /// What the file is, e.g. `dump` or `cycles-csv`. Stored alongside the URL.
+    pub kind: &'static str,
+
+    pub path: PathBuf,
+}
+
+/// Destination for uploaded artifacts.
+#[derive(Debug, Clone)]
+pub struct Upload {
+    /// Bucket and key prefix, like `s3://bucket/latency`.
+    dest: String,
+
+    /// Endpoint for non-AWS storage, e.g. `https://minio.lab:9000`.
+    endpoint: Option<String>,
+}
+
+impl Upload {
+    pub fn new(dest: &str, endpoint: Option<String>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            dest.starts_with("s3://"),
+            "Upload destination {} must start with s3://",
+            dest
+        );
+
+        Ok(Self {
+            dest: dest.trim_end_matches('/').to_string(),
+            endpoint,
+        })
+    }
+
+    /// Upload a file under the destination prefix, keeping its file name. Returns the object URL.
+    pub fn put(&self, path: &Path) -> anyhow::Result<String> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("No file name in {}", path.display()))?;
+
+        let url = format!("{}/{}", self.dest, name.to_string_lossy());
+
+        let mut cmd = Command::new("aws");
+
+        cmd.args(["s3", "cp", "--only-show-errors"])
+            .arg(path)
+            .arg(&url);
+
+        if let Some(endpoint) = self.endpoint.as_ref() {
+            cmd.arg("--endpoint-url").arg(endpoint);
+        }
+
+        log::debug!("Running upload command {:?}", cmd);
+
+        let status = cmd
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run aws: {}", e))?;
+
+        anyhow::ensure!(status.success(), "aws s3 cp exited with {}", status);
+
+        Ok(url)
+    }
+}
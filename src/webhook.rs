@@ -0,0 +1,179 @@
+//! Start suite runs from an HTTP request, e.g. from EtherCrab's CI.
+//!
+//! - `POST /run` with `Authorization: Bearer <token>` starts the configured suite and returns its
+//!   job ID, or `409` if a suite is already running
+//! - `GET /runs/<id>` returns a job's state: `running`, `succeeded` or `failed`
+//! - `GET /runs` lists every job since the listener started
+//!
+//! The suite runs as a separate process of this binary with a fixed set of arguments, so a request
+//! can't change what is run. Its output is saved to `<log dir>/<id>.log`.
+
+use crate::serve::{error, read_request, write_json};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(serde::Serialize, Debug, Clone)]
+struct Job {
+    id: usize,
+    state: &'static str,
+    started: DateTime<Utc>,
+    finished: Option<DateTime<Utc>>,
+    exit_code: Option<i32>,
+    log: PathBuf,
+}
+
+struct Config {
+    token: String,
+    suite: Vec<String>,
+    log_dir: PathBuf,
+}
+
+type Jobs = Arc<Mutex<Vec<Job>>>;
+
+pub async fn listen(
+    listen: &str,
+    token: String,
+    suite: Vec<String>,
+    log_dir: PathBuf,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(!token.is_empty(), "Webhook token must not be empty");
+
+    fs::create_dir_all(&log_dir)?;
+
+    let config = Arc::new(Config {
+        token,
+        suite,
+        log_dir,
+    });
+    let jobs = Jobs::default();
+
+    let listener = TcpListener::bind(listen).await?;
+
+    log::info!(
+        "Waiting for run requests on http://{}, will run with {:?}",
+        listener.local_addr()?,
+        config.suite
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+
+        let config = config.clone();
+        let jobs = jobs.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = handle(&config, &jobs, stream).await {
+                log::warn!("Request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(config: &Config, jobs: &Jobs, mut stream: TcpStream) -> anyhow::Result<()> {
+    let request = read_request(&mut stream).await?;
+
+    let authorised = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_eq(token, &config.token));
+
+    if !authorised {
+        log::warn!("Unauthorised {} {}", request.method, request.target);
+
+        return write_json(&mut stream, "401 Unauthorized", error("Bad token")).await;
+    }
+
+    let path = request.target.split('?').next().unwrap_or_default();
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+    let (status, body) = match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["run"]) => match start(config, jobs) {
+            Ok(Some(job)) => ("202 Accepted", json!(job)),
+            Ok(None) => ("409 Conflict", error("A suite is already running")),
+            Err(e) => ("500 Internal Server Error", error(&e.to_string())),
+        },
+        ("GET", ["runs"]) => ("200 OK", json!(*jobs.lock().expect("Jobs lock"))),
+        ("GET", ["runs", id]) => {
+            let jobs = jobs.lock().expect("Jobs lock");
+
+            match id.parse::<usize>().ok().and_then(|id| jobs.get(id)) {
+                Some(job) => ("200 OK", json!(job)),
+                None => ("404 Not Found", error("No such job")),
+            }
+        }
+        _ => ("404 Not Found", error("Not found")),
+    };
+
+    write_json(&mut stream, status, body).await
+}
+
+/// Start the suite unless one is already running.
+fn start(config: &Config, jobs: &Jobs) -> anyhow::Result<Option<Job>> {
+    let mut list = jobs.lock().expect("Jobs lock");
+
+    if list.iter().any(|job| job.state == "running") {
+        return Ok(None);
+    }
+
+    let id = list.len();
+    let log = config.log_dir.join(format!("{}.log", id));
+    let out = File::create(&log)?;
+
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(&config.suite)
+        .stdout(out.try_clone()?)
+        .stderr(out)
+        .spawn()?;
+
+    log::info!("Started job {}, output in {}", id, log.display());
+
+    let job = Job {
+        id,
+        state: "running",
+        started: Utc::now(),
+        finished: None,
+        exit_code: None,
+        log,
+    };
+
+    list.push(job.clone());
+
+    let jobs = jobs.clone();
+
+    thread::spawn(move || {
+        let status = child.wait();
+
+        let mut list = jobs.lock().expect("Jobs lock");
+        let job = &mut list[id];
+
+        job.finished = Some(Utc::now());
+        job.exit_code = status.as_ref().ok().and_then(|status| status.code());
+        job.state = if status.is_ok_and(|status| status.success()) {
+            "succeeded"
+        } else {
+            "failed"
+        };
+
+        log::info!("Job {} {}", id, job.state);
+    });
+
+    Ok(Some(job))
+}
+
+/// Compare tokens without returning early on the first mismatch.
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}